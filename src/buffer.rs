@@ -1,12 +1,16 @@
-use rand::{thread_rng, Rng};
+pub mod replacer;
+
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
 
 use anyhow::Result;
+use log::debug;
 
+use crate::buffer::replacer::{Replacer, ReplacerPolicy};
 use crate::disk::{DiskManager, PAGE_SIZE};
+use crate::wal::{page_diff, page_lsn, set_page_lsn, LogManager, LogRecordBody, Lsn, TxnId, Undo};
 
-const POOL_SIZE: usize = 5;
+pub const DEFAULT_POOL_SIZE: usize = 5;
 
 #[derive(Debug)]
 pub struct BufferPool {
@@ -14,6 +18,8 @@ pub struct BufferPool {
     page_table: HashMap<usize, usize>,
     frames: Vec<Frame>,
     free_frame_ids: Vec<usize>,
+    // chooses the victim among the unpinned frames
+    replacer: Box<dyn Replacer>,
 }
 
 #[derive(Debug)]
@@ -27,7 +33,12 @@ pub struct Frame {
 #[derive(Debug)]
 pub struct BufferPoolManager {
     disk_manager: Arc<DiskManager>,
+    log_manager: Arc<LogManager>,
     buffer_pool: Mutex<BufferPool>,
+    // pages deleted by running transactions, which are freed once they commit
+    deleted_pages: Mutex<HashMap<TxnId, Vec<usize>>>,
+    // block number of the root of an index -> latch held by its writers
+    index_latches: Mutex<HashMap<usize, Arc<Mutex<()>>>>,
 }
 
 #[derive(Debug)]
@@ -39,11 +50,19 @@ pub struct FetchPageResult {
 
 impl BufferPoolManager {
     pub fn new(disk_manager: Arc<DiskManager>) -> Self {
+        Self::with_pool(disk_manager, DEFAULT_POOL_SIZE, ReplacerPolicy::default())
+    }
+    pub fn with_pool(
+        disk_manager: Arc<DiskManager>,
+        pool_size: usize,
+        replacer_policy: ReplacerPolicy,
+    ) -> Self {
         BufferPoolManager {
+            log_manager: Arc::new(LogManager::new(disk_manager.clone())),
             disk_manager,
             buffer_pool: Mutex::new(BufferPool {
                 page_table: HashMap::new(),
-                frames: (0..POOL_SIZE)
+                frames: (0..pool_size)
                     .map(|_| Frame {
                         data: Arc::new(RwLock::new(vec![0u8; PAGE_SIZE])),
                         dirty: false,
@@ -51,15 +70,26 @@ impl BufferPoolManager {
                         block_number: None,
                     })
                     .collect(),
-                free_frame_ids: (0..POOL_SIZE).collect(),
+                free_frame_ids: (0..pool_size).collect(),
+                replacer: replacer_policy.build(pool_size),
             }),
+            deleted_pages: Mutex::new(HashMap::new()),
+            index_latches: Mutex::new(HashMap::new()),
         }
     }
+    pub fn disk_manager(&self) -> &Arc<DiskManager> {
+        &self.disk_manager
+    }
+    pub fn log_manager(&self) -> &Arc<LogManager> {
+        &self.log_manager
+    }
     pub fn fetch_page(&self, block_number: usize) -> Result<FetchPageResult> {
         let mut buffer_pool = self.buffer_pool.lock().unwrap();
         // If the page(P) exists in the pool, pin it and return it immediately.
         if let Some(&frame_id) = buffer_pool.page_table.get(&block_number) {
             buffer_pool.frames[frame_id].pin_count += 1;
+            buffer_pool.replacer.record_access(frame_id);
+            buffer_pool.replacer.set_evictable(frame_id, false);
             return Ok(FetchPageResult {
                 block_number,
                 frame_id,
@@ -73,22 +103,48 @@ impl BufferPoolManager {
             buffer_pool.free_frame_ids.pop();
         } else {
             // If the free list is empty, find a replacement page(R) from the replacer.
-            // TODO: implement replacer. Following replacer is random one.
-            loop {
-                let mut rng = thread_rng();
-                let v: usize = rng.gen_range(0..POOL_SIZE);
-                if buffer_pool.frames[v].pin_count == 0 {
-                    frame_id = v;
-                    break;
+            frame_id = match buffer_pool.replacer.evict() {
+                Some(frame_id) => frame_id,
+                None => {
+                    return Err(anyhow!(
+                        "no frame can be evicted since all {} pages in the buffer pool are pinned",
+                        buffer_pool.frames.len()
+                    ))
                 }
+            };
+            debug!("evict {}", frame_id);
+        }
+        if let Err(e) = self.replace_page(&mut buffer_pool, frame_id, block_number) {
+            // The frame keeps R, or stays free.
+            if buffer_pool.frames[frame_id].block_number.is_some() {
+                buffer_pool.replacer.record_access(frame_id);
+                buffer_pool.replacer.set_evictable(frame_id, true);
+            } else {
+                buffer_pool.free_frame_ids.push(frame_id);
             }
-            println!("evict {}", frame_id);
+            return Err(e);
         }
-        // If the R is dirty, write it back to the disk.
+        buffer_pool.replacer.record_access(frame_id);
+        buffer_pool.replacer.set_evictable(frame_id, false);
+        Ok(FetchPageResult {
+            block_number,
+            frame_id,
+            data: buffer_pool.frames[frame_id].data.clone(),
+        })
+    }
+    // Loads the page(P) into the frame, pinned, in place of R.
+    fn replace_page(
+        &self,
+        buffer_pool: &mut BufferPool,
+        frame_id: usize,
+        block_number: usize,
+    ) -> Result<()> {
+        // If the R is dirty, write it back to the disk after the log records of its changes.
         if buffer_pool.frames[frame_id].dirty {
-            // TODO: implement flush.
+            self.log_manager
+                .flush(page_lsn(&buffer_pool.frames[frame_id].data.read().unwrap()))?;
             let old_block_number = buffer_pool.frames[frame_id].block_number;
-            println!(
+            debug!(
                 "flush frame_id: {}, block_number {:?}",
                 frame_id, old_block_number
             );
@@ -97,44 +153,261 @@ impl BufferPoolManager {
                 &buffer_pool.frames[frame_id].data.clone().read().unwrap(),
             )?;
         }
+        let data = self.disk_manager.read_page(block_number)?;
+        // Delete R from the page table and insert P.
         if let Some(old_block_number) = buffer_pool.frames[frame_id].block_number {
             buffer_pool.page_table.remove(&old_block_number);
         }
         buffer_pool.page_table.insert(block_number, frame_id);
-        // Delete R from the page table and insert P.
-        let data = self.disk_manager.read_page(block_number)?;
         buffer_pool.frames[frame_id] = Frame {
             data: Arc::new(RwLock::new(data)),
             dirty: false,
             pin_count: 1,
             block_number: Some(block_number),
         };
-        Ok(FetchPageResult {
-            block_number,
-            frame_id,
-            data: buffer_pool.frames[frame_id].data.clone(),
-        })
+        Ok(())
     }
     pub fn unpin_frame(&self, frame_id: usize, dirty: bool) {
         let mut buffer_pool = self.buffer_pool.lock().unwrap();
         if buffer_pool.frames[frame_id].pin_count > 0 {
             buffer_pool.frames[frame_id].pin_count -= 1;
-            buffer_pool.frames[frame_id].dirty = dirty;
+            buffer_pool.frames[frame_id].dirty |= dirty;
+            if buffer_pool.frames[frame_id].pin_count == 0 {
+                buffer_pool.replacer.set_evictable(frame_id, true);
+            }
         }
     }
     pub fn new_page(&self, data: &[u8]) -> Result<FetchPageResult> {
         // Allocate page throught disk_manager.
-        let block_number = self.disk_manager.write_new_page(&data)?;
-        Ok(self.fetch_page(block_number)?)
+        let block_number = self.disk_manager.write_new_page(data)?;
+        // The image is logged since the page may have been freed and reused, in which case
+        // the recovery replays the changes of its previous life too.
+        let res = self.fetch_page(block_number)?;
+        let lsn = self.log_manager.append(
+            0,
+            LogRecordBody::NewPage {
+                block_number,
+                data: data.to_vec(),
+            },
+        );
+        if let Ok(lsn) = lsn {
+            set_page_lsn(&mut res.data.write().unwrap(), lsn);
+        }
+        let mut buffer_pool = self.buffer_pool.lock().unwrap();
+        buffer_pool.frames[res.frame_id].dirty = true;
+        drop(buffer_pool);
+        lsn?;
+        Ok(res)
+    }
+    // Replaces the content of the page with `data` and its LSN, without reading the page
+    // first, which the crash may have cut off the data file.
+    pub fn reset_page(&self, block_number: usize, data: &[u8], lsn: Lsn) -> Result<()> {
+        let mut data = data.to_vec();
+        set_page_lsn(&mut data, lsn);
+        let cached = self
+            .buffer_pool
+            .lock()
+            .unwrap()
+            .page_table
+            .contains_key(&block_number);
+        if !cached {
+            return self.disk_manager.write_page(block_number, &data);
+        }
+        let res = self.fetch_page(block_number)?;
+        res.data.write().unwrap().copy_from_slice(&data);
+        self.unpin_frame(res.frame_id, true);
+        Ok(())
+    }
+    // Replaces the content of the fetched page with `data`, logging the change for the
+    // transaction, and unpins the page. The caller holds the write latch of the page from
+    // the read of the old content, so that no other change is lost.
+    pub fn write_page(
+        &self,
+        txn_id: TxnId,
+        res: &FetchPageResult,
+        mut page_data: RwLockWriteGuard<Vec<u8>>,
+        data: &[u8],
+        undo: Undo,
+    ) -> Result<()> {
+        let (offset, after) = match page_diff(&page_data, data) {
+            Some(diff) => diff,
+            None => {
+                drop(page_data);
+                self.unpin_frame(res.frame_id, false);
+                return Ok(());
+            }
+        };
+        let lsn = self.log_manager.append(
+            txn_id,
+            LogRecordBody::Update {
+                block_number: res.block_number,
+                offset,
+                data: after,
+                undo,
+            },
+        );
+        if let Ok(lsn) = lsn {
+            page_data.copy_from_slice(data);
+            set_page_lsn(&mut page_data, lsn);
+        }
+        drop(page_data);
+        self.unpin_frame(res.frame_id, lsn.is_ok());
+        lsn?;
+        Ok(())
+    }
+    // Returns the latch which serializes the changes to the index whose root is the page.
+    pub fn index_latch(&self, root_block_number: usize) -> Arc<Mutex<()>> {
+        self.index_latches
+            .lock()
+            .unwrap()
+            .entry(root_block_number)
+            .or_default()
+            .clone()
+    }
+    // Frees the page once the transaction commits, so that a rollback can bring it back.
+    // Pages which belong to no transaction (txn_id 0) are freed immediately.
+    pub fn delete_page(&self, txn_id: TxnId, block_number: usize) -> Result<()> {
+        if txn_id == 0 {
+            return self.free_page(block_number);
+        }
+        self.deleted_pages
+            .lock()
+            .unwrap()
+            .entry(txn_id)
+            .or_default()
+            .push(block_number);
+        Ok(())
+    }
+    // Frees the pages deleted by a committed transaction.
+    pub fn free_deleted_pages(&self, txn_id: TxnId) -> Result<()> {
+        let block_numbers = self.deleted_pages.lock().unwrap().remove(&txn_id);
+        for block_number in block_numbers.unwrap_or_default() {
+            self.free_page(block_number)?;
+        }
+        Ok(())
+    }
+    // Keeps the pages deleted by an aborted transaction.
+    pub fn forget_deleted_pages(&self, txn_id: TxnId) {
+        self.deleted_pages.lock().unwrap().remove(&txn_id);
+    }
+    // Drops the page from the pool without writing it back and returns it to the free page
+    // list of the disk manager.
+    fn free_page(&self, block_number: usize) -> Result<()> {
+        self.discard_page(block_number)?;
+        self.disk_manager.free_page(block_number)
+    }
+    // Allocates a page for the runs of a sort. Neither the page nor its changes are logged,
+    // since the runs are dropped by a crash.
+    pub fn new_spill_page(&self, data: &[u8]) -> Result<FetchPageResult> {
+        let block_number = self.disk_manager.write_new_spill_page(data)?;
+        self.fetch_page(block_number)
+    }
+    pub fn free_spill_page(&self, block_number: usize) -> Result<()> {
+        self.discard_page(block_number)?;
+        self.disk_manager.free_spill_page(block_number)
+    }
+    // Frees the pages of the sorts cut off by a crash.
+    pub fn free_spill_pages(&self) -> Result<()> {
+        for block_number in self.disk_manager.spill_page_block_numbers()? {
+            self.discard_page(block_number)?;
+        }
+        self.disk_manager.free_spill_pages()
+    }
+    // Drops the page from the pool without writing it back.
+    fn discard_page(&self, block_number: usize) -> Result<()> {
+        let mut buffer_pool = self.buffer_pool.lock().unwrap();
+        if let Some(&frame_id) = buffer_pool.page_table.get(&block_number) {
+            if buffer_pool.frames[frame_id].pin_count > 0 {
+                return Err(anyhow!("page {} is pinned", block_number));
+            }
+            buffer_pool.page_table.remove(&block_number);
+            buffer_pool.frames[frame_id] = Frame {
+                data: Arc::new(RwLock::new(vec![0u8; PAGE_SIZE])),
+                dirty: false,
+                pin_count: 0,
+                block_number: None,
+            };
+            buffer_pool.replacer.remove(frame_id);
+            buffer_pool.free_frame_ids.push(frame_id);
+        }
+        Ok(())
     }
     pub fn flush_all_pages(&self) -> Result<()> {
-        let buffer_pool = self.buffer_pool.lock().unwrap();
-        for frame in buffer_pool.frames.iter() {
+        // The pages are pinned so that they stay in their frames, and latched after the pool
+        // is unlocked since a writer may hold a latch while it fetches another page.
+        let mut buffer_pool = self.buffer_pool.lock().unwrap();
+        let mut pages = Vec::new();
+        for (frame_id, frame) in buffer_pool.frames.iter_mut().enumerate() {
             if let Some(block_number) = frame.block_number {
-                self.disk_manager
-                    .write_page(block_number, &frame.data.clone().read().unwrap())?;
+                frame.pin_count += 1;
+                pages.push((frame_id, block_number, frame.data.clone()));
             }
         }
+        for &(frame_id, _, _) in &pages {
+            buffer_pool.replacer.set_evictable(frame_id, false);
+        }
+        drop(buffer_pool);
+        let mut result = Ok(());
+        for (frame_id, block_number, data) in pages {
+            if result.is_ok() {
+                let data = data.read().unwrap();
+                result = self
+                    .log_manager
+                    .flush(page_lsn(&data))
+                    .and_then(|()| self.disk_manager.write_page(block_number, &data));
+            }
+            self.unpin_frame(frame_id, false);
+        }
+        result
+    }
+    // Writes every page back and empties the log if no transaction is running.
+    pub fn checkpoint(&self) -> Result<()> {
+        let lsn = self.log_manager.flush_all()?;
+        self.flush_all_pages()?;
+        self.disk_manager.sync_data_file()?;
+        self.log_manager.truncate(lsn)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::replacer::ReplacerPolicy;
+    use crate::buffer::BufferPoolManager;
+    use crate::disk::{DiskManager, PAGE_SIZE};
+    use anyhow::Result;
+    use std::fs;
+    use std::sync::Arc;
+    const POOL_SIZE: usize = 3;
+    #[test]
+    fn evict_unpinned_frames() -> Result<()> {
+        for (name, policy) in [
+            ("lru", ReplacerPolicy::Lru),
+            ("lru_k", ReplacerPolicy::LruK(2)),
+            ("clock", ReplacerPolicy::Clock),
+        ] {
+            let dir = format!("tmp/evict_unpinned_frames/{}/", name);
+            fs::create_dir_all(&dir)?;
+            let disk_manager = Arc::new(DiskManager::new(dir));
+            disk_manager.init_data_file()?;
+            let block_numbers = (0..=POOL_SIZE)
+                .map(|i| disk_manager.write_new_page(&vec![i as u8; PAGE_SIZE]))
+                .collect::<Result<Vec<_>>>()?;
+            let buffer_pool_manager = BufferPoolManager::with_pool(disk_manager, POOL_SIZE, policy);
+            let frames = block_numbers[..POOL_SIZE]
+                .iter()
+                .map(|&block_number| buffer_pool_manager.fetch_page(block_number))
+                .collect::<Result<Vec<_>>>()?;
+            // Every frame is pinned.
+            assert!(buffer_pool_manager
+                .fetch_page(block_numbers[POOL_SIZE])
+                .is_err());
+            buffer_pool_manager.unpin_frame(frames[1].frame_id, false);
+            let res = buffer_pool_manager.fetch_page(block_numbers[POOL_SIZE])?;
+            assert_eq!(res.frame_id, frames[1].frame_id);
+            assert_eq!(res.data.read().unwrap()[0], POOL_SIZE as u8);
+            assert!(buffer_pool_manager.fetch_page(block_numbers[1]).is_err());
+        }
         Ok(())
     }
 }
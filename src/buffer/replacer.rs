@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use anyhow::Result;
+
+// Chooses the frame whose page is evicted when the buffer pool is full. Only the frames marked
+// evictable, that is the unpinned ones, may be chosen.
+pub trait Replacer: Debug + Send {
+    // Records that the page in the frame has been fetched.
+    fn record_access(&mut self, frame_id: usize);
+    fn set_evictable(&mut self, frame_id: usize, evictable: bool);
+    // Chooses a frame and forgets it. Returns None if no frame is evictable.
+    fn evict(&mut self) -> Option<usize>;
+    // Forgets the frame, whose page has been removed from the pool.
+    fn remove(&mut self, frame_id: usize);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum ReplacerPolicy {
+    #[default]
+    Lru,
+    // K, the number of accesses remembered per frame
+    LruK(usize),
+    Clock,
+}
+
+impl ReplacerPolicy {
+    pub fn build(self, pool_size: usize) -> Box<dyn Replacer> {
+        match self {
+            ReplacerPolicy::Lru => Box::new(LruReplacer::new()),
+            ReplacerPolicy::LruK(k) => Box::new(LruKReplacer::new(k)),
+            ReplacerPolicy::Clock => Box::new(ClockReplacer::new(pool_size)),
+        }
+    }
+}
+
+// "lru", "clock", or "lru-k" which remembers 2 accesses and "lru-<k>" for another K.
+impl FromStr for ReplacerPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match &*s.to_lowercase() {
+            "lru" => Ok(ReplacerPolicy::Lru),
+            "lru-k" => Ok(ReplacerPolicy::LruK(2)),
+            "clock" => Ok(ReplacerPolicy::Clock),
+            s => match s.strip_prefix("lru-").and_then(|k| k.parse().ok()) {
+                Some(k) if k > 0 => Ok(ReplacerPolicy::LruK(k)),
+                _ => Err(anyhow!("unknown replacer policy: {}", s)),
+            },
+        }
+    }
+}
+
+// Evicts the least recently used frame.
+#[derive(Debug, Default)]
+pub struct LruReplacer {
+    current_timestamp: u64,
+    // frame_id -> timestamp of the last access
+    last_accesses: HashMap<usize, u64>,
+    evictable: HashSet<usize>,
+}
+
+impl LruReplacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn record_access(&mut self, frame_id: usize) {
+        self.current_timestamp += 1;
+        self.last_accesses.insert(frame_id, self.current_timestamp);
+    }
+    fn set_evictable(&mut self, frame_id: usize, evictable: bool) {
+        if evictable {
+            self.evictable.insert(frame_id);
+        } else {
+            self.evictable.remove(&frame_id);
+        }
+    }
+    fn evict(&mut self) -> Option<usize> {
+        let frame_id = *self
+            .evictable
+            .iter()
+            .min_by_key(|frame_id| self.last_accesses.get(frame_id))?;
+        self.remove(frame_id);
+        Some(frame_id)
+    }
+    fn remove(&mut self, frame_id: usize) {
+        self.last_accesses.remove(&frame_id);
+        self.evictable.remove(&frame_id);
+    }
+}
+
+// Evicts the frame whose K-th most recent access is the oldest, so that a page read once by a
+// scan does not push out the pages used over and over. The frames accessed fewer than K times
+// go first, in LRU order.
+#[derive(Debug)]
+pub struct LruKReplacer {
+    k: usize,
+    current_timestamp: u64,
+    // frame_id -> timestamps of the last K accesses, oldest first
+    histories: HashMap<usize, VecDeque<u64>>,
+    evictable: HashSet<usize>,
+}
+
+impl LruKReplacer {
+    pub fn new(k: usize) -> Self {
+        LruKReplacer {
+            k,
+            current_timestamp: 0,
+            histories: HashMap::new(),
+            evictable: HashSet::new(),
+        }
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn record_access(&mut self, frame_id: usize) {
+        self.current_timestamp += 1;
+        let history = self.histories.entry(frame_id).or_default();
+        history.push_back(self.current_timestamp);
+        if history.len() > self.k {
+            history.pop_front();
+        }
+    }
+    fn set_evictable(&mut self, frame_id: usize, evictable: bool) {
+        if evictable {
+            self.evictable.insert(frame_id);
+        } else {
+            self.evictable.remove(&frame_id);
+        }
+    }
+    fn evict(&mut self) -> Option<usize> {
+        let frame_id = *self.evictable.iter().min_by_key(|frame_id| {
+            let history = self.histories.get(frame_id);
+            (
+                history.map_or(0, |history| history.len()) >= self.k,
+                history.and_then(|history| history.front().copied()),
+            )
+        })?;
+        self.remove(frame_id);
+        Some(frame_id)
+    }
+    fn remove(&mut self, frame_id: usize) {
+        self.histories.remove(&frame_id);
+        self.evictable.remove(&frame_id);
+    }
+}
+
+// Approximates LRU with a reference bit per frame. The hand sweeps the frames, clearing the
+// bits, and evicts the first evictable frame whose bit is already clear.
+#[derive(Debug)]
+pub struct ClockReplacer {
+    referenced: Vec<bool>,
+    evictable: Vec<bool>,
+    hand: usize,
+}
+
+impl ClockReplacer {
+    pub fn new(pool_size: usize) -> Self {
+        ClockReplacer {
+            referenced: vec![false; pool_size],
+            evictable: vec![false; pool_size],
+            hand: 0,
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn record_access(&mut self, frame_id: usize) {
+        self.referenced[frame_id] = true;
+    }
+    fn set_evictable(&mut self, frame_id: usize, evictable: bool) {
+        self.evictable[frame_id] = evictable;
+    }
+    fn evict(&mut self) -> Option<usize> {
+        // Two rounds clear every bit on the way.
+        for _ in 0..self.referenced.len() * 2 {
+            let frame_id = self.hand;
+            self.hand = (self.hand + 1) % self.referenced.len();
+            if !self.evictable[frame_id] {
+                continue;
+            }
+            if self.referenced[frame_id] {
+                self.referenced[frame_id] = false;
+            } else {
+                self.remove(frame_id);
+                return Some(frame_id);
+            }
+        }
+        None
+    }
+    fn remove(&mut self, frame_id: usize) {
+        self.referenced[frame_id] = false;
+        self.evictable[frame_id] = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::replacer::{
+        ClockReplacer, LruKReplacer, LruReplacer, Replacer, ReplacerPolicy,
+    };
+    use anyhow::Result;
+    // Accesses the frames in order, then unpins them.
+    fn access(replacer: &mut dyn Replacer, frame_ids: &[usize]) {
+        for &frame_id in frame_ids {
+            replacer.record_access(frame_id);
+            replacer.set_evictable(frame_id, true);
+        }
+    }
+    #[test]
+    fn lru() {
+        let mut replacer = LruReplacer::new();
+        access(&mut replacer, &[0, 1, 2, 0]);
+        replacer.set_evictable(1, false);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), None);
+        replacer.set_evictable(1, true);
+        assert_eq!(replacer.evict(), Some(1));
+    }
+    #[test]
+    fn lru_k() {
+        let mut replacer = LruKReplacer::new(2);
+        // 0 and 1 are used twice and 2, 3 once as by a scan.
+        access(&mut replacer, &[0, 1, 0, 1, 2, 3]);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(3));
+        access(&mut replacer, &[0]);
+        // The second most recent access of 1 is older than that of 0.
+        assert_eq!(replacer.evict(), Some(1));
+        replacer.remove(0);
+        assert_eq!(replacer.evict(), None);
+    }
+    #[test]
+    fn clock() {
+        let mut replacer = ClockReplacer::new(4);
+        access(&mut replacer, &[0, 1, 2]);
+        // Every bit is set, so the hand comes back to the first frame.
+        assert_eq!(replacer.evict(), Some(0));
+        access(&mut replacer, &[1, 3]);
+        assert_eq!(replacer.evict(), Some(2));
+        replacer.set_evictable(1, false);
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), None);
+    }
+    #[test]
+    fn parse_policy() -> Result<()> {
+        assert_eq!("LRU".parse::<ReplacerPolicy>()?, ReplacerPolicy::Lru);
+        assert_eq!("lru-k".parse::<ReplacerPolicy>()?, ReplacerPolicy::LruK(2));
+        assert_eq!("lru-3".parse::<ReplacerPolicy>()?, ReplacerPolicy::LruK(3));
+        assert_eq!("clock".parse::<ReplacerPolicy>()?, ReplacerPolicy::Clock);
+        assert!("lru-0".parse::<ReplacerPolicy>().is_err());
+        assert!("random".parse::<ReplacerPolicy>().is_err());
+        Ok(())
+    }
+}
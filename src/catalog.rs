@@ -1,19 +1,49 @@
 use std::cmp;
+use std::fmt;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 
 use crate::buffer::BufferPoolManager;
+use crate::storage::btree::BTree;
+use crate::storage::page;
 use crate::storage::table::Table;
 use crate::storage::tuple::Tuple;
+use crate::transaction::Snapshot;
 use crate::value::Value;
+use crate::wal::{recovery, TxnId};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct Schema {
     pub columns: Vec<Column>,
 }
 
+impl Schema {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+    // Columns of a FROM clause are named `table.column`, so an unqualified name matches
+    // the column of that name in any table as long as it is unique.
+    pub fn resolve_column(&self, name: &str) -> Result<usize> {
+        if let Some(i) = self.column_index(name) {
+            return Ok(i);
+        }
+        let matches: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.name.split_once('.').map(|(_, c)| c) == Some(name))
+            .map(|(i, _)| i)
+            .collect();
+        match matches[..] {
+            [i] => Ok(i),
+            [] => Err(anyhow!("Column({}) not found", name)),
+            _ => Err(anyhow!("Column({}) is ambiguous", name)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct Column {
     pub name: String,
@@ -26,6 +56,51 @@ pub enum ColumnType {
     Varchar,
 }
 
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnType::Int => write!(f, "Int"),
+            ColumnType::Varchar => write!(f, "Varchar"),
+        }
+    }
+}
+
+// Constraints of a column, kept in catalog_attributes along with its name and type. PRIMARY KEY
+// and UNIQUE are not among them, since they are enforced by unique indexes.
+#[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct ColumnConstraints {
+    pub not_null: bool,
+    // the value of the column when an INSERT omits it, NULL if None
+    pub default: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct IndexInfo {
+    pub object_id: usize,
+    pub name: String,
+    pub column_names: Vec<String>,
+    pub unique: bool,
+    pub root_block_number: usize,
+}
+
+impl IndexInfo {
+    // Schema of the keys, which are the indexed columns of `schema`.
+    pub fn key_schema(&self, schema: &Schema) -> Result<Schema> {
+        let mut columns = Vec::new();
+        for name in self.column_names.iter() {
+            columns.push(schema.columns[schema.resolve_column(name)?].clone());
+        }
+        Ok(Schema { columns })
+    }
+    pub fn key(&self, schema: &Schema, tuple: &Tuple) -> Result<Vec<Value>> {
+        let mut key = Vec::new();
+        for name in self.column_names.iter() {
+            key.push(tuple.values[schema.resolve_column(name)?].clone());
+        }
+        Ok(key)
+    }
+}
+
 #[derive(Debug)]
 pub struct Catalog {
     buffer_pool_manager: Arc<BufferPoolManager>,
@@ -36,9 +111,11 @@ pub struct Catalog {
 const HEADER_FIRST_BLOCK_NUMBER: usize = 0;
 const CATALOG_TABLE_FIRST_BLOCK_NUMBER: usize = 1;
 const CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER: usize = 2;
+const CATALOG_INDEX_FIRST_BLOCK_NUMBER: usize = 3;
 const HEADER_OID: usize = 0;
 const CATALOG_TABLE_OID: usize = 1;
 const CATALOG_ATTRIBUTE_OID: usize = 1;
+const CATALOG_INDEX_OID: usize = 2;
 
 impl Catalog {
     pub fn new(buffer_pool_manager: Arc<BufferPoolManager>) -> Self {
@@ -49,107 +126,253 @@ impl Catalog {
             oid_counter: Arc::new(AtomicUsize::new(0)),
         }
     }
+    pub fn buffer_pool_manager(&self) -> &Arc<BufferPoolManager> {
+        &self.buffer_pool_manager
+    }
     pub fn initialize(&self) -> Result<()> {
+        let txn_id = self.buffer_pool_manager.log_manager().begin();
         let header_table =
             Table::create(&self.buffer_pool_manager, &self.catalog_schema_map.header)?;
-        header_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(HEADER_OID as i32),
-                Value::Int(HEADER_FIRST_BLOCK_NUMBER as i32),
-            ],
-        })?;
-        header_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_TABLE_OID as i32),
-                Value::Int(CATALOG_TABLE_FIRST_BLOCK_NUMBER as i32),
-            ],
-        })?;
-        header_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_ATTRIBUTE_OID as i32),
-                Value::Int(CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER as i32),
-            ],
-        })?;
+        header_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(HEADER_OID as i32),
+                    Value::Int(HEADER_FIRST_BLOCK_NUMBER as i32),
+                ],
+            },
+        )?;
+        header_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_TABLE_OID as i32),
+                    Value::Int(CATALOG_TABLE_FIRST_BLOCK_NUMBER as i32),
+                ],
+            },
+        )?;
+        header_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Int(CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER as i32),
+                ],
+            },
+        )?;
+        header_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_INDEX_OID as i32),
+                    Value::Int(CATALOG_INDEX_FIRST_BLOCK_NUMBER as i32),
+                ],
+            },
+        )?;
         let catalog_table_table = Table::create(
             &self.buffer_pool_manager,
             &self.catalog_schema_map.catalog_table,
         )?;
-        catalog_table_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_TABLE_OID as i32),
-                Value::Varchar("catalog_tables".to_string()),
-            ],
-        })?;
-        catalog_table_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_ATTRIBUTE_OID as i32),
-                Value::Varchar("catalog_attributes".to_string()),
-            ],
-        })?;
+        catalog_table_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_TABLE_OID as i32),
+                    Value::Varchar("catalog_tables".to_string()),
+                ],
+            },
+        )?;
+        catalog_table_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("catalog_attributes".to_string()),
+                ],
+            },
+        )?;
+        catalog_table_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_INDEX_OID as i32),
+                    Value::Varchar("catalog_indexes".to_string()),
+                ],
+            },
+        )?;
         let catalog_attribute_table = Table::create(
             &self.buffer_pool_manager,
             &self.catalog_schema_map.catalog_attribute,
         )?;
-        catalog_attribute_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_TABLE_OID as i32),
-                Value::Varchar("object_id".to_string()),
-                Value::Varchar("integer".to_string()),
-            ],
-        })?;
-        catalog_attribute_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_TABLE_OID as i32),
-                Value::Varchar("name".to_string()),
-                Value::Varchar("varchar".to_string()),
-            ],
-        })?;
-        catalog_attribute_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_ATTRIBUTE_OID as i32),
-                Value::Varchar("object_id".to_string()),
-                Value::Varchar("integer".to_string()),
-            ],
-        })?;
-        catalog_attribute_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_ATTRIBUTE_OID as i32),
-                Value::Varchar("name".to_string()),
-                Value::Varchar("varchar".to_string()),
-            ],
-        })?;
-        catalog_attribute_table.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(CATALOG_ATTRIBUTE_OID as i32),
-                Value::Varchar("type".to_string()),
-                Value::Varchar("varchar".to_string()),
-            ],
-        })?;
-        self.buffer_pool_manager.flush_all_pages()?;
-        Ok(())
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_TABLE_OID as i32),
+                    Value::Varchar("object_id".to_string()),
+                    Value::Varchar("integer".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_TABLE_OID as i32),
+                    Value::Varchar("name".to_string()),
+                    Value::Varchar("varchar".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("object_id".to_string()),
+                    Value::Varchar("integer".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("name".to_string()),
+                    Value::Varchar("varchar".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("type".to_string()),
+                    Value::Varchar("varchar".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("not_null".to_string()),
+                    Value::Varchar("integer".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        catalog_attribute_table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(CATALOG_ATTRIBUTE_OID as i32),
+                    Value::Varchar("default_value".to_string()),
+                    Value::Varchar("varchar".to_string()),
+                    Value::Int(0),
+                    Value::Null,
+                ],
+            },
+        )?;
+        for (name, column_type) in [
+            ("object_id", "integer"),
+            ("name", "varchar"),
+            ("table_object_id", "integer"),
+            ("column_names", "varchar"),
+            ("is_unique", "integer"),
+        ] {
+            catalog_attribute_table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![
+                        Value::Int(CATALOG_INDEX_OID as i32),
+                        Value::Varchar(name.to_string()),
+                        Value::Varchar(column_type.to_string()),
+                        Value::Int(0),
+                        Value::Null,
+                    ],
+                },
+            )?;
+        }
+        Table::create(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.catalog_index,
+        )?;
+        self.buffer_pool_manager.log_manager().commit(txn_id)?;
+        self.buffer_pool_manager.checkpoint()
     }
-    pub fn bootstrap(&self) {
-        self.set_oid();
+    // Recovers the data from the log before reading the system tables.
+    pub fn bootstrap(&self) -> Result<()> {
+        // Refuse a data file written in another page layout before recovery touches it.
+        let res = self
+            .buffer_pool_manager
+            .fetch_page(HEADER_FIRST_BLOCK_NUMBER)?;
+        let checked = page::check_format_version(&res.data.read().unwrap());
+        self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        checked?;
+        recovery::recover(&self.buffer_pool_manager)?;
+        self.set_oid()
     }
-    fn set_oid(&self) {
+    fn set_oid(&self) -> Result<()> {
         let header = Table::new(
             &self.buffer_pool_manager,
             &self.catalog_schema_map.header,
             HEADER_FIRST_BLOCK_NUMBER,
         );
         let mut max: usize = 0;
-        for page in header {
-            for tuple in page.tuples.iter() {
-                if let Value::Int(v) = tuple.values[0] {
-                    max = cmp::max(max, v as usize);
-                }
+        // The ids of the deleted objects are not reused until their versions are pruned.
+        for item in header.scan_all_versions() {
+            let (_, tuple) = item?;
+            if let Value::Int(v) = tuple.values[0] {
+                max = cmp::max(max, v as usize);
             }
         }
         self.oid_counter.store(max, Ordering::Relaxed);
+        Ok(())
     }
-    pub fn create_table(&self, table_name: &str, schema: &Schema) -> Result<()> {
-        // TODO: validations
-        // table name dup, attribute name dup
+    pub fn create_table(&self, txn_id: TxnId, table_name: &str, schema: &Schema) -> Result<()> {
+        self.create_table_with_constraints(
+            txn_id,
+            table_name,
+            schema,
+            &vec![ColumnConstraints::default(); schema.columns.len()],
+        )
+    }
+    // `constraints` are those of the columns of `schema`, in the same order.
+    pub fn create_table_with_constraints(
+        &self,
+        txn_id: TxnId,
+        table_name: &str,
+        schema: &Schema,
+        constraints: &[ColumnConstraints],
+    ) -> Result<()> {
+        // The caller checks that the table does not exist yet.
+        if constraints.len() != schema.columns.len() {
+            return Err(anyhow!(
+                "{} column constraints are given for {} columns",
+                constraints.len(),
+                schema.columns.len()
+            ));
+        }
+        for (i, c) in schema.columns.iter().enumerate() {
+            if schema.columns[..i].iter().any(|other| other.name == c.name) {
+                return Err(anyhow!("Column({}) specified more than once", c.name));
+            }
+        }
         // create table page.
         let table = Table::create(&self.buffer_pool_manager, schema)?;
         // insert into catalog_table.
@@ -160,101 +383,360 @@ impl Catalog {
             &self.catalog_schema_map.catalog_table,
             CATALOG_TABLE_FIRST_BLOCK_NUMBER,
         );
-        catalog_tables.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(new_oid as i32),
-                Value::Varchar(table_name.to_string()),
-            ],
-        })?;
+        catalog_tables.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(new_oid as i32),
+                    Value::Varchar(table_name.to_string()),
+                ],
+            },
+        )?;
         // insert into header.
         let header = Table::new(
             &self.buffer_pool_manager,
             &self.catalog_schema_map.header,
             HEADER_FIRST_BLOCK_NUMBER,
         );
-        header.insert_tuple(Tuple {
-            values: vec![
-                Value::Int(new_oid as i32),
-                Value::Int(table.first_block_number as i32),
-            ],
-        })?;
+        header.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(new_oid as i32),
+                    Value::Int(table.first_block_number as i32),
+                ],
+            },
+        )?;
         // insert into catalog_attribute.
         let catalog_attributes = Table::new(
             &self.buffer_pool_manager,
             &self.catalog_schema_map.catalog_attribute,
             CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER,
         );
-        for c in schema.columns.iter() {
-            catalog_attributes.insert_tuple(Tuple {
+        for (c, constraints) in schema.columns.iter().zip(constraints) {
+            catalog_attributes.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![
+                        Value::Int(new_oid as i32),
+                        Value::Varchar(c.name.clone()),
+                        Value::Varchar(match c.column_type {
+                            ColumnType::Int => "int".to_string(),
+                            ColumnType::Varchar => "varchar".to_string(),
+                        }),
+                        Value::Int(constraints.not_null as i32),
+                        match &constraints.default {
+                            Some(Value::Null) | None => Value::Null,
+                            Some(default) => Value::Varchar(default.to_string()),
+                        },
+                    ],
+                },
+            )?;
+        }
+        Ok(())
+    }
+    // Removes the table from the system tables. Its pages are freed when the transaction
+    // commits.
+    pub fn drop_table(&self, txn_id: TxnId, table_name: &str) -> Result<()> {
+        let oid = match self.get_oid(table_name)? {
+            Some(oid) => oid,
+            None => return Err(anyhow!("Table({}) not found", table_name)),
+        };
+        if oid <= CATALOG_INDEX_OID {
+            return Err(anyhow!("Table({}) is a system table", table_name));
+        }
+        let schema = match self.get_schema(table_name)? {
+            Some(schema) => schema,
+            None => return Err(anyhow!("Table({}) not found", table_name)),
+        };
+        let first_block_number = match self.get_first_block_number(table_name)? {
+            Some(first_block_number) => first_block_number,
+            None => return Err(anyhow!("Table({}) not found", table_name)),
+        };
+        for index in self.get_indexes(table_name)? {
+            self.drop_index(txn_id, &index.name)?;
+        }
+        self.delete_object_rows(
+            txn_id,
+            &self.catalog_schema_map.header,
+            HEADER_FIRST_BLOCK_NUMBER,
+            oid,
+        )?;
+        self.delete_object_rows(
+            txn_id,
+            &self.catalog_schema_map.catalog_table,
+            CATALOG_TABLE_FIRST_BLOCK_NUMBER,
+            oid,
+        )?;
+        self.delete_object_rows(
+            txn_id,
+            &self.catalog_schema_map.catalog_attribute,
+            CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER,
+            oid,
+        )?;
+        Table::new(&self.buffer_pool_manager, &schema, first_block_number).drop_pages(txn_id)
+    }
+    // Creates an empty index. The caller fills it with the rows of the table.
+    pub fn create_index(
+        &self,
+        txn_id: TxnId,
+        index_name: &str,
+        table_name: &str,
+        column_names: &[String],
+        unique: bool,
+    ) -> Result<IndexInfo> {
+        let table_oid = match self.get_oid(table_name)? {
+            Some(oid) => oid,
+            None => return Err(anyhow!("Table({}) not found", table_name)),
+        };
+        if self.get_index(index_name)?.is_some() {
+            return Err(anyhow!("Index({}) exists", index_name));
+        }
+        let schema = self.get_schema(table_name)?.unwrap_or(Schema {
+            columns: Vec::new(),
+        });
+        for (i, name) in column_names.iter().enumerate() {
+            if schema.column_index(name).is_none() {
+                return Err(anyhow!("Column({}) not found", name));
+            }
+            if column_names[..i].contains(name) {
+                return Err(anyhow!("Column({}) specified more than once", name));
+            }
+        }
+        let mut index = IndexInfo {
+            object_id: 0,
+            name: index_name.to_string(),
+            column_names: column_names.to_vec(),
+            unique,
+            root_block_number: 0,
+        };
+        let key_schema = index.key_schema(&schema)?;
+        index.root_block_number =
+            BTree::create(&self.buffer_pool_manager, &key_schema)?.root_block_number;
+        self.oid_counter.fetch_add(1, Ordering::Relaxed);
+        index.object_id = self.oid_counter.load(Ordering::Relaxed);
+        let catalog_indexes = Table::new(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.catalog_index,
+            CATALOG_INDEX_FIRST_BLOCK_NUMBER,
+        );
+        catalog_indexes.insert_tuple(
+            txn_id,
+            Tuple {
                 values: vec![
-                    Value::Int(new_oid as i32),
-                    Value::Varchar(c.name.clone()),
-                    Value::Varchar(match c.column_type {
-                        ColumnType::Int => "int".to_string(),
-                        ColumnType::Varchar => "varchar".to_string(),
-                    }),
+                    Value::Int(index.object_id as i32),
+                    Value::Varchar(index_name.to_string()),
+                    Value::Int(table_oid as i32),
+                    Value::Varchar(column_names.join(",")),
+                    Value::Int(unique as i32),
                 ],
-            })?;
+            },
+        )?;
+        // The header holds the root of the index.
+        let header = Table::new(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.header,
+            HEADER_FIRST_BLOCK_NUMBER,
+        );
+        header.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![
+                    Value::Int(index.object_id as i32),
+                    Value::Int(index.root_block_number as i32),
+                ],
+            },
+        )?;
+        Ok(index)
+    }
+    // Removes the index from the system tables. Its pages are freed when the transaction
+    // commits.
+    pub fn drop_index(&self, txn_id: TxnId, index_name: &str) -> Result<()> {
+        let (index, table_oid) = match self.get_index(index_name)? {
+            Some(index) => index,
+            None => return Err(anyhow!("Index({}) not found", index_name)),
+        };
+        let schema = self.get_schema_by_oid(table_oid)?;
+        let key_schema = index.key_schema(&schema)?;
+        self.delete_object_rows(
+            txn_id,
+            &self.catalog_schema_map.header,
+            HEADER_FIRST_BLOCK_NUMBER,
+            index.object_id,
+        )?;
+        self.delete_object_rows(
+            txn_id,
+            &self.catalog_schema_map.catalog_index,
+            CATALOG_INDEX_FIRST_BLOCK_NUMBER,
+            index.object_id,
+        )?;
+        BTree::new(
+            &self.buffer_pool_manager,
+            &key_schema,
+            index.root_block_number,
+        )
+        .drop_pages(txn_id)
+    }
+    pub fn get_indexes(&self, table_name: &str) -> Result<Vec<IndexInfo>> {
+        let table_oid = match self.get_oid(table_name)? {
+            Some(oid) => oid,
+            None => return Ok(Vec::new()),
+        };
+        let mut indexes = Vec::new();
+        for (index, oid) in self.scan_indexes()? {
+            if oid == table_oid {
+                indexes.push(index);
+            }
+        }
+        Ok(indexes)
+    }
+    pub fn index_exists(&self, index_name: &str) -> Result<bool> {
+        Ok(self.get_index(index_name)?.is_some())
+    }
+    // Returns the index along with the object id of its table.
+    fn get_index(&self, index_name: &str) -> Result<Option<(IndexInfo, usize)>> {
+        Ok(self
+            .scan_indexes()?
+            .into_iter()
+            .find(|(index, _)| index.name == index_name))
+    }
+    fn scan_indexes(&self) -> Result<Vec<(IndexInfo, usize)>> {
+        let table = Table::new(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.catalog_index,
+            CATALOG_INDEX_FIRST_BLOCK_NUMBER,
+        );
+        let mut indexes = Vec::new();
+        for item in table.scan_tuples(&Snapshot::latest()) {
+            let (_, tuple) = item?;
+            if let [Value::Int(oid), Value::Varchar(name), Value::Int(table_oid), Value::Varchar(column_names), Value::Int(unique)] =
+                &tuple.values[..]
+            {
+                let root_block_number = match self.get_first_block_number_by_oid(*oid as usize)? {
+                    Some(root_block_number) => root_block_number,
+                    None => return Err(anyhow!("root of Index({}) not found", name)),
+                };
+                indexes.push((
+                    IndexInfo {
+                        object_id: *oid as usize,
+                        name: name.clone(),
+                        column_names: column_names.split(',').map(|s| s.to_string()).collect(),
+                        unique: *unique != 0,
+                        root_block_number,
+                    },
+                    *table_oid as usize,
+                ));
+            }
+        }
+        Ok(indexes)
+    }
+    // Deletes the rows of a system table whose object_id is `oid`.
+    fn delete_object_rows(
+        &self,
+        txn_id: TxnId,
+        schema: &Schema,
+        first_block_number: usize,
+        oid: usize,
+    ) -> Result<()> {
+        let table = Table::new(&self.buffer_pool_manager, schema, first_block_number);
+        let mut tuple_ids = Vec::new();
+        for item in table.scan_tuples(&Snapshot::latest()) {
+            let (tuple_id, tuple) = item?;
+            if tuple.values[0] == Value::Int(oid as i32) {
+                tuple_ids.push(tuple_id);
+            }
+        }
+        for tuple_id in tuple_ids.iter() {
+            table.delete_tuple(txn_id, tuple_id)?;
         }
         Ok(())
     }
     pub fn get_schema(&self, table_name: &str) -> Result<Option<Schema>> {
         match self.get_oid(table_name)? {
-            Some(oid) => {
-                let table = Table::new(
-                    &self.buffer_pool_manager,
-                    &self.catalog_schema_map.catalog_attribute,
-                    CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER,
-                );
-                let mut columns = Vec::new();
-                for page in table {
-                    for tuple in page.tuples.iter() {
-                        if let Value::Int(v) = tuple.values[0] {
-                            if v as usize == oid {
-                                if let Value::Varchar(name) = &tuple.values[1] {
-                                    if let Value::Varchar(column_type_string) = &tuple.values[2] {
-                                        columns.push(Column {
-                                            name: name.clone(),
-                                            column_type: match &**column_type_string {
-                                                "int" => ColumnType::Int,
-                                                "varchar" => ColumnType::Varchar,
-                                                _ => ColumnType::Varchar,
-                                            },
-                                        })
-                                    }
-                                }
-                            }
-                        }
-                    }
+            Some(oid) => Ok(Some(self.get_schema_by_oid(oid)?)),
+            None => Ok(None),
+        }
+    }
+    // Returns the constraints of the columns of the table, in the order of its schema.
+    pub fn get_column_constraints(&self, table_name: &str) -> Result<Vec<ColumnConstraints>> {
+        match self.get_oid(table_name)? {
+            Some(oid) => Ok(self
+                .scan_attributes(oid)?
+                .into_iter()
+                .map(|(_, constraints)| constraints)
+                .collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+    fn get_schema_by_oid(&self, oid: usize) -> Result<Schema> {
+        let columns = self
+            .scan_attributes(oid)?
+            .into_iter()
+            .map(|(column, _)| column)
+            .collect();
+        Ok(Schema { columns })
+    }
+    fn scan_attributes(&self, oid: usize) -> Result<Vec<(Column, ColumnConstraints)>> {
+        let table = Table::new(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.catalog_attribute,
+            CATALOG_ATTRIBUTE_FIRST_BLOCK_NUMBER,
+        );
+        let mut attributes = Vec::new();
+        for item in table.scan_tuples(&Snapshot::latest()) {
+            let (_, tuple) = item?;
+            if let [Value::Int(v), Value::Varchar(name), Value::Varchar(column_type_string), Value::Int(not_null), default_value] =
+                &tuple.values[..]
+            {
+                if *v as usize == oid {
+                    let column_type = match &**column_type_string {
+                        "int" => ColumnType::Int,
+                        "varchar" => ColumnType::Varchar,
+                        _ => ColumnType::Varchar,
+                    };
+                    // The text was written from a value of the column type.
+                    let default = match default_value.cast(&column_type) {
+                        Some(Value::Null) | None => None,
+                        default => default,
+                    };
+                    attributes.push((
+                        Column {
+                            name: name.clone(),
+                            column_type,
+                        },
+                        ColumnConstraints {
+                            not_null: *not_null != 0,
+                            default,
+                        },
+                    ));
                 }
-                Ok(Some(Schema { columns }))
             }
-            None => Ok(None),
         }
+        Ok(attributes)
     }
     pub fn get_first_block_number(&self, table_name: &str) -> Result<Option<usize>> {
         match self.get_oid(table_name)? {
-            Some(oid) => {
-                let header = Table::new(
-                    &self.buffer_pool_manager,
-                    &self.catalog_schema_map.header,
-                    HEADER_FIRST_BLOCK_NUMBER,
-                );
-                for page in header {
-                    for tuple in page.tuples.iter() {
-                        if let Value::Int(v) = tuple.values[0] {
-                            if v as usize == oid {
-                                if let Value::Int(first_block_number) = tuple.values[1] {
-                                    return Ok(Some(first_block_number as usize));
-                                }
-                            }
-                        }
+            Some(oid) => self.get_first_block_number_by_oid(oid),
+            None => Ok(None),
+        }
+    }
+    fn get_first_block_number_by_oid(&self, oid: usize) -> Result<Option<usize>> {
+        let header = Table::new(
+            &self.buffer_pool_manager,
+            &self.catalog_schema_map.header,
+            HEADER_FIRST_BLOCK_NUMBER,
+        );
+        for item in header.scan_tuples(&Snapshot::latest()) {
+            let (_, tuple) = item?;
+            if let Value::Int(v) = tuple.values[0] {
+                if v as usize == oid {
+                    if let Value::Int(first_block_number) = tuple.values[1] {
+                        return Ok(Some(first_block_number as usize));
                     }
                 }
-                Ok(None)
             }
-            None => Ok(None),
         }
+        Ok(None)
     }
     pub fn get_oid(&self, table_name: &str) -> Result<Option<usize>> {
         let table = Table::new(
@@ -262,13 +744,12 @@ impl Catalog {
             &self.catalog_schema_map.catalog_table,
             CATALOG_TABLE_FIRST_BLOCK_NUMBER,
         );
-        for page in table {
-            for tuple in page.tuples.iter() {
-                if let Value::Varchar(v) = &tuple.values[1] {
-                    if v == table_name {
-                        if let Value::Int(oid) = tuple.values[0] {
-                            return Ok(Some(oid as usize));
-                        }
+        for item in table.scan_tuples(&Snapshot::latest()) {
+            let (_, tuple) = item?;
+            if let Value::Varchar(v) = &tuple.values[1] {
+                if v == table_name {
+                    if let Value::Int(oid) = tuple.values[0] {
+                        return Ok(Some(oid as usize));
                     }
                 }
             }
@@ -282,6 +763,7 @@ struct CatalogSchemaMap {
     header: Schema,
     catalog_table: Schema,
     catalog_attribute: Schema,
+    catalog_index: Schema,
 }
 
 impl CatalogSchemaMap {
@@ -325,8 +807,125 @@ impl CatalogSchemaMap {
                         name: "type".to_string(),
                         column_type: ColumnType::Varchar,
                     },
+                    Column {
+                        name: "not_null".to_string(),
+                        column_type: ColumnType::Int,
+                    },
+                    // the text of the value, NULL if there is no default
+                    Column {
+                        name: "default_value".to_string(),
+                        column_type: ColumnType::Varchar,
+                    },
+                ],
+            },
+            catalog_index: Schema {
+                columns: vec![
+                    Column {
+                        name: "object_id".to_string(),
+                        column_type: ColumnType::Int,
+                    },
+                    Column {
+                        name: "name".to_string(),
+                        column_type: ColumnType::Varchar,
+                    },
+                    Column {
+                        name: "table_object_id".to_string(),
+                        column_type: ColumnType::Int,
+                    },
+                    Column {
+                        name: "column_names".to_string(),
+                        column_type: ColumnType::Varchar,
+                    },
+                    Column {
+                        name: "is_unique".to_string(),
+                        column_type: ColumnType::Int,
+                    },
                 ],
             },
         }
     }
 }
+
+// Creates the data files in the directory and opens a catalog on them.
+#[cfg(test)]
+pub fn open_catalog(dir: &str) -> Result<Arc<Catalog>> {
+    std::fs::create_dir_all(dir)?;
+    let disk_manager = Arc::new(crate::disk::DiskManager::new(dir.to_string()));
+    disk_manager.init_data_file()?;
+    let catalog = Arc::new(Catalog::new(Arc::new(BufferPoolManager::new(disk_manager))));
+    catalog.initialize()?;
+    catalog.bootstrap()?;
+    Ok(catalog)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{open_catalog, Catalog, Column, ColumnType, Schema};
+    use crate::disk::DiskManager;
+    use crate::storage::table::Table;
+    use crate::storage::tuple::Tuple;
+    use crate::transaction::{Snapshot, TransactionManager};
+    use crate::value::Value;
+    use crate::wal::PAGE_LSN_SIZE;
+    use anyhow::Result;
+    use std::fs;
+    use std::sync::Arc;
+    #[test]
+    fn reject_other_page_format() -> Result<()> {
+        let dir = "tmp/reject_other_page_format/";
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        let catalog = Catalog::new(Arc::new(BufferPoolManager::new(disk_manager.clone())));
+        catalog.initialize()?;
+        // Pages written before the header had a version keep the next block number there.
+        let mut data = disk_manager.read_page(0)?;
+        data[PAGE_LSN_SIZE..PAGE_LSN_SIZE + 2].copy_from_slice(&[255, 255]);
+        disk_manager.write_page(0, &data)?;
+        let catalog = Catalog::new(Arc::new(BufferPoolManager::new(disk_manager)));
+        assert!(catalog.bootstrap().is_err());
+        Ok(())
+    }
+    #[test]
+    fn drop_table() -> Result<()> {
+        let catalog = open_catalog("tmp/drop_table/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let disk_manager = buffer_pool_manager.disk_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let txn_id = transaction_manager.begin();
+        let schema = Schema {
+            columns: vec![Column {
+                name: "name".to_string(),
+                column_type: ColumnType::Varchar,
+            }],
+        };
+        catalog.create_table(txn_id, "users", &schema)?;
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        for _ in 0..3 {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Varchar("a".repeat(3000))],
+                },
+            )?;
+        }
+        catalog.drop_table(txn_id, "users")?;
+        transaction_manager.commit(txn_id)?;
+        let txn_id = transaction_manager.begin();
+        assert_eq!(catalog.get_oid("users")?, None);
+        assert_eq!(catalog.get_schema("users")?, None);
+        assert_eq!(catalog.get_first_block_number("users")?, None);
+        assert_eq!(disk_manager.free_page_count()?, 3);
+        assert!(catalog.drop_table(txn_id, "users").is_err());
+        assert!(catalog.drop_table(txn_id, "catalog_tables").is_err());
+        // The freed pages are reused by the next table.
+        catalog.create_table(txn_id, "users", &schema)?;
+        assert_eq!(disk_manager.free_page_count()?, 2);
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        assert_eq!(table.scan_tuples(&Snapshot::latest()).count(), 0);
+        Ok(())
+    }
+}
@@ -1,15 +1,24 @@
 use std::io;
 use std::io::Write;
+use std::net::SocketAddr;
 
 use anyhow::Result;
 use serde_json::{json, Value};
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
-pub struct Cli {}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Cli {
+    // address of the server
+    address: SocketAddr,
+    // issued by BEGIN, and sent with the following queries until the transaction ends
+    session_id: Option<String>,
+}
 
 impl Cli {
-    pub fn new() -> Self {
-        Cli {}
+    pub fn new(address: SocketAddr) -> Self {
+        Cli {
+            address,
+            session_id: None,
+        }
     }
     pub async fn start(&mut self) -> Result<()> {
         loop {
@@ -22,24 +31,62 @@ impl Cli {
                 break;
             }
             let client = reqwest::Client::new();
-            let body = json!({
+            let mut body = json!({
                 "query": query.trim(),
             });
+            if let Some(session_id) = &self.session_id {
+                body["session_id"] = json!(session_id);
+            }
             let resp = client
-                .post("http://127.0.0.1:3305/")
+                .post(&format!("http://{}/", self.address))
                 .body(body.to_string())
                 .send()
                 .await?
                 .text()
                 .await?;
             let v: Value = serde_json::from_str(&resp)?;
-            if let Value::String(s) = &v["result"] {
-                for l in s.split('\n') {
-                    println!("{}", l.trim());
-                }
-                io::stdout().flush()?;
-            }
+            self.session_id = v["session_id"]
+                .as_str()
+                .map(|session_id| session_id.to_string());
+            print_response(&v);
+            io::stdout().flush()?;
         }
         Ok(())
     }
 }
+
+fn print_response(v: &Value) {
+    if let Some(message) = v["error"]["message"].as_str() {
+        println!("Error: {}", message);
+        return;
+    }
+    if let Some(rows_affected) = v["rows_affected"].as_u64() {
+        println!("Query OK, {} rows affected", rows_affected);
+        return;
+    }
+    let columns = match v["columns"].as_array() {
+        Some(columns) if !columns.is_empty() => columns,
+        _ => {
+            println!("Query OK");
+            return;
+        }
+    };
+    let names: Vec<&str> = columns
+        .iter()
+        .map(|column| column["name"].as_str().unwrap_or(""))
+        .collect();
+    println!("{}", names.join(", "));
+    for row in v["rows"].as_array().into_iter().flatten() {
+        let values: Vec<String> = row
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|value| match value {
+                Value::Null => "NULL".to_string(),
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            })
+            .collect();
+        println!("{}", values.join(", "));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::LevelFilter;
+use serde::Deserialize;
+
+use crate::buffer::replacer::ReplacerPolicy;
+use crate::buffer::DEFAULT_POOL_SIZE;
+use crate::transaction::DEFAULT_CHECKPOINT_LOG_SIZE;
+
+// Read when no config file is given, if it exists.
+pub const DEFAULT_CONFIG_FILE_NAME: &str = "toy_db.toml";
+
+// Settings of a server, and of the client which connects to it. Each one is taken from the
+// command line flags, the config file or the default, in that order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub data_dir: String,
+    // number of frames in the buffer pool
+    pub pool_size: usize,
+    pub replacer_policy: ReplacerPolicy,
+    // size in bytes of the log file from which a checkpoint empties it
+    pub checkpoint_log_size: u64,
+    // time after which the transaction of a session which runs no statement is aborted
+    pub session_timeout: Duration,
+    pub host: IpAddr,
+    pub port: u16,
+    // port for the clients of PostgreSQL
+    pub pg_port: u16,
+    pub log_level: LevelFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: "data/".to_string(),
+            pool_size: DEFAULT_POOL_SIZE,
+            replacer_policy: ReplacerPolicy::default(),
+            checkpoint_log_size: DEFAULT_CHECKPOINT_LOG_SIZE,
+            session_timeout: Duration::from_secs(300),
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3305,
+            pg_port: 5432,
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+// The config file, whose settings are all optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    data_dir: Option<String>,
+    pool_size: Option<usize>,
+    replacer: Option<String>,
+    checkpoint_log_size: Option<u64>,
+    session_timeout: Option<u64>,
+    host: Option<String>,
+    port: Option<u16>,
+    pg_port: Option<u16>,
+    log_level: Option<String>,
+}
+
+impl Config {
+    // Reads the config file at the path, or the default one if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_FILE_NAME).exists() => {
+                Path::new(DEFAULT_CONFIG_FILE_NAME)
+            }
+            None => return Ok(Config::default()),
+        };
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Config::from_toml(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        let mut config = Config::default();
+        if let Some(data_dir) = file.data_dir {
+            config.data_dir = data_dir;
+        }
+        if let Some(pool_size) = file.pool_size {
+            config.set_pool_size(pool_size)?;
+        }
+        if let Some(replacer) = file.replacer {
+            config.replacer_policy = replacer.parse()?;
+        }
+        if let Some(checkpoint_log_size) = file.checkpoint_log_size {
+            config.checkpoint_log_size = checkpoint_log_size;
+        }
+        if let Some(session_timeout) = file.session_timeout {
+            config.set_session_timeout(session_timeout)?;
+        }
+        if let Some(host) = file.host {
+            config.host = host
+                .parse()
+                .with_context(|| format!("invalid host: {}", host))?;
+        }
+        if let Some(port) = file.port {
+            config.port = port;
+        }
+        if let Some(pg_port) = file.pg_port {
+            config.pg_port = pg_port;
+        }
+        if let Some(log_level) = file.log_level {
+            config.set_log_level(&log_level)?;
+        }
+        Ok(config)
+    }
+    pub fn set_pool_size(&mut self, pool_size: usize) -> Result<()> {
+        if pool_size == 0 {
+            return Err(anyhow!("pool_size must be at least 1"));
+        }
+        self.pool_size = pool_size;
+        Ok(())
+    }
+    // In seconds.
+    pub fn set_session_timeout(&mut self, session_timeout: u64) -> Result<()> {
+        if session_timeout == 0 {
+            return Err(anyhow!("session_timeout must be at least 1"));
+        }
+        self.session_timeout = Duration::from_secs(session_timeout);
+        Ok(())
+    }
+    // One of off, error, warn, info, debug and trace.
+    pub fn set_log_level(&mut self, log_level: &str) -> Result<()> {
+        self.log_level = log_level
+            .parse()
+            .map_err(|_| anyhow!("unknown log level: {}", log_level))?;
+        Ok(())
+    }
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+    pub fn pg_address(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.pg_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::replacer::ReplacerPolicy;
+    use crate::config::Config;
+    use anyhow::Result;
+    use log::LevelFilter;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+    #[test]
+    fn from_toml() -> Result<()> {
+        let config = Config::from_toml(
+            r#"
+            data_dir = "data2/"
+            pool_size = 64
+            replacer = "lru-k"
+            checkpoint_log_size = 1048576
+            session_timeout = 60
+            port = 3306
+            pg_port = 5433
+            log_level = "debug"
+            "#,
+        )?;
+        assert_eq!(
+            config,
+            Config {
+                data_dir: "data2/".to_string(),
+                pool_size: 64,
+                replacer_policy: ReplacerPolicy::LruK(2),
+                checkpoint_log_size: 1 << 20,
+                session_timeout: Duration::from_secs(60),
+                port: 3306,
+                pg_port: 5433,
+                log_level: LevelFilter::Debug,
+                ..Config::default()
+            }
+        );
+        assert_eq!(config.address().to_string(), "127.0.0.1:3306");
+        assert_eq!(config.pg_address().to_string(), "127.0.0.1:5433");
+        assert_eq!(Config::from_toml("")?, Config::default());
+        for content in [
+            "pool_size = 0",
+            "pool_size = -1",
+            "checkpoint_log_size = -1",
+            "session_timeout = 0",
+            "replacer = \"random\"",
+            "host = \"localhost:3305\"",
+            "log_level = \"verbose\"",
+            "bind = \"0.0.0.0\"",
+        ] {
+            assert!(Config::from_toml(content).is_err(), "{}", content);
+        }
+        Ok(())
+    }
+    #[test]
+    fn load() -> Result<()> {
+        let dir = "tmp/load_config/";
+        fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join("toy_db.toml");
+        fs::write(&path, "host = \"0.0.0.0\"\n")?;
+        let config = Config::load(Some(&path))?;
+        assert_eq!(config.address().to_string(), "0.0.0.0:3305");
+        assert!(Config::load(Some(&Path::new(dir).join("missing.toml"))).is_err());
+        Ok(())
+    }
+}
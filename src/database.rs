@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Catalog, Column};
+use crate::execution::planner::plan_select;
+use crate::execution::{
+    execute_select, CreateIndexExecutor, CreateTableExecutor, DeleteExecutor, DropTableExecutor,
+    Executor, InsertExecutor, QueryResult, Returning, UpdateExecutor,
+};
+use crate::parser::token;
+use crate::parser::{Parser, Stmt};
+use crate::transaction::{Transaction, TransactionManager};
+use crate::wal::TxnId;
+
+// Runs the statements of the clients, whatever protocol they speak. BEGIN returns the id of a
+// new session, and the statements sent with it share its transaction. The others run in a
+// transaction of their own.
+#[derive(Debug)]
+pub struct Database {
+    catalog: Arc<Catalog>,
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    transaction_manager: Arc<TransactionManager>,
+}
+
+impl Database {
+    pub fn new(
+        catalog: Arc<Catalog>,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        transaction_manager: Arc<TransactionManager>,
+    ) -> Self {
+        Database {
+            catalog,
+            buffer_pool_manager,
+            transaction_manager,
+        }
+    }
+    pub fn execute_query(&self, query: &str, session_id: Option<&str>) -> Result<QueryResult> {
+        let tokens = token::tokenize(&mut query.chars().peekable())?;
+        let mut parser = Parser::new(tokens);
+        let stmt = parser.parse()?;
+        self.execute(stmt, session_id)
+    }
+    pub fn execute(&self, stmt: Stmt, session_id: Option<&str>) -> Result<QueryResult> {
+        let transaction_manager = &self.transaction_manager;
+        match stmt {
+            Stmt::BeginStmt(ast) => {
+                if let Some(session_id) = session_id {
+                    return match transaction_manager.session_txn(session_id) {
+                        Some(_) => Err(anyhow!("a transaction is already in progress")),
+                        None => Err(anyhow!("session {} does not exist", session_id)),
+                    };
+                }
+                return Ok(QueryResult {
+                    session_id: Some(transaction_manager.begin_session(ast.isolation_level)),
+                    ..Default::default()
+                });
+            }
+            Stmt::CommitStmt | Stmt::RollbackStmt => {
+                let txn_id = match session_id {
+                    Some(session_id) => transaction_manager.end_session(session_id)?,
+                    None => return Err(anyhow!("no transaction is in progress")),
+                };
+                if stmt == Stmt::CommitStmt {
+                    transaction_manager.commit(txn_id)?;
+                } else {
+                    transaction_manager.abort(txn_id)?;
+                }
+                return Ok(QueryResult::default());
+            }
+            _ => {}
+        }
+
+        // Inside a transaction, a failed statement is rolled back alone, unless the transaction
+        // was chosen to break a deadlock and must abort to release its locks.
+        if let Some(session_id) = session_id {
+            let txn_id = transaction_manager.enter_session(session_id)?;
+            let result = self.execute_in_session(stmt, txn_id);
+            transaction_manager.leave_session(session_id);
+            if result.is_err() && transaction_manager.is_deadlock_victim(txn_id) {
+                transaction_manager.end_session(session_id)?;
+                transaction_manager.abort(txn_id)?;
+                return result.context("the transaction has been rolled back");
+            }
+            return result;
+        }
+        // Otherwise the statement runs in a transaction of its own.
+        let txn_id = transaction_manager.begin();
+        let txn = transaction_manager.transaction(txn_id)?;
+        match self.execute_stmt(stmt, txn) {
+            Ok(result) => {
+                transaction_manager.commit(txn_id)?;
+                Ok(result)
+            }
+            Err(e) => {
+                transaction_manager.abort(txn_id)?;
+                Err(e)
+            }
+        }
+    }
+    fn execute_in_session(&self, stmt: Stmt, txn_id: TxnId) -> Result<QueryResult> {
+        let transaction_manager = &self.transaction_manager;
+        let savepoint = transaction_manager.savepoint(txn_id)?;
+        let txn = transaction_manager.transaction(txn_id)?;
+        let result = self.execute_stmt(stmt, txn);
+        if result.is_err() && !transaction_manager.is_deadlock_victim(txn_id) {
+            transaction_manager.rollback_to(txn_id, savepoint)?;
+        }
+        result
+    }
+    // Returns the columns of the rows the statement would return, or None if it returns no
+    // rows. The statement is planned but not run.
+    pub fn describe(&self, stmt: &Stmt, session_id: Option<&str>) -> Result<Option<Vec<Column>>> {
+        let ast = match stmt {
+            Stmt::SelectStmt(ast) => ast,
+            Stmt::InsertStmt(ast) => {
+                let select_list = match &ast.returning {
+                    Some(select_list) => select_list,
+                    None => return Ok(None),
+                };
+                let schema = match self.catalog.get_schema(&ast.table_name)? {
+                    Some(schema) => schema,
+                    None => return Err(anyhow!("Table({}) not found", ast.table_name)),
+                };
+                return Ok(Some(Returning::new(select_list, &schema)?.columns));
+            }
+            _ => return Ok(None),
+        };
+        let transaction_manager = &self.transaction_manager;
+        let txn_id = match session_id {
+            Some(session_id) => transaction_manager.enter_session(session_id)?,
+            None => transaction_manager.begin(),
+        };
+        let columns = transaction_manager.transaction(txn_id).and_then(|txn| {
+            let plan = plan_select(ast, &txn, &self.catalog, &self.buffer_pool_manager)?;
+            Ok(plan.schema().columns.clone())
+        });
+        match session_id {
+            Some(session_id) => transaction_manager.leave_session(session_id),
+            None => transaction_manager.commit(txn_id)?,
+        }
+        columns.map(Some)
+    }
+    // Returns the session whose transaction is open after the statement: the one BEGIN
+    // issued, or the one the statement was sent with unless the transaction has ended.
+    pub fn open_session(
+        &self,
+        result: &Result<QueryResult>,
+        session_id: Option<&str>,
+    ) -> Option<String> {
+        match result {
+            Ok(QueryResult {
+                session_id: Some(session_id),
+                ..
+            }) => Some(session_id.clone()),
+            _ => session_id
+                .filter(|session_id| self.in_transaction(session_id))
+                .map(|session_id| session_id.to_string()),
+        }
+    }
+    pub fn in_transaction(&self, session_id: &str) -> bool {
+        self.transaction_manager.session_txn(session_id).is_some()
+    }
+    // Aborts the transaction left open by a client which has gone away.
+    pub fn close_session(&self, session_id: &str) -> Result<()> {
+        if self.in_transaction(session_id) {
+            let txn_id = self.transaction_manager.end_session(session_id)?;
+            self.transaction_manager.abort(txn_id)?;
+        }
+        Ok(())
+    }
+    fn execute_stmt(&self, stmt: Stmt, txn: Transaction) -> Result<QueryResult> {
+        let catalog = self.catalog.clone();
+        let buffer_pool_manager = self.buffer_pool_manager.clone();
+        let result = match stmt {
+            Stmt::CreateTableStmt(_) => CreateTableExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::CreateIndexStmt(_) => CreateIndexExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::DropTableStmt(_) => DropTableExecutor { stmt, txn, catalog }.execute()?,
+            Stmt::InsertStmt(_) => InsertExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::SelectStmt(ast) => execute_select(&ast, &txn, &catalog, &buffer_pool_manager)?,
+            Stmt::DeleteStmt(_) => DeleteExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::UpdateStmt(_) => UpdateExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::BeginStmt(_) | Stmt::CommitStmt | Stmt::RollbackStmt => {
+                return Err(anyhow!("unexpected transaction statement"))
+            }
+        };
+        Ok(result)
+    }
+}
+
+// The SQLSTATE of an error, which the clients use to tell the ones worth a retry.
+pub fn sqlstate(e: &anyhow::Error) -> &'static str {
+    let message = format!("{:#}", e);
+    if message.contains("deadlock detected") {
+        "40P01"
+    } else if message.contains("could not serialize access") {
+        "40001"
+    } else if message.contains("violates unique") {
+        "23505"
+    } else if message.contains("violates NOT NULL") {
+        "23502"
+    } else if message.contains("cannot affect a row written by the same statement") {
+        "21000"
+    } else if message.contains("no unique index matches") {
+        "42P10"
+    } else if message.contains("is of type") {
+        "42804"
+    } else if message.contains("invalid query")
+        || message.contains("invalid token")
+        || message.contains("invalid parameter")
+        || message.contains("values are given")
+    {
+        "42601"
+    } else {
+        "XX000"
+    }
+}
@@ -1,29 +1,52 @@
-use std::fs::{File, OpenOptions};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use anyhow::Result;
 
 pub const PAGE_SIZE: usize = 4096;
 pub const DATAFILE_NAME: &str = "data";
+// Block numbers of the freed pages, 4 bytes each, next to the data file.
+pub const FREE_PAGES_FILE_NAME: &str = "free_pages";
+pub const LOG_FILE_NAME: &str = "wal";
+// Block numbers of the pages which hold the runs of the running sorts, 4 bytes each. The
+// runs are not logged, so the pages left by a crash are freed from this list.
+pub const SPILL_PAGES_FILE_NAME: &str = "spill_pages";
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(Debug)]
 pub struct DiskManager {
-    home_dir: String,
     datafile_path_buf: PathBuf,
+    free_pages_path_buf: PathBuf,
+    spill_pages_path_buf: PathBuf,
+    log_path_buf: PathBuf,
+    // Held while a page is allocated or freed, since both read and then change the free
+    // page list or the size of the data file.
+    allocation_latch: Mutex<()>,
 }
 
 impl DiskManager {
     pub fn new(home_dir: String) -> Self {
         let datafile_path_buf = Path::new(&home_dir).join(DATAFILE_NAME);
+        let free_pages_path_buf = Path::new(&home_dir).join(FREE_PAGES_FILE_NAME);
+        let spill_pages_path_buf = Path::new(&home_dir).join(SPILL_PAGES_FILE_NAME);
+        let log_path_buf = Path::new(&home_dir).join(LOG_FILE_NAME);
         Self {
-            home_dir,
             datafile_path_buf,
+            free_pages_path_buf,
+            spill_pages_path_buf,
+            log_path_buf,
+            allocation_latch: Mutex::new(()),
         }
     }
     pub fn init_data_file(&self) -> Result<()> {
         let datafile_path = self.datafile_path_buf.as_path();
         File::create(datafile_path)?;
+        File::create(self.free_pages_path_buf.as_path())?;
+        File::create(self.spill_pages_path_buf.as_path())?;
+        File::create(self.log_path_buf.as_path())?;
         Ok(())
     }
     pub fn write_page(&self, block_number: usize, data: &[u8]) -> Result<()> {
@@ -34,10 +57,25 @@ impl DiskManager {
         Ok(())
     }
 
+    // Reuses the most recently freed page if any, otherwise extends the data file.
     pub fn write_new_page(&self, data: &[u8]) -> Result<usize> {
+        let _latch = self.allocation_latch.lock().unwrap();
+        self.allocate_page(data)
+    }
+    // Allocates a page like `write_new_page` and adds it to the spill page list.
+    pub fn write_new_spill_page(&self, data: &[u8]) -> Result<usize> {
+        let _latch = self.allocation_latch.lock().unwrap();
+        let block_number = self.allocate_page(data)?;
+        append_block_number(&self.spill_pages_path_buf, block_number)?;
+        Ok(block_number)
+    }
+    fn allocate_page(&self, data: &[u8]) -> Result<usize> {
+        if let Some(block_number) = self.pop_free_page()? {
+            self.write_page(block_number, data)?;
+            return Ok(block_number);
+        }
         let datafile_path = self.datafile_path_buf.as_path();
         let metadata = datafile_path.metadata()?;
-        // TODO: get block number safely.
         let block_number = (metadata.len() / PAGE_SIZE as u64) as usize;
         self.write_page(block_number, data)?;
         Ok(block_number)
@@ -51,6 +89,150 @@ impl DiskManager {
         file.read_exact(&mut buf)?;
         Ok(buf)
     }
+
+    pub fn sync_data_file(&self) -> Result<()> {
+        File::open(self.datafile_path_buf.as_path())?.sync_all()?;
+        Ok(())
+    }
+
+    // Appends to the log file and waits until the data reaches the disk.
+    pub fn append_log(&self, data: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path_buf.as_path())?;
+        file.write_all(data)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn read_log(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut file = File::open(self.log_path_buf.as_path())?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0u8; len];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn log_size(&self) -> Result<u64> {
+        let log_path = self.log_path_buf.as_path();
+        if !log_path.exists() {
+            return Ok(0);
+        }
+        Ok(log_path.metadata()?.len())
+    }
+
+    pub fn truncate_log(&self, len: u64) -> Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(self.log_path_buf.as_path())?;
+        file.set_len(len)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    // Replaces the log with `header`. The header is overwritten before the records are cut
+    // off, so a crash in between leaves the old records behind the new header.
+    pub fn reset_log(&self, header: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.log_path_buf.as_path())?;
+        file.write_all(header)?;
+        file.sync_data()?;
+        file.set_len(header.len() as u64)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    // The list is synced, so that a freed page is not lost nor handed out twice after a crash.
+    pub fn free_page(&self, block_number: usize) -> Result<()> {
+        let _latch = self.allocation_latch.lock().unwrap();
+        append_block_number(&self.free_pages_path_buf, block_number)
+    }
+    // Moves the page from the spill page list to the free page list. A crash in between
+    // leaves the page in both lists, which `free_spill_pages` allows for.
+    pub fn free_spill_page(&self, block_number: usize) -> Result<()> {
+        let _latch = self.allocation_latch.lock().unwrap();
+        append_block_number(&self.free_pages_path_buf, block_number)?;
+        let mut block_numbers = read_block_numbers(&self.spill_pages_path_buf)?;
+        block_numbers.retain(|&spill_block_number| spill_block_number != block_number);
+        write_block_numbers(&self.spill_pages_path_buf, &block_numbers)
+    }
+    pub fn spill_page_block_numbers(&self) -> Result<Vec<usize>> {
+        read_block_numbers(&self.spill_pages_path_buf)
+    }
+    // Frees every page of the spill page list, which the sorts cut off by a crash have left.
+    pub fn free_spill_pages(&self) -> Result<()> {
+        let _latch = self.allocation_latch.lock().unwrap();
+        let free_block_numbers = read_block_numbers(&self.free_pages_path_buf)?;
+        for block_number in read_block_numbers(&self.spill_pages_path_buf)? {
+            if !free_block_numbers.contains(&block_number) {
+                append_block_number(&self.free_pages_path_buf, block_number)?;
+            }
+        }
+        write_block_numbers(&self.spill_pages_path_buf, &[])
+    }
+
+    pub fn free_page_count(&self) -> Result<usize> {
+        let free_pages_path = self.free_pages_path_buf.as_path();
+        if !free_pages_path.exists() {
+            return Ok(0);
+        }
+        Ok(free_pages_path.metadata()?.len() as usize / mem::size_of::<u32>())
+    }
+
+    fn pop_free_page(&self) -> Result<Option<usize>> {
+        let size = mem::size_of::<u32>() as u64;
+        let free_pages_path = self.free_pages_path_buf.as_path();
+        // Data files created before the free page list have no such file.
+        if !free_pages_path.exists() {
+            return Ok(None);
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(free_pages_path)?;
+        let len = file.metadata()?.len();
+        if len < size {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(len - size))?;
+        let mut buf = [0u8; mem::size_of::<u32>()];
+        file.read_exact(&mut buf)?;
+        file.set_len(len - size)?;
+        file.sync_data()?;
+        Ok(Some(u32::from_be_bytes(buf) as usize))
+    }
+}
+
+// The lists of block numbers are synced, so that they survive a crash.
+fn append_block_number(path: &Path, block_number: usize) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&(block_number as u32).to_be_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+fn write_block_numbers(path: &Path, block_numbers: &[usize]) -> Result<()> {
+    let mut file = File::create(path)?;
+    for &block_number in block_numbers {
+        file.write_all(&(block_number as u32).to_be_bytes())?;
+    }
+    file.sync_data()?;
+    Ok(())
+}
+
+// Data files created before a list have no file for it.
+fn read_block_numbers(path: &Path) -> Result<Vec<usize>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read(path)?
+        .chunks_exact(mem::size_of::<u32>())
+        .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()) as usize)
+        .collect())
 }
 
 #[cfg(test)]
@@ -61,6 +243,8 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
     use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
     #[test]
     fn init_data_file_create() -> Result<()> {
         let disk_manager = DiskManager::new("tmp/".to_string());
@@ -98,4 +282,57 @@ mod tests {
         assert_eq!(data, vec![66u8; PAGE_SIZE]);
         Ok(())
     }
+    #[test]
+    fn reuse_free_pages() -> Result<()> {
+        let dir = "tmp/reuse_free_pages/";
+        fs::create_dir_all(dir)?;
+        let disk_manager = DiskManager::new(dir.to_string());
+        disk_manager.init_data_file()?;
+        for i in 0..3 {
+            assert_eq!(disk_manager.write_new_page(&[i as u8; PAGE_SIZE])?, i);
+        }
+        disk_manager.free_page(0)?;
+        disk_manager.free_page(2)?;
+        assert_eq!(disk_manager.free_page_count()?, 2);
+        // The free page list survives a restart.
+        let disk_manager = DiskManager::new(dir.to_string());
+        assert_eq!(disk_manager.write_new_page(&[68u8; PAGE_SIZE])?, 2);
+        assert_eq!(disk_manager.write_new_page(&[69u8; PAGE_SIZE])?, 0);
+        assert_eq!(disk_manager.write_new_page(&[70u8; PAGE_SIZE])?, 3);
+        assert_eq!(disk_manager.free_page_count()?, 0);
+        assert_eq!(disk_manager.read_page(0)?, vec![69u8; PAGE_SIZE]);
+        Ok(())
+    }
+    #[test]
+    fn allocate_pages_concurrently() -> Result<()> {
+        let dir = "tmp/allocate_pages_concurrently/";
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        for i in 0..20 {
+            disk_manager.write_new_page(&[i as u8; PAGE_SIZE])?;
+        }
+        for i in 0..10 {
+            disk_manager.free_page(i)?;
+        }
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let disk_manager = disk_manager.clone();
+                thread::spawn(move || -> Result<Vec<usize>> {
+                    (0..10)
+                        .map(|_| disk_manager.write_new_page(&[0u8; PAGE_SIZE]))
+                        .collect()
+                })
+            })
+            .collect();
+        let mut block_numbers = Vec::new();
+        for handle in handles {
+            block_numbers.append(&mut handle.join().unwrap()?);
+        }
+        // Every freed page is handed out once and the rest extend the data file.
+        block_numbers.sort_unstable();
+        assert_eq!(block_numbers, (0..10).chain(20..50).collect::<Vec<_>>());
+        assert_eq!(disk_manager.free_page_count()?, 0);
+        Ok(())
+    }
 }
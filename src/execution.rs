@@ -1,116 +1,1562 @@
+pub mod aggregation;
+pub mod expression;
+pub mod join;
+pub mod operator;
+pub mod planner;
+pub mod sort;
+
 use std::sync::Arc;
 
 use anyhow::Result;
+use serde_json::json;
 
+use self::aggregation::contains_aggregate;
+use self::expression::{
+    check_column_references, evaluate, evaluate_constant, evaluate_predicate, infer_type,
+};
+use self::planner::{plan_index_range, plan_select, plan_select_list};
 use crate::buffer::BufferPoolManager;
-use crate::catalog::{Catalog, Column, Schema};
-use crate::parser::Stmt;
+use crate::catalog::{Catalog, Column, ColumnConstraints, IndexInfo, Schema};
+use crate::lock::LockMode;
+use crate::parser::{ast, Stmt};
+use crate::storage::btree::BTree;
 use crate::storage::table::Table;
-use crate::storage::tuple::Tuple;
+use crate::storage::tuple::{Tuple, TupleId};
+use crate::transaction::Transaction;
+use crate::value::Value;
+use crate::wal::TxnId;
 
 pub trait Executor {
-    fn execute(&self) -> Result<String>;
+    fn execute(&self) -> Result<QueryResult>;
+}
+
+// `columns` and `rows` are only filled by statements that return tuples,
+// `rows_affected` only by statements that modify them, and `session_id` only by BEGIN.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryResult {
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Value>>,
+    pub rows_affected: Option<usize>,
+    pub session_id: Option<String>,
+}
+
+impl QueryResult {
+    pub fn rows_affected(rows_affected: usize) -> Self {
+        QueryResult {
+            rows_affected: Some(rows_affected),
+            ..Default::default()
+        }
+    }
+    pub fn to_json(&self) -> serde_json::Value {
+        let columns: Vec<serde_json::Value> = self
+            .columns
+            .iter()
+            .map(|column| {
+                json!({
+                    "name": column.name,
+                    "type": column.column_type.to_string(),
+                })
+            })
+            .collect();
+        let rows: Vec<Vec<serde_json::Value>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|value| value.to_json()).collect())
+            .collect();
+        json!({
+            "columns": columns,
+            "rows": rows,
+            "rows_affected": self.rows_affected,
+        })
+    }
+}
+
+// Runs the query to the end.
+pub fn execute_select(
+    ast: &ast::SelectStmtAst,
+    txn: &Transaction,
+    catalog: &Catalog,
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+) -> Result<QueryResult> {
+    let mut plan = plan_select(ast, txn, catalog, buffer_pool_manager)?;
+    plan.init()?;
+    let mut rows = Vec::new();
+    while let Some(tuple) = plan.next()? {
+        rows.push(tuple.values);
+    }
+    Ok(QueryResult {
+        columns: plan.schema().columns.clone(),
+        rows,
+        ..Default::default()
+    })
 }
 
 #[derive(Debug)]
 pub struct CreateTableExecutor {
     pub stmt: Stmt,
+    pub txn: Transaction,
     pub catalog: Arc<Catalog>,
+    pub buffer_pool_manager: Arc<BufferPoolManager>,
 }
 
 impl Executor for CreateTableExecutor {
-    fn execute(&self) -> Result<String> {
+    fn execute(&self) -> Result<QueryResult> {
         if let Stmt::CreateTableStmt(ast) = &self.stmt {
             if self
                 .catalog
                 .get_first_block_number(&ast.table_name)?
                 .is_some()
             {
-                return Err(anyhow!("Table({}) exists\n", ast.table_name));
+                return Err(anyhow!("Table({}) exists", ast.table_name));
+            } else if let Some(query) = &ast.query {
+                // The columns are named and typed after those of the query, whose rows fill
+                // the table.
+                let mut plan =
+                    plan_select(query, &self.txn, &self.catalog, &self.buffer_pool_manager)?;
+                let schema = plan.schema().clone();
+                self.catalog
+                    .create_table(self.txn.txn_id, &ast.table_name, &schema)?;
+                let first_block_number =
+                    match self.catalog.get_first_block_number(&ast.table_name)? {
+                        Some(first_block_number) => first_block_number,
+                        None => return Err(anyhow!("Table({}) not found", ast.table_name)),
+                    };
+                plan.init()?;
+                let mut tuples = Vec::new();
+                while let Some(tuple) = plan.next()? {
+                    tuples.push(tuple);
+                }
+                let table = Table::new(&self.buffer_pool_manager, &schema, first_block_number);
+                self.txn.lock_table_for_write(first_block_number, false)?;
+                for tuple in tuples.iter() {
+                    let tuple_id = table.insert_tuple(self.txn.txn_id, tuple.clone())?;
+                    self.txn.lock_row(tuple_id)?;
+                }
+                return Ok(QueryResult::rows_affected(tuples.len()));
             } else {
-                self.catalog.create_table(
+                let schema = Schema {
+                    columns: ast
+                        .table_element_list
+                        .iter()
+                        .map(|table_element| Column {
+                            name: table_element.column_name.clone(),
+                            column_type: table_element.column_type.clone(),
+                        })
+                        .collect::<Vec<Column>>(),
+                };
+                let mut constraints = Vec::new();
+                for (table_element, column) in
+                    ast.table_element_list.iter().zip(schema.columns.iter())
+                {
+                    // The default is computed once, since it refers to no column.
+                    let default = match &table_element.default {
+                        Some(expr) => Some(cast_to_column(&evaluate_constant(expr)?, column)?),
+                        None => None,
+                    };
+                    constraints.push(ColumnConstraints {
+                        not_null: table_element.not_null,
+                        default,
+                    });
+                }
+                let mut primary_keys = ast.constraints.iter().filter(|c| c.primary_key);
+                if let Some(primary_key) = primary_keys.next() {
+                    if primary_keys.next().is_some() {
+                        return Err(anyhow!(
+                            "multiple primary keys for Table({}) are not allowed",
+                            ast.table_name
+                        ));
+                    }
+                    // The columns of the primary key are NOT NULL.
+                    for name in primary_key.column_names.iter() {
+                        match schema.column_index(name) {
+                            Some(i) => constraints[i].not_null = true,
+                            None => return Err(anyhow!("Column({}) not found", name)),
+                        }
+                    }
+                }
+                self.catalog.create_table_with_constraints(
+                    self.txn.txn_id,
                     &ast.table_name,
-                    &Schema {
-                        columns: ast
-                            .table_element_list
-                            .iter()
-                            .map(|table_element| Column {
-                                name: table_element.column_name.clone(),
-                                column_type: table_element.column_type.clone(),
-                            })
-                            .collect::<Vec<Column>>(),
-                    },
+                    &schema,
+                    &constraints,
                 )?;
+                // PRIMARY KEY and UNIQUE are enforced by unique indexes, which need not be
+                // filled since the table is empty.
+                for constraint in ast.constraints.iter() {
+                    let index_name = match &constraint.name {
+                        Some(name) => name.clone(),
+                        None => constraint_index_name(&self.catalog, &ast.table_name, constraint)?,
+                    };
+                    self.catalog.create_index(
+                        self.txn.txn_id,
+                        &index_name,
+                        &ast.table_name,
+                        &constraint.column_names,
+                        true,
+                    )?;
+                }
+            }
+        }
+        Ok(QueryResult::default())
+    }
+}
+
+// Names the index of a constraint as PostgreSQL does, `<table>_pkey` for the primary key and
+// `<table>_<columns>_key` otherwise, with a number added if the name is taken.
+fn constraint_index_name(
+    catalog: &Catalog,
+    table_name: &str,
+    constraint: &ast::TableConstraintAst,
+) -> Result<String> {
+    let base_name = if constraint.primary_key {
+        format!("{}_pkey", table_name)
+    } else {
+        format!("{}_{}_key", table_name, constraint.column_names.join("_"))
+    };
+    let mut index_name = base_name.clone();
+    let mut n = 0;
+    while catalog.index_exists(&index_name)? {
+        n += 1;
+        index_name = format!("{}{}", base_name, n);
+    }
+    Ok(index_name)
+}
+
+// Converts a value for the column, so that no tuple which does not fit its schema is written.
+fn cast_to_column(value: &Value, column: &Column) -> Result<Value> {
+    match value.cast(&column.column_type) {
+        Some(value) => Ok(value),
+        None => Err(anyhow!(
+            "Column({}) is of type {} but got {:?}",
+            column.name,
+            column.column_type,
+            value
+        )),
+    }
+}
+
+// Fails if the tuple has NULL in a NOT NULL column.
+fn check_not_null(schema: &Schema, constraints: &[ColumnConstraints], tuple: &Tuple) -> Result<()> {
+    for ((column, constraints), value) in schema
+        .columns
+        .iter()
+        .zip(constraints)
+        .zip(tuple.values.iter())
+    {
+        if constraints.not_null && *value == Value::Null {
+            return Err(anyhow!(
+                "NULL violates NOT NULL constraint of Column({})",
+                column.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct CreateIndexExecutor {
+    pub stmt: Stmt,
+    pub txn: Transaction,
+    pub catalog: Arc<Catalog>,
+    pub buffer_pool_manager: Arc<BufferPoolManager>,
+}
+
+impl Executor for CreateIndexExecutor {
+    fn execute(&self) -> Result<QueryResult> {
+        if let Stmt::CreateIndexStmt(ast) = &self.stmt {
+            let schema = match self.catalog.get_schema(&ast.table_name)? {
+                Some(schema) => schema,
+                None => return Err(anyhow!("Table({}) not found", ast.table_name)),
+            };
+            let first_block_number = match self.catalog.get_first_block_number(&ast.table_name)? {
+                Some(first_block_number) => first_block_number,
+                None => return Err(anyhow!("Table({}) not found", ast.table_name)),
+            };
+            // The rows must not change while the index is filled.
+            self.txn.lock_table(first_block_number, LockMode::Shared)?;
+            let index = self.catalog.create_index(
+                self.txn.txn_id,
+                &ast.index_name,
+                &ast.table_name,
+                &ast.column_names,
+                ast.unique,
+            )?;
+            let key_schema = index.key_schema(&schema)?;
+            let indexes = TableIndexes {
+                buffer_pool_manager: &self.buffer_pool_manager,
+                txn_id: self.txn.txn_id,
+                schema: &schema,
+                indexes: vec![(index, key_schema)],
+            };
+            let table = Table::new(&self.buffer_pool_manager, &schema, first_block_number);
+            if let Err(e) = indexes.fill(&table) {
+                self.catalog.drop_index(self.txn.txn_id, &ast.index_name)?;
+                return Err(e);
             }
         }
-        Ok("Query OK\n".to_string())
+        Ok(QueryResult::default())
+    }
+}
+
+#[derive(Debug)]
+pub struct DropTableExecutor {
+    pub stmt: Stmt,
+    pub txn: Transaction,
+    pub catalog: Arc<Catalog>,
+}
+
+impl Executor for DropTableExecutor {
+    fn execute(&self) -> Result<QueryResult> {
+        if let Stmt::DropTableStmt(ast) = &self.stmt {
+            let first_block_number = match self.catalog.get_first_block_number(&ast.table_name)? {
+                Some(first_block_number) => first_block_number,
+                None if ast.if_exists => return Ok(QueryResult::default()),
+                None => return Err(anyhow!("Table({}) not found", ast.table_name)),
+            };
+            self.txn
+                .lock_table(first_block_number, LockMode::Exclusive)?;
+            self.catalog.drop_table(self.txn.txn_id, &ast.table_name)?;
+        }
+        Ok(QueryResult::default())
     }
 }
 
 #[derive(Debug)]
 pub struct InsertExecutor {
     pub stmt: Stmt,
+    pub txn: Transaction,
     pub catalog: Arc<Catalog>,
     pub buffer_pool_manager: Arc<BufferPoolManager>,
 }
 
 impl Executor for InsertExecutor {
-    fn execute(&self) -> Result<String> {
+    fn execute(&self) -> Result<QueryResult> {
         if let Stmt::InsertStmt(ast) = &self.stmt {
             if let Some(schema) = self.catalog.get_schema(&ast.table_name)? {
                 if let Some(first_block_number) =
                     self.catalog.get_first_block_number(&ast.table_name)?
                 {
                     let table = Table::new(&self.buffer_pool_manager, &schema, first_block_number);
-                    table.insert_tuple(Tuple {
-                        values: ast.values.clone(),
-                    })?;
+                    let indexes = TableIndexes::new(
+                        &self.catalog,
+                        &self.buffer_pool_manager,
+                        self.txn.txn_id,
+                        &ast.table_name,
+                        &schema,
+                    )?;
+                    let constraints = self.catalog.get_column_constraints(&ast.table_name)?;
+                    // the positions of the columns the values are for
+                    let targets: Vec<usize> = match &ast.column_names {
+                        Some(column_names) => {
+                            let mut targets = Vec::new();
+                            for name in column_names.iter() {
+                                let i = match schema.column_index(name) {
+                                    Some(i) => i,
+                                    None => return Err(anyhow!("Column({}) not found", name)),
+                                };
+                                if targets.contains(&i) {
+                                    return Err(anyhow!(
+                                        "Column({}) specified more than once",
+                                        name
+                                    ));
+                                }
+                                targets.push(i);
+                            }
+                            targets
+                        }
+                        None => (0..schema.columns.len()).collect(),
+                    };
+                    let on_conflict = match &ast.on_conflict {
+                        Some(on_conflict) => Some(OnConflict::new(
+                            on_conflict,
+                            &schema,
+                            &constraints,
+                            &indexes,
+                        )?),
+                        None => None,
+                    };
+                    let returning = match &ast.returning {
+                        Some(select_list) => Some(Returning::new(select_list, &schema)?),
+                        None => None,
+                    };
+                    // None for DEFAULT
+                    let rows: Vec<Vec<Option<Value>>> = match &ast.source {
+                        ast::InsertSourceAst::Values(rows) => {
+                            let mut values = Vec::new();
+                            for row in rows.iter() {
+                                values.push(
+                                    row.iter()
+                                        .map(|value| {
+                                            value.as_ref().map(evaluate_constant).transpose()
+                                        })
+                                        .collect::<Result<Vec<_>>>()?,
+                                );
+                            }
+                            values
+                        }
+                        // The query is run to the end first, so that it does not see the rows
+                        // inserted into the table it reads.
+                        ast::InsertSourceAst::Select(query) => execute_select(
+                            query,
+                            &self.txn,
+                            &self.catalog,
+                            &self.buffer_pool_manager,
+                        )?
+                        .rows
+                        .into_iter()
+                        .map(|row| row.into_iter().map(Some).collect())
+                        .collect(),
+                    };
+                    // Every row is checked before any is written.
+                    let mut tuples = Vec::new();
+                    for row in rows.iter() {
+                        if row.len() != targets.len() {
+                            return Err(match ast.column_names {
+                                Some(_) => anyhow!(
+                                    "{} columns are listed but {} values are given",
+                                    targets.len(),
+                                    row.len()
+                                ),
+                                None => anyhow!(
+                                    "Table({}) has {} columns but {} values are given",
+                                    ast.table_name,
+                                    targets.len(),
+                                    row.len()
+                                ),
+                            });
+                        }
+                        // The columns left out and those given DEFAULT get their default.
+                        let mut values: Vec<Value> = constraints
+                            .iter()
+                            .map(|constraints| constraints.default.clone().unwrap_or(Value::Null))
+                            .collect();
+                        for (&i, value) in targets.iter().zip(row.iter()) {
+                            if let Some(value) = value {
+                                values[i] = cast_to_column(value, &schema.columns[i])?;
+                            }
+                        }
+                        let tuple = Tuple { values };
+                        check_not_null(&schema, &constraints, &tuple)?;
+                        tuples.push(tuple);
+                    }
+                    // Resolving a conflict reads the existing row.
+                    self.txn
+                        .lock_table_for_write(first_block_number, on_conflict.is_some())?;
+                    let mut written = Vec::new();
+                    let mut rows = Vec::new();
+                    for tuple in tuples.into_iter() {
+                        if let Some(tuple) = self.insert_tuple(
+                            &table,
+                            &indexes,
+                            tuple,
+                            on_conflict.as_ref(),
+                            &mut written,
+                        )? {
+                            if let Some(returning) = &returning {
+                                rows.push(returning.evaluate(&tuple, &schema)?);
+                            }
+                        }
+                    }
+                    let mut result = QueryResult::rows_affected(written.len());
+                    if let Some(returning) = returning {
+                        result.columns = returning.columns;
+                        result.rows = rows;
+                    }
+                    return Ok(result);
                 } else {
-                    return Err(anyhow!("Table({}) not found\n", ast.table_name));
+                    return Err(anyhow!("Table({}) not found", ast.table_name));
                 }
             } else {
-                return Err(anyhow!("Table({}) not found\n", ast.table_name));
+                return Err(anyhow!("Table({}) not found", ast.table_name));
+            }
+        }
+        Err(anyhow!("not an INSERT statement"))
+    }
+}
+
+impl InsertExecutor {
+    // Inserts the tuple, or resolves its conflict in an arbiter index as told by ON CONFLICT.
+    // Returns the row written, if any, and adds its id to `written`.
+    //
+    // The tuple is inserted before its key is checked, so that the check and the insert are
+    // atomic under the latch of the index. If the key is taken, the tuple is deleted again and
+    // the conflicting row is resolved instead.
+    fn insert_tuple(
+        &self,
+        table: &Table,
+        indexes: &TableIndexes,
+        tuple: Tuple,
+        on_conflict: Option<&OnConflict>,
+        written: &mut Vec<TupleId>,
+    ) -> Result<Option<Tuple>> {
+        let arbiters = on_conflict.map_or(&[][..], |on_conflict| &on_conflict.arbiters[..]);
+        loop {
+            let tuple_id = table.insert_tuple(self.txn.txn_id, tuple.clone())?;
+            self.txn.lock_row(tuple_id)?;
+            let conflict_id =
+                match indexes.insert_with_arbiters(table, &tuple, tuple_id, arbiters)? {
+                    Some(conflict_id) => conflict_id,
+                    None => {
+                        written.push(tuple_id);
+                        return Ok(Some(tuple));
+                    }
+                };
+            table.delete_tuple(self.txn.txn_id, &tuple_id)?;
+            // Waits for the transaction which has written the conflicting row to end.
+            self.txn.lock_row(conflict_id)?;
+            if !table.is_live(self.txn.txn_id, &conflict_id)? {
+                // The row has been rolled back or deleted since, so the key may be free.
+                continue;
+            }
+            // Only the arbiter indexes report conflicts, and there are none without ON CONFLICT.
+            let on_conflict = on_conflict.unwrap();
+            if written.contains(&conflict_id) {
+                // DO UPDATE must not change a row twice.
+                return match on_conflict.assignments {
+                    Some(_) => Err(anyhow!(
+                        "ON CONFLICT DO UPDATE cannot affect a row written by the same statement"
+                    )),
+                    None => Ok(None),
+                };
+            }
+            // The row must be visible to the transaction, whatever is done with it.
+            let existing = match table.get_tuple(&self.txn.snapshot, &conflict_id)? {
+                Some(existing) => existing,
+                None => {
+                    return Err(anyhow!(
+                        "could not serialize access due to a concurrent insert"
+                    ))
+                }
+            };
+            let assignments = match &on_conflict.assignments {
+                Some(assignments) => assignments,
+                None => return Ok(None),
+            };
+            let new_tuple = on_conflict.update(assignments, &existing, &tuple)?;
+            let new_tuple_id =
+                table.update_tuple(self.txn.txn_id, &conflict_id, new_tuple.clone())?;
+            self.txn.lock_row(new_tuple_id)?;
+            indexes.insert(table, &new_tuple, new_tuple_id)?;
+            written.push(new_tuple_id);
+            return Ok(Some(new_tuple));
+        }
+    }
+}
+
+// ON CONFLICT resolved against the table.
+struct OnConflict<'a> {
+    // the positions in TableIndexes of the indexes whose conflicts are resolved
+    arbiters: Vec<usize>,
+    // DO UPDATE SET, or None for DO NOTHING
+    assignments: Option<Vec<(usize, &'a ast::ExprAst)>>,
+    schema: &'a Schema,
+    constraints: &'a [ColumnConstraints],
+    // The columns of the existing row followed by those of the proposed one, which are
+    // named `excluded.<column name>`.
+    update_schema: Schema,
+}
+
+impl<'a> OnConflict<'a> {
+    fn new(
+        ast: &'a ast::OnConflictAst,
+        schema: &'a Schema,
+        constraints: &'a [ColumnConstraints],
+        indexes: &TableIndexes,
+    ) -> Result<Self> {
+        if let Some(column_names) = &ast.column_names {
+            for name in column_names.iter() {
+                if schema.column_index(name).is_none() {
+                    return Err(anyhow!("Column({}) not found", name));
+                }
+            }
+        }
+        let arbiters = indexes.arbiters(ast.column_names.as_deref());
+        if let (Some(column_names), true) = (&ast.column_names, arbiters.is_empty()) {
+            return Err(anyhow!(
+                "no unique index matches ON CONFLICT ({})",
+                column_names.join(", ")
+            ));
+        }
+        let mut update_schema = schema.clone();
+        for column in schema.columns.iter() {
+            update_schema.columns.push(Column {
+                name: format!("excluded.{}", column.name),
+                column_type: column.column_type.clone(),
+            });
+        }
+        let assignments = match &ast.action {
+            ast::ConflictActionAst::Nothing => None,
+            ast::ConflictActionAst::Update(assignment_list) => {
+                let mut assignments = Vec::new();
+                for assignment in assignment_list.iter() {
+                    let index = match schema.column_index(&assignment.column_name) {
+                        Some(index) => index,
+                        None => {
+                            return Err(anyhow!("Column({}) not found", assignment.column_name))
+                        }
+                    };
+                    if assignments.iter().any(|(i, _)| *i == index) {
+                        return Err(anyhow!(
+                            "Column({}) assigned more than once",
+                            assignment.column_name
+                        ));
+                    }
+                    check_column_references(&assignment.expr, &update_schema)?;
+                    assignments.push((index, &assignment.expr));
+                }
+                Some(assignments)
+            }
+        };
+        Ok(OnConflict {
+            arbiters,
+            assignments,
+            schema,
+            constraints,
+            update_schema,
+        })
+    }
+    // The new version of the existing row, given the row proposed for insertion.
+    fn update(
+        &self,
+        assignments: &[(usize, &ast::ExprAst)],
+        existing: &Tuple,
+        proposed: &Tuple,
+    ) -> Result<Tuple> {
+        let mut values = existing.values.clone();
+        values.extend(proposed.values.iter().cloned());
+        let rows = Tuple { values };
+        let mut new_tuple = existing.clone();
+        for (index, expr) in assignments.iter() {
+            let value = evaluate(expr, &rows, &self.update_schema)?;
+            new_tuple.values[*index] = cast_to_column(&value, &self.schema.columns[*index])?;
+        }
+        check_not_null(self.schema, self.constraints, &new_tuple)?;
+        Ok(new_tuple)
+    }
+}
+
+// The select list of RETURNING, which is evaluated against each row written.
+#[derive(Debug)]
+pub struct Returning {
+    exprs: Vec<ast::ExprAst>,
+    pub columns: Vec<Column>,
+}
+
+impl Returning {
+    pub fn new(select_list: &[ast::SelectItemAst], schema: &Schema) -> Result<Self> {
+        let (exprs, names) = plan_select_list(select_list, schema)?;
+        let mut columns = Vec::new();
+        for (expr, name) in exprs.iter().zip(names) {
+            if contains_aggregate(expr) {
+                return Err(anyhow!("aggregate functions are not allowed in RETURNING"));
             }
+            columns.push(Column {
+                name,
+                column_type: infer_type(expr, schema)?,
+            });
         }
-        Ok("Query OK\n".to_string())
+        Ok(Returning { exprs, columns })
+    }
+    fn evaluate(&self, tuple: &Tuple, schema: &Schema) -> Result<Vec<Value>> {
+        self.exprs
+            .iter()
+            .map(|expr| evaluate(expr, tuple, schema))
+            .collect()
     }
 }
 
 #[derive(Debug)]
-pub struct SelectExecutor {
+pub struct DeleteExecutor {
     pub stmt: Stmt,
+    pub txn: Transaction,
     pub catalog: Arc<Catalog>,
     pub buffer_pool_manager: Arc<BufferPoolManager>,
 }
 
-impl Executor for SelectExecutor {
-    fn execute(&self) -> Result<String> {
-        let mut res = String::new();
-        if let Stmt::SelectStmt(ast) = &self.stmt {
+impl Executor for DeleteExecutor {
+    fn execute(&self) -> Result<QueryResult> {
+        let mut deleted = 0;
+        if let Stmt::DeleteStmt(ast) = &self.stmt {
             if let Some(schema) = self.catalog.get_schema(&ast.table_name)? {
                 if let Some(first_block_number) =
                     self.catalog.get_first_block_number(&ast.table_name)?
                 {
+                    if let Some(where_clause) = &ast.where_clause {
+                        check_column_references(where_clause, &schema)?;
+                    }
+                    self.txn.lock_table_for_write(first_block_number, true)?;
                     let table = Table::new(&self.buffer_pool_manager, &schema, first_block_number);
-                    for page in table {
-                        for tuple in page.tuples.iter() {
-                            for (i, _) in schema.columns.iter().enumerate() {
-                                if i == 0 {
-                                    res = format!("{}{}", res, &tuple.values[i]);
-                                } else {
-                                    res = format!("{}, {}", res, &tuple.values[i]);
-                                }
+                    // Collect the targets first so that the scan does not observe its own deletes.
+                    // The index entries stay for the snapshots which still see the tuples.
+                    let targets = scan_targets(
+                        &ast.table_name,
+                        &table,
+                        &schema,
+                        ast.where_clause.as_ref(),
+                        &self.txn,
+                        &self.catalog,
+                        &self.buffer_pool_manager,
+                    )?;
+                    for (tuple_id, _) in targets.iter() {
+                        self.txn.lock_row(*tuple_id)?;
+                        table.delete_tuple(self.txn.txn_id, tuple_id)?;
+                    }
+                    deleted = targets.len();
+                } else {
+                    return Err(anyhow!("Table({}) not found", ast.table_name));
+                }
+            } else {
+                return Err(anyhow!("Table({}) not found", ast.table_name));
+            }
+        }
+        Ok(QueryResult::rows_affected(deleted))
+    }
+}
+
+#[derive(Debug)]
+pub struct UpdateExecutor {
+    pub stmt: Stmt,
+    pub txn: Transaction,
+    pub catalog: Arc<Catalog>,
+    pub buffer_pool_manager: Arc<BufferPoolManager>,
+}
+
+impl Executor for UpdateExecutor {
+    fn execute(&self) -> Result<QueryResult> {
+        let mut updated = 0;
+        if let Stmt::UpdateStmt(ast) = &self.stmt {
+            if let Some(schema) = self.catalog.get_schema(&ast.table_name)? {
+                if let Some(first_block_number) =
+                    self.catalog.get_first_block_number(&ast.table_name)?
+                {
+                    let mut assignments = Vec::new();
+                    for assignment in ast.assignments.iter() {
+                        let index = match schema.column_index(&assignment.column_name) {
+                            Some(index) => index,
+                            None => {
+                                return Err(anyhow!("Column({}) not found", assignment.column_name))
                             }
-                            res = format!("{}\n", res);
+                        };
+                        if assignments.iter().any(|(i, _)| *i == index) {
+                            return Err(anyhow!(
+                                "Column({}) assigned more than once",
+                                assignment.column_name
+                            ));
+                        }
+                        check_column_references(&assignment.expr, &schema)?;
+                        assignments.push((index, &assignment.expr));
+                    }
+                    if let Some(where_clause) = &ast.where_clause {
+                        check_column_references(where_clause, &schema)?;
+                    }
+                    self.txn.lock_table_for_write(first_block_number, true)?;
+                    let table = Table::new(&self.buffer_pool_manager, &schema, first_block_number);
+                    let indexes = TableIndexes::new(
+                        &self.catalog,
+                        &self.buffer_pool_manager,
+                        self.txn.txn_id,
+                        &ast.table_name,
+                        &schema,
+                    )?;
+                    let constraints = self.catalog.get_column_constraints(&ast.table_name)?;
+                    // Compute every new tuple before writing so that the new versions
+                    // are not visited by the scan.
+                    let mut updates = Vec::new();
+                    for (tuple_id, tuple) in scan_targets(
+                        &ast.table_name,
+                        &table,
+                        &schema,
+                        ast.where_clause.as_ref(),
+                        &self.txn,
+                        &self.catalog,
+                        &self.buffer_pool_manager,
+                    )? {
+                        let mut new_tuple = tuple.clone();
+                        for (index, expr) in assignments.iter() {
+                            let value = evaluate(expr, &tuple, &schema)?;
+                            new_tuple.values[*index] =
+                                cast_to_column(&value, &schema.columns[*index])?;
                         }
+                        check_not_null(&schema, &constraints, &new_tuple)?;
+                        updates.push((tuple_id, new_tuple));
+                    }
+                    for (tuple_id, new_tuple) in updates.into_iter() {
+                        self.txn.lock_row(tuple_id)?;
+                        let new_tuple_id =
+                            table.update_tuple(self.txn.txn_id, &tuple_id, new_tuple.clone())?;
+                        self.txn.lock_row(new_tuple_id)?;
+                        indexes.insert(&table, &new_tuple, new_tuple_id)?;
+                        updated += 1;
                     }
                 } else {
-                    return Err(anyhow!("Table({}) not found\n", ast.table_name));
+                    return Err(anyhow!("Table({}) not found", ast.table_name));
                 }
             } else {
-                return Err(anyhow!("Table({}) not found\n", ast.table_name));
+                return Err(anyhow!("Table({}) not found", ast.table_name));
+            }
+        }
+        Ok(QueryResult::rows_affected(updated))
+    }
+}
+
+// Returns the tuples visible to the transaction which satisfy the WHERE clause of an UPDATE
+// or DELETE, read through an index when one matches the clause like in a SELECT.
+fn scan_targets(
+    table_name: &str,
+    table: &Table,
+    schema: &Schema,
+    where_clause: Option<&ast::ExprAst>,
+    txn: &Transaction,
+    catalog: &Catalog,
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+) -> Result<Vec<(TupleId, Tuple)>> {
+    let range = match where_clause {
+        Some(where_clause) => plan_index_range(table_name, schema, where_clause, catalog)?,
+        None => None,
+    };
+    let mut candidates = Vec::new();
+    match range {
+        Some(range) => {
+            let tree = BTree::new(
+                buffer_pool_manager,
+                &range.key_schema,
+                range.root_block_number,
+            );
+            for entry in tree.scan(range.lower, range.upper)? {
+                let tuple_id = entry?.1;
+                // The entries of the versions the snapshot does not see are skipped.
+                if let Some(tuple) = table.get_tuple(&txn.snapshot, &tuple_id)? {
+                    candidates.push((tuple_id, tuple));
+                }
+            }
+        }
+        None => {
+            for item in table.scan_tuples(&txn.snapshot) {
+                candidates.push(item?);
+            }
+        }
+    }
+    let mut targets = Vec::new();
+    for (tuple_id, tuple) in candidates {
+        if let Some(where_clause) = where_clause {
+            // The index may return more tuples than the WHERE clause accepts.
+            if !evaluate_predicate(where_clause, &tuple, schema)? {
+                continue;
             }
         }
-        Ok(res)
+        targets.push((tuple_id, tuple));
+    }
+    Ok(targets)
+}
+
+// The indexes of a table, which are kept in sync with its tuples.
+struct TableIndexes<'a> {
+    buffer_pool_manager: &'a Arc<BufferPoolManager>,
+    txn_id: TxnId,
+    schema: &'a Schema,
+    // each index with the schema of its keys
+    indexes: Vec<(IndexInfo, Schema)>,
+}
+
+impl<'a> TableIndexes<'a> {
+    fn new(
+        catalog: &Catalog,
+        buffer_pool_manager: &'a Arc<BufferPoolManager>,
+        txn_id: TxnId,
+        table_name: &str,
+        schema: &'a Schema,
+    ) -> Result<Self> {
+        let mut indexes = Vec::new();
+        for index in catalog.get_indexes(table_name)? {
+            let key_schema = index.key_schema(schema)?;
+            indexes.push((index, key_schema));
+        }
+        Ok(TableIndexes {
+            buffer_pool_manager,
+            txn_id,
+            schema,
+            indexes,
+        })
+    }
+    // Adds the entries of the tuple. Fails if a unique index has its key for another live
+    // tuple, where keys with NULL never conflict.
+    fn insert(&self, table: &Table, tuple: &Tuple, tuple_id: TupleId) -> Result<()> {
+        self.insert_with_arbiters(table, tuple, tuple_id, &[])
+            .map(|_| ())
+    }
+    // Like `insert`, but the entries of the arbiter indexes, given by their positions, are
+    // added first. If one of them has the key for another live tuple, that tuple is returned
+    // and the rest of the entries are not added.
+    fn insert_with_arbiters(
+        &self,
+        table: &Table,
+        tuple: &Tuple,
+        tuple_id: TupleId,
+        arbiters: &[usize],
+    ) -> Result<Option<TupleId>> {
+        let others = (0..self.indexes.len()).filter(|i| !arbiters.contains(i));
+        for i in arbiters.iter().copied().chain(others) {
+            let (index, key_schema) = &self.indexes[i];
+            let tree = BTree::new(
+                self.buffer_pool_manager,
+                key_schema,
+                index.root_block_number,
+            );
+            let key = index.key(self.schema, tuple)?;
+            if !index.unique || key.contains(&Value::Null) {
+                tree.insert(self.txn_id, key, tuple_id)?;
+                continue;
+            }
+            // The entries of the deleted versions stay in the index, so only the live ones
+            // conflict.
+            if let Some(conflict_id) =
+                tree.insert_unique(self.txn_id, key.clone(), tuple_id, |tuple_id| {
+                    table.is_live(self.txn_id, tuple_id)
+                })?
+            {
+                if arbiters.contains(&i) {
+                    return Ok(Some(conflict_id));
+                }
+                let key: Vec<String> = key.iter().map(|v| v.to_string()).collect();
+                return Err(anyhow!(
+                    "duplicate key ({}) violates unique Index({})",
+                    key.join(", "),
+                    index.name
+                ));
+            }
+        }
+        Ok(None)
+    }
+    // The positions of the unique indexes on the columns, in any order, or of every unique
+    // index if no columns are given.
+    fn arbiters(&self, column_names: Option<&[String]>) -> Vec<usize> {
+        let sorted = |names: &[String]| {
+            let mut names = names.to_vec();
+            names.sort();
+            names.dedup();
+            names
+        };
+        self.indexes
+            .iter()
+            .enumerate()
+            .filter(|(_, (index, _))| {
+                index.unique
+                    && match column_names {
+                        Some(names) => sorted(names) == sorted(&index.column_names),
+                        None => true,
+                    }
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+    // Adds every version of the tuples of the table, since older snapshots may still see
+    // the deleted ones.
+    fn fill(&self, table: &Table) -> Result<()> {
+        for item in table.scan_all_versions() {
+            let (tuple_id, tuple) = item?;
+            self.insert(table, &tuple, tuple_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{open_catalog, Catalog, Column, ColumnType, Schema};
+    use crate::execution::planner::plan_select;
+    use crate::execution::{
+        CreateIndexExecutor, CreateTableExecutor, DeleteExecutor, Executor, InsertExecutor,
+        UpdateExecutor,
+    };
+    use crate::parser::token::tokenize;
+    use crate::parser::{Parser, Stmt};
+    use crate::transaction::{Transaction, TransactionManager};
+    use crate::value::Value;
+    use anyhow::Result;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    fn execute(
+        sql: &str,
+        catalog: &Arc<Catalog>,
+        buffer_pool_manager: &Arc<BufferPoolManager>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let tokens = tokenize(&mut sql.chars().peekable())?;
+        let stmt = Parser::new(tokens).parse()?;
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let txn_id = transaction_manager.begin();
+        let txn = transaction_manager.transaction(txn_id)?;
+        match execute_stmt(stmt, txn, catalog.clone(), buffer_pool_manager.clone()) {
+            Ok(ret) => {
+                transaction_manager.commit(txn_id)?;
+                Ok(ret)
+            }
+            Err(e) => {
+                transaction_manager.abort(txn_id)?;
+                Err(e)
+            }
+        }
+    }
+    fn execute_stmt(
+        stmt: Stmt,
+        txn: Transaction,
+        catalog: Arc<Catalog>,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let result = match stmt {
+            Stmt::SelectStmt(ast) => {
+                let mut plan = plan_select(&ast, &txn, &catalog, &buffer_pool_manager)?;
+                plan.init()?;
+                let mut ret = Vec::new();
+                while let Some(tuple) = plan.next()? {
+                    ret.push(tuple.values);
+                }
+                return Ok(ret);
+            }
+            Stmt::InsertStmt(_) => InsertExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::UpdateStmt(_) => UpdateExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::DeleteStmt(_) => DeleteExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::CreateIndexStmt(_) => CreateIndexExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            Stmt::CreateTableStmt(_) => CreateTableExecutor {
+                stmt,
+                txn,
+                catalog,
+                buffer_pool_manager,
+            }
+            .execute()?,
+            stmt => panic!("unexpected statement {:?}", stmt),
+        };
+        // the rows of RETURNING
+        Ok(result.rows)
+    }
+    #[test]
+    fn maintain_indexes() -> Result<()> {
+        let catalog = open_catalog("tmp/maintain_indexes/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let schema = Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "age".to_string(),
+                    column_type: ColumnType::Int,
+                },
+            ],
+        };
+        let txn_id = buffer_pool_manager.log_manager().begin();
+        catalog.create_table(txn_id, "users", &schema)?;
+        buffer_pool_manager.log_manager().commit(txn_id)?;
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        for id in 0..100 {
+            run(&format!("INSERT INTO users VALUES ({}, {});", id, id % 10))?;
+        }
+        // The index is filled with the existing tuples.
+        run("CREATE UNIQUE INDEX users_id ON users (id);")?;
+        run("CREATE INDEX users_age_id ON users (age, id);")?;
+        assert!(run("CREATE UNIQUE INDEX users_age ON users (age);").is_err());
+        assert!(catalog.get_indexes("users")?.len() == 2);
+
+        assert!(run("INSERT INTO users VALUES (5, 0);").is_err());
+        run("INSERT INTO users VALUES (NULL, 0);")?;
+        run("INSERT INTO users VALUES (NULL, 0);")?;
+        run("DELETE FROM users WHERE id = 3;")?;
+        run("UPDATE users SET id = 3 WHERE id = 4;")?;
+        assert!(run("UPDATE users SET id = 5 WHERE id = 6;").is_err());
+        // The failed statement is rolled back along with the rows it already updated.
+        assert!(run("UPDATE users SET id = 200 WHERE id >= 98;").is_err());
+        assert_eq!(
+            run("SELECT id FROM users WHERE id >= 98;")?,
+            vec![vec![Value::Int(98)], vec![Value::Int(99)]]
+        );
+
+        assert_eq!(
+            run("SELECT id FROM users WHERE id = 3;")?,
+            vec![vec![Value::Int(3)]]
+        );
+        assert_eq!(
+            run("SELECT id FROM users WHERE id = 4;")?,
+            Vec::<Vec<Value>>::new()
+        );
+        assert_eq!(
+            run("SELECT id FROM users u WHERE 97 < u.id;")?,
+            vec![vec![Value::Int(98)], vec![Value::Int(99)]]
+        );
+        assert_eq!(
+            run("SELECT id FROM users WHERE age = 2 AND id >= 70;")?,
+            vec![
+                vec![Value::Int(72)],
+                vec![Value::Int(82)],
+                vec![Value::Int(92)]
+            ]
+        );
+        assert_eq!(
+            run("SELECT COUNT(*) FROM users WHERE age = 0 AND id < 30;")?,
+            vec![vec![Value::Int(3)]]
+        );
+
+        // UPDATE and DELETE read only the tuples in the range of the index, so the division
+        // by zero on the rows with age 0 is never evaluated.
+        run("UPDATE users SET age = 1 WHERE 10 / age = 1 AND id = 19;")?;
+        assert_eq!(
+            run("SELECT age FROM users WHERE id = 19;")?,
+            vec![vec![Value::Int(1)]]
+        );
+        run("DELETE FROM users WHERE 10 / age = 10 AND id = 19;")?;
+        assert_eq!(
+            run("SELECT id FROM users WHERE id = 19;")?,
+            Vec::<Vec<Value>>::new()
+        );
+        // Without a matching index every tuple is read.
+        assert!(run("DELETE FROM users WHERE 10 / age = 1;").is_err());
+        Ok(())
+    }
+    #[test]
+    fn enforce_constraints() -> Result<()> {
+        let catalog = open_catalog("tmp/enforce_constraints/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        run("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR NOT NULL, email VARCHAR UNIQUE, UNIQUE (name, email));")?;
+        let mut index_names: Vec<String> = catalog
+            .get_indexes("users")?
+            .into_iter()
+            .map(|index| index.name)
+            .collect();
+        index_names.sort();
+        assert_eq!(
+            index_names,
+            vec!["users_email_key", "users_name_email_key", "users_pkey"]
+        );
+        run("INSERT INTO users VALUES (1, 'a', 'a@example.com');")?;
+        run("INSERT INTO users VALUES (2, 'b', NULL);")?;
+        run("INSERT INTO users VALUES (3, 'b', NULL);")?;
+        for sql in [
+            "INSERT INTO users VALUES (1, 'c', NULL);",
+            "INSERT INTO users VALUES (NULL, 'c', NULL);",
+            "INSERT INTO users VALUES (4, NULL, NULL);",
+            "INSERT INTO users VALUES (4, 'c', 'a@example.com');",
+            "UPDATE users SET id = 1 WHERE id = 2;",
+            "UPDATE users SET name = NULL WHERE id = 2;",
+        ] {
+            assert!(run(sql).is_err(), "{}", sql);
+        }
+        let error = run("INSERT INTO users VALUES (1, 'c', NULL);").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "duplicate key (1) violates unique Index(users_pkey)"
+        );
+        // The key is free again once the row is deleted.
+        run("DELETE FROM users WHERE id = 1;")?;
+        run("INSERT INTO users VALUES (1, 'c', 'a@example.com');")?;
+        assert_eq!(run("SELECT id FROM users WHERE id >= 0;")?.len(), 3);
+
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR PRIMARY KEY);",
+            "CREATE TABLE t (id INT, UNIQUE (name));",
+            "CREATE TABLE t (id INT, id VARCHAR);",
+        ] {
+            assert!(run(sql).is_err(), "{}", sql);
+        }
+        assert_eq!(catalog.get_oid("t")?, None);
+        // Another table gets names of its own, even when they collide.
+        run("CREATE TABLE t (id INT CONSTRAINT t_key UNIQUE, id2 INT UNIQUE, UNIQUE (id2));")?;
+        let mut index_names: Vec<String> = catalog
+            .get_indexes("t")?
+            .into_iter()
+            .map(|index| index.name)
+            .collect();
+        index_names.sort();
+        assert_eq!(index_names, vec!["t_id2_key", "t_id2_key1", "t_key"]);
+        Ok(())
+    }
+    #[test]
+    fn cast_inserted_values() -> Result<()> {
+        let catalog = open_catalog("tmp/cast_inserted_values/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        run("CREATE TABLE users (id INT, name VARCHAR);")?;
+        run("INSERT INTO users VALUES ('1', 2);")?;
+        run("INSERT INTO users VALUES (NULL, NULL);")?;
+        run("UPDATE users SET name = id WHERE id = 1;")?;
+        assert_eq!(
+            run("SELECT * FROM users ORDER BY id;")?,
+            vec![
+                vec![Value::Null, Value::Null],
+                vec![Value::Int(1), Value::Varchar("1".to_string())],
+            ]
+        );
+        for (sql, message) in [
+            (
+                "INSERT INTO users VALUES ('one', 'a');",
+                "Column(id) is of type Int but got Varchar(\"one\")",
+            ),
+            (
+                "INSERT INTO users VALUES (1);",
+                "Table(users) has 2 columns but 1 values are given",
+            ),
+            (
+                "INSERT INTO users VALUES (1, 'a', 'b');",
+                "Table(users) has 2 columns but 3 values are given",
+            ),
+            (
+                "UPDATE users SET id = 'one';",
+                "Column(id) is of type Int but got Varchar(\"one\")",
+            ),
+        ] {
+            assert_eq!(run(sql).unwrap_err().to_string(), message);
+        }
+        assert_eq!(run("SELECT * FROM users;")?.len(), 2);
+        Ok(())
+    }
+    #[test]
+    fn insert_rows_with_defaults() -> Result<()> {
+        let catalog = open_catalog("tmp/insert_rows_with_defaults/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        run("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR DEFAULT 'none', age INT NOT NULL DEFAULT 6 * 3);")?;
+        run("INSERT INTO users (age, id) VALUES (20, 1), (DEFAULT, -2), (30, 3);")?;
+        run("INSERT INTO users VALUES (4, DEFAULT, 40);")?;
+        run("INSERT INTO users (id, name) VALUES (5, NULL);")?;
+        assert_eq!(
+            run("SELECT * FROM users ORDER BY id;")?,
+            vec![
+                vec![
+                    Value::Int(-2),
+                    Value::Varchar("none".to_string()),
+                    Value::Int(18)
+                ],
+                vec![
+                    Value::Int(1),
+                    Value::Varchar("none".to_string()),
+                    Value::Int(20)
+                ],
+                vec![
+                    Value::Int(3),
+                    Value::Varchar("none".to_string()),
+                    Value::Int(30)
+                ],
+                vec![
+                    Value::Int(4),
+                    Value::Varchar("none".to_string()),
+                    Value::Int(40)
+                ],
+                vec![Value::Int(5), Value::Null, Value::Int(18)],
+            ]
+        );
+        for (sql, message) in [
+            (
+                "INSERT INTO users (id, name) VALUES (6);",
+                "2 columns are listed but 1 values are given",
+            ),
+            (
+                "INSERT INTO users (id, id) VALUES (6, 6);",
+                "Column(id) specified more than once",
+            ),
+            (
+                "INSERT INTO users (id, email) VALUES (6, 'a');",
+                "Column(email) not found",
+            ),
+            (
+                "INSERT INTO users (id, age) VALUES (6, NULL);",
+                "NULL violates NOT NULL constraint of Column(age)",
+            ),
+            (
+                "INSERT INTO users (id) VALUES (6), (1), (7);",
+                "duplicate key (1) violates unique Index(users_pkey)",
+            ),
+            (
+                "INSERT INTO users (id) VALUES (id + 1);",
+                "Column(id) not found",
+            ),
+        ] {
+            assert_eq!(run(sql).unwrap_err().to_string(), message);
+        }
+        // The rows before the failed one are rolled back.
+        assert_eq!(run("SELECT * FROM users;")?.len(), 5);
+        assert!(run("CREATE TABLE t (id INT DEFAULT 'one');").is_err());
+        assert!(run("CREATE TABLE t (id INT DEFAULT id);").is_err());
+        Ok(())
+    }
+    #[test]
+    fn insert_from_select() -> Result<()> {
+        let catalog = open_catalog("tmp/insert_from_select/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        run("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR NOT NULL, age INT DEFAULT 18);")?;
+        run("INSERT INTO users VALUES (1, 'a', 20), (2, 'b', 30);")?;
+        // The query does not see the rows inserted by the statement.
+        run("INSERT INTO users (name, id) SELECT name, id + 10 FROM users;")?;
+        run("INSERT INTO users SELECT id + 20, '3', age FROM users WHERE id = 1;")?;
+        assert_eq!(
+            run("SELECT * FROM users ORDER BY id;")?,
+            vec![
+                vec![
+                    Value::Int(1),
+                    Value::Varchar("a".to_string()),
+                    Value::Int(20)
+                ],
+                vec![
+                    Value::Int(2),
+                    Value::Varchar("b".to_string()),
+                    Value::Int(30)
+                ],
+                vec![
+                    Value::Int(11),
+                    Value::Varchar("a".to_string()),
+                    Value::Int(18)
+                ],
+                vec![
+                    Value::Int(12),
+                    Value::Varchar("b".to_string()),
+                    Value::Int(18)
+                ],
+                vec![
+                    Value::Int(21),
+                    Value::Varchar("3".to_string()),
+                    Value::Int(20)
+                ],
+            ]
+        );
+        for (sql, message) in [
+            (
+                "INSERT INTO users SELECT id + 30, name FROM users;",
+                "Table(users) has 3 columns but 2 values are given",
+            ),
+            (
+                "INSERT INTO users (id, age) SELECT id + 30, name FROM users;",
+                "Column(age) is of type Int but got Varchar(\"a\")",
+            ),
+            (
+                "INSERT INTO users (id) SELECT id + 30 FROM users;",
+                "NULL violates NOT NULL constraint of Column(name)",
+            ),
+            (
+                "INSERT INTO users SELECT id + 1, name, age FROM users WHERE id = 11;",
+                "duplicate key (12) violates unique Index(users_pkey)",
+            ),
+            (
+                "INSERT INTO users SELECT * FROM orders;",
+                "Table(orders) not found",
+            ),
+        ] {
+            assert_eq!(run(sql).unwrap_err().to_string(), message);
+        }
+        assert_eq!(run("SELECT * FROM users;")?.len(), 5);
+
+        run("CREATE TABLE adults AS SELECT id, name AS nickname, age * 2 FROM users WHERE age > 18;")?;
+        assert_eq!(
+            catalog.get_schema("adults")?,
+            Some(Schema {
+                columns: vec![
+                    Column {
+                        name: "id".to_string(),
+                        column_type: ColumnType::Int,
+                    },
+                    Column {
+                        name: "nickname".to_string(),
+                        column_type: ColumnType::Varchar,
+                    },
+                    Column {
+                        name: "?column?".to_string(),
+                        column_type: ColumnType::Int,
+                    },
+                ],
+            })
+        );
+        assert_eq!(
+            run("SELECT * FROM adults ORDER BY id;")?,
+            vec![
+                vec![
+                    Value::Int(1),
+                    Value::Varchar("a".to_string()),
+                    Value::Int(40)
+                ],
+                vec![
+                    Value::Int(2),
+                    Value::Varchar("b".to_string()),
+                    Value::Int(60)
+                ],
+                vec![
+                    Value::Int(21),
+                    Value::Varchar("3".to_string()),
+                    Value::Int(40)
+                ],
+            ]
+        );
+        assert_eq!(
+            run("CREATE TABLE adults AS SELECT * FROM users;")
+                .unwrap_err()
+                .to_string(),
+            "Table(adults) exists"
+        );
+        // Another statement is refused rather than reported as a row inserted.
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let txn_id = transaction_manager.begin();
+        let executor = InsertExecutor {
+            stmt: Parser::new(tokenize(&mut "DELETE FROM users;".chars().peekable())?).parse()?,
+            txn: transaction_manager.transaction(txn_id)?,
+            catalog: catalog.clone(),
+            buffer_pool_manager: buffer_pool_manager.clone(),
+        };
+        assert!(executor.execute().is_err());
+        transaction_manager.abort(txn_id)?;
+        assert_eq!(
+            run("SELECT COUNT(*) FROM users;")?,
+            vec![vec![Value::Int(5)]]
+        );
+        Ok(())
+    }
+    #[test]
+    fn upsert() -> Result<()> {
+        let catalog = open_catalog("tmp/upsert/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let run = |sql: &str| execute(sql, &catalog, &buffer_pool_manager);
+        let varchar = |s: &str| Value::Varchar(s.to_string());
+        run("CREATE TABLE users (id INT PRIMARY KEY, email VARCHAR UNIQUE, visits INT NOT NULL DEFAULT 0);")?;
+        run("INSERT INTO users VALUES (1, 'a@x', 1), (2, 'b@x', 1);")?;
+        assert_eq!(
+            run("INSERT INTO users VALUES (1, 'z@x', 1), (3, 'c@x', 1) ON CONFLICT (id) DO NOTHING RETURNING id;")?,
+            vec![vec![Value::Int(3)]]
+        );
+        assert_eq!(
+            run("INSERT INTO users VALUES (1, 'z@x', 5), (4, 'd@x', 1) ON CONFLICT (id) DO UPDATE SET visits = visits + excluded.visits RETURNING *;")?,
+            vec![
+                vec![Value::Int(1), varchar("a@x"), Value::Int(6)],
+                vec![Value::Int(4), varchar("d@x"), Value::Int(1)],
+            ]
+        );
+        // Without columns, DO NOTHING resolves the conflicts of every unique index.
+        assert_eq!(
+            run("INSERT INTO users VALUES (5, 'b@x', 1) ON CONFLICT DO NOTHING RETURNING id;")?,
+            Vec::<Vec<Value>>::new()
+        );
+        // A row inserted by the same statement conflicts too.
+        assert_eq!(
+            run("INSERT INTO users (id, email) VALUES (6, 'f@x'), (6, 'g@x') ON CONFLICT (id) DO NOTHING RETURNING email, visits + 1 AS next;")?,
+            vec![vec![varchar("f@x"), Value::Int(1)]]
+        );
+        for (sql, message) in [
+            (
+                "INSERT INTO users VALUES (5, 'b@x', 1) ON CONFLICT (id) DO NOTHING;",
+                "duplicate key (b@x) violates unique Index(users_email_key)",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1), (2, 'b@x', 1) ON CONFLICT (id) DO UPDATE SET visits = 0;",
+                "ON CONFLICT DO UPDATE cannot affect a row written by the same statement",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1) ON CONFLICT (id) DO UPDATE SET visits = NULL;",
+                "NULL violates NOT NULL constraint of Column(visits)",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1) ON CONFLICT (id) DO UPDATE SET email = 'a@x';",
+                "duplicate key (a@x) violates unique Index(users_email_key)",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1) ON CONFLICT (id, email) DO NOTHING;",
+                "no unique index matches ON CONFLICT (id, email)",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1) ON CONFLICT (name) DO NOTHING;",
+                "Column(name) not found",
+            ),
+            (
+                "INSERT INTO users VALUES (2, 'b@x', 1) ON CONFLICT (id) DO UPDATE SET visits = excluded.name;",
+                "Column(excluded.name) not found",
+            ),
+            (
+                "INSERT INTO users VALUES (7, 'g@x', 1) RETURNING COUNT(*);",
+                "aggregate functions are not allowed in RETURNING",
+            ),
+        ] {
+            assert_eq!(run(sql).unwrap_err().to_string(), message);
+        }
+        assert_eq!(
+            run("SELECT * FROM users ORDER BY id;")?,
+            vec![
+                vec![Value::Int(1), varchar("a@x"), Value::Int(6)],
+                vec![Value::Int(2), varchar("b@x"), Value::Int(1)],
+                vec![Value::Int(3), varchar("c@x"), Value::Int(1)],
+                vec![Value::Int(4), varchar("d@x"), Value::Int(1)],
+                vec![Value::Int(6), varchar("f@x"), Value::Int(0)],
+            ]
+        );
+
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let stmt = |sql: &str| Parser::new(tokenize(&mut sql.chars().peekable())?).parse();
+        let run_in = |txn_id, sql: &str| {
+            execute_stmt(
+                stmt(sql)?,
+                transaction_manager.transaction(txn_id)?,
+                catalog.clone(),
+                buffer_pool_manager.clone(),
+            )
+        };
+        // The conflict with an uncommitted row waits for its transaction to end.
+        let writer = transaction_manager.begin();
+        run_in(writer, "INSERT INTO users VALUES (7, 'g@x', 1);")?;
+        let upsert = stmt(
+            "INSERT INTO users VALUES (7, 'h@x', 1) ON CONFLICT (id) DO NOTHING RETURNING email;",
+        )?;
+        let upserter = transaction_manager.begin();
+        let txn = transaction_manager.transaction(upserter)?;
+        let (catalog_for_upsert, buffer_pool_manager_for_upsert) =
+            (catalog.clone(), buffer_pool_manager.clone());
+        let handle = thread::spawn(move || {
+            execute_stmt(
+                upsert,
+                txn,
+                catalog_for_upsert,
+                buffer_pool_manager_for_upsert,
+            )
+        });
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_finished());
+        transaction_manager.abort(writer)?;
+        assert_eq!(handle.join().unwrap()?, vec![vec![varchar("h@x")]]);
+        transaction_manager.commit(upserter)?;
+        // A row committed after the snapshot cannot be resolved.
+        let reader = transaction_manager.begin();
+        transaction_manager.transaction(reader)?;
+        let writer = transaction_manager.begin();
+        run_in(writer, "INSERT INTO users VALUES (8, 'i@x', 1);")?;
+        transaction_manager.commit(writer)?;
+        assert_eq!(
+            run_in(
+                reader,
+                "INSERT INTO users VALUES (8, 'j@x', 1) ON CONFLICT (id) DO NOTHING;"
+            )
+            .unwrap_err()
+            .to_string(),
+            "could not serialize access due to a concurrent insert"
+        );
+        transaction_manager.abort(reader)?;
+        Ok(())
     }
 }
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::vec;
+
+use anyhow::Result;
+
+use super::expression::{evaluate, infer_type};
+use super::operator::{BoxedOperator, Operator};
+use crate::catalog::{Column, Schema};
+use crate::parser::ast::{AggregateFunction, ExprAst};
+use crate::storage::tuple::Tuple;
+use crate::value::Value;
+
+// Hash aggregation.
+// Input tuples are grouped by the values of the GROUP BY expressions and every group keeps one
+// accumulator per aggregate. The output tuples hold the group keys followed by the aggregate
+// values, laid out as described by `output_schema`.
+#[derive(Debug)]
+pub struct HashAggregate<'a> {
+    schema: &'a Schema,
+    group_by: &'a [ExprAst],
+    aggregates: &'a [ExprAst],
+    // groups in the order they first appeared
+    groups: Vec<(Vec<Value>, Vec<Accumulator>)>,
+    group_indexes: HashMap<Vec<Value>, usize>,
+}
+
+impl<'a> HashAggregate<'a> {
+    pub fn new(schema: &'a Schema, group_by: &'a [ExprAst], aggregates: &'a [ExprAst]) -> Self {
+        HashAggregate {
+            schema,
+            group_by,
+            aggregates,
+            groups: Vec::new(),
+            group_indexes: HashMap::new(),
+        }
+    }
+    pub fn push(&mut self, tuple: &Tuple) -> Result<()> {
+        let mut keys = Vec::new();
+        for expr in self.group_by.iter() {
+            keys.push(evaluate(expr, tuple, self.schema)?);
+        }
+        let index = match self.group_indexes.get(&keys) {
+            Some(index) => *index,
+            None => {
+                self.groups
+                    .push((keys.clone(), accumulators(self.aggregates)?));
+                self.group_indexes.insert(keys, self.groups.len() - 1);
+                self.groups.len() - 1
+            }
+        };
+        for (expr, accumulator) in self.aggregates.iter().zip(self.groups[index].1.iter_mut()) {
+            let value = match expr {
+                ExprAst::Aggregate { arg: Some(arg), .. } => evaluate(arg, tuple, self.schema)?,
+                // COUNT(*) counts rows, so any non-NULL value will do.
+                _ => Value::Int(1),
+            };
+            // Every aggregate ignores NULLs.
+            if value != Value::Null {
+                accumulator.add(value)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<Vec<Tuple>> {
+        // Without GROUP BY the whole input is a single group, even if it is empty.
+        if self.groups.is_empty() && self.group_by.is_empty() {
+            self.groups
+                .push((Vec::new(), accumulators(self.aggregates)?));
+        }
+        let mut ret = Vec::new();
+        for (keys, accumulators) in self.groups.into_iter() {
+            let mut values = keys;
+            for accumulator in accumulators.iter() {
+                values.push(accumulator.result()?);
+            }
+            ret.push(Tuple { values });
+        }
+        Ok(ret)
+    }
+}
+
+// Groups the tuples of its child in `init`.
+// HAVING is evaluated by a filter over this operator.
+pub struct Aggregate {
+    child: BoxedOperator,
+    group_by: Vec<ExprAst>,
+    aggregates: Vec<ExprAst>,
+    schema: Schema,
+    tuples: vec::IntoIter<Tuple>,
+}
+
+impl Aggregate {
+    pub fn new(
+        child: BoxedOperator,
+        group_by: Vec<ExprAst>,
+        aggregates: Vec<ExprAst>,
+    ) -> Result<Self> {
+        let schema = output_schema(child.schema(), &group_by, &aggregates)?;
+        Ok(Aggregate {
+            child,
+            group_by,
+            aggregates,
+            schema,
+            tuples: Vec::new().into_iter(),
+        })
+    }
+}
+
+impl Operator for Aggregate {
+    fn init(&mut self) -> Result<()> {
+        self.child.init()?;
+        let schema = self.child.schema().clone();
+        let mut aggregate = HashAggregate::new(&schema, &self.group_by, &self.aggregates);
+        while let Some(tuple) = self.child.next()? {
+            aggregate.push(&tuple)?;
+        }
+        self.tuples = aggregate.finish()?.into_iter();
+        Ok(())
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        Ok(self.tuples.next())
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+fn output_schema(schema: &Schema, group_by: &[ExprAst], aggregates: &[ExprAst]) -> Result<Schema> {
+    let mut columns = Vec::new();
+    for (i, expr) in group_by.iter().enumerate() {
+        columns.push(Column {
+            name: group_column_name(i),
+            column_type: infer_type(expr, schema)?,
+        });
+    }
+    for (i, expr) in aggregates.iter().enumerate() {
+        columns.push(Column {
+            name: aggregate_column_name(i),
+            column_type: infer_type(expr, schema)?,
+        });
+    }
+    Ok(Schema { columns })
+}
+
+// Appends the aggregates in `expr` that are not in `aggregates` yet.
+pub fn collect_aggregates(expr: &ExprAst, aggregates: &mut Vec<ExprAst>) -> Result<()> {
+    match expr {
+        ExprAst::Aggregate { arg, .. } => {
+            if let Some(arg) = arg {
+                if contains_aggregate(arg) {
+                    return Err(anyhow!("aggregate function calls cannot be nested"));
+                }
+            }
+            if !aggregates.contains(expr) {
+                aggregates.push(expr.clone());
+            }
+            Ok(())
+        }
+        ExprAst::UnaryOp { expr, .. } | ExprAst::IsNull { expr, .. } => {
+            collect_aggregates(expr, aggregates)
+        }
+        ExprAst::BinaryOp { left, right, .. } => {
+            collect_aggregates(left, aggregates)?;
+            collect_aggregates(right, aggregates)
+        }
+        ExprAst::Column(_) | ExprAst::Lit(_) => Ok(()),
+    }
+}
+
+pub fn contains_aggregate(expr: &ExprAst) -> bool {
+    match expr {
+        ExprAst::Aggregate { .. } => true,
+        ExprAst::UnaryOp { expr, .. } | ExprAst::IsNull { expr, .. } => contains_aggregate(expr),
+        ExprAst::BinaryOp { left, right, .. } => {
+            contains_aggregate(left) || contains_aggregate(right)
+        }
+        ExprAst::Column(_) | ExprAst::Lit(_) => false,
+    }
+}
+
+// Rewrites `expr` to be evaluated against the output of the aggregation.
+pub fn rewrite(expr: &ExprAst, group_by: &[ExprAst], aggregates: &[ExprAst]) -> Result<ExprAst> {
+    if let Some(i) = group_by.iter().position(|e| e == expr) {
+        return Ok(ExprAst::Column(group_column_name(i)));
+    }
+    match expr {
+        ExprAst::Aggregate { .. } => match aggregates.iter().position(|e| e == expr) {
+            Some(i) => Ok(ExprAst::Column(aggregate_column_name(i))),
+            None => Err(anyhow!("aggregate {:?} not found", expr)),
+        },
+        ExprAst::Column(name) => Err(anyhow!(
+            "Column({}) must appear in the GROUP BY clause or be used in an aggregate function",
+            name
+        )),
+        ExprAst::Lit(_) => Ok(expr.clone()),
+        ExprAst::UnaryOp { op, expr } => Ok(ExprAst::UnaryOp {
+            op: *op,
+            expr: Box::new(rewrite(expr, group_by, aggregates)?),
+        }),
+        ExprAst::BinaryOp { op, left, right } => Ok(ExprAst::BinaryOp {
+            op: *op,
+            left: Box::new(rewrite(left, group_by, aggregates)?),
+            right: Box::new(rewrite(right, group_by, aggregates)?),
+        }),
+        ExprAst::IsNull { expr, negated } => Ok(ExprAst::IsNull {
+            expr: Box::new(rewrite(expr, group_by, aggregates)?),
+            negated: *negated,
+        }),
+    }
+}
+
+// '#' never appears in identifiers, so these cannot collide with user columns.
+fn group_column_name(i: usize) -> String {
+    format!("#group{}", i)
+}
+
+fn aggregate_column_name(i: usize) -> String {
+    format!("#aggregate{}", i)
+}
+
+fn accumulators(aggregates: &[ExprAst]) -> Result<Vec<Accumulator>> {
+    let mut ret = Vec::new();
+    for expr in aggregates.iter() {
+        ret.push(match expr {
+            ExprAst::Aggregate { func, .. } => match func {
+                AggregateFunction::Count => Accumulator::Count(0),
+                AggregateFunction::Sum => Accumulator::Sum(None),
+                AggregateFunction::Min => Accumulator::Min(None),
+                AggregateFunction::Max => Accumulator::Max(None),
+                AggregateFunction::Avg => Accumulator::Avg { sum: 0, count: 0 },
+            },
+            _ => return Err(anyhow!("expected aggregate function")),
+        });
+    }
+    Ok(ret)
+}
+
+#[derive(Debug)]
+enum Accumulator {
+    Count(i64),
+    // Sums are kept in i64 so that only the final result can overflow.
+    Sum(Option<i64>),
+    Min(Option<Value>),
+    Max(Option<Value>),
+    Avg { sum: i64, count: i64 },
+}
+
+impl Accumulator {
+    fn add(&mut self, value: Value) -> Result<()> {
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => *sum = Some(sum.unwrap_or(0) + int_value(&value)?),
+            Accumulator::Min(min) => {
+                if min.as_ref().is_none_or(|min| value < *min) {
+                    *min = Some(value);
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.as_ref().is_none_or(|max| value > *max) {
+                    *max = Some(value);
+                }
+            }
+            Accumulator::Avg { sum, count } => {
+                *sum += int_value(&value)?;
+                *count += 1;
+            }
+        }
+        Ok(())
+    }
+    fn result(&self) -> Result<Value> {
+        match self {
+            Accumulator::Count(count) => to_int(*count),
+            Accumulator::Sum(sum) => sum.map_or(Ok(Value::Null), to_int),
+            Accumulator::Min(v) | Accumulator::Max(v) => Ok(v.clone().unwrap_or(Value::Null)),
+            // AVG of integers is truncated toward zero as there is no numeric type.
+            Accumulator::Avg { sum, count } => {
+                if *count == 0 {
+                    Ok(Value::Null)
+                } else {
+                    to_int(sum / count)
+                }
+            }
+        }
+    }
+}
+
+fn int_value(value: &Value) -> Result<i64> {
+    match value {
+        Value::Int(v) => Ok(*v as i64),
+        _ => Err(anyhow!("cannot aggregate {:?}", value)),
+    }
+}
+
+fn to_int(v: i64) -> Result<Value> {
+    i32::try_from(v)
+        .map(Value::Int)
+        .map_err(|_| anyhow!("integer out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::catalog::{Column, ColumnType, Schema};
+    use crate::execution::aggregation::{collect_aggregates, rewrite, HashAggregate};
+    use crate::parser::ast::{AggregateFunction, ExprAst};
+    use crate::storage::tuple::Tuple;
+    use crate::value::Value;
+    fn schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "name".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+            ],
+        }
+    }
+    fn aggregate(func: AggregateFunction, column: Option<&str>) -> ExprAst {
+        ExprAst::Aggregate {
+            func,
+            arg: column.map(|name| Box::new(ExprAst::Column(name.to_string()))),
+        }
+    }
+    #[test]
+    fn group_by() -> Result<()> {
+        let schema = schema();
+        let group_by = vec![ExprAst::Column("name".to_string())];
+        let aggregates = vec![
+            aggregate(AggregateFunction::Count, None),
+            aggregate(AggregateFunction::Sum, Some("id")),
+            aggregate(AggregateFunction::Min, Some("id")),
+            aggregate(AggregateFunction::Max, Some("id")),
+            aggregate(AggregateFunction::Avg, Some("id")),
+        ];
+        let mut aggregate = HashAggregate::new(&schema, &group_by, &aggregates);
+        for (id, name) in [(1, "foo"), (2, "bar"), (4, "foo"), (8, "foo")] {
+            aggregate.push(&Tuple {
+                values: vec![Value::Int(id), Value::Varchar(name.to_string())],
+            })?;
+        }
+        let ints = |values: [i32; 5]| values.iter().map(|v| Value::Int(*v)).collect::<Vec<_>>();
+        assert_eq!(
+            aggregate.finish()?,
+            vec![
+                Tuple {
+                    values: [
+                        vec![Value::Varchar("foo".to_string())],
+                        ints([3, 13, 1, 8, 4])
+                    ]
+                    .concat(),
+                },
+                Tuple {
+                    values: [
+                        vec![Value::Varchar("bar".to_string())],
+                        ints([1, 2, 2, 2, 2])
+                    ]
+                    .concat(),
+                },
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn empty_input() -> Result<()> {
+        let schema = schema();
+        let aggregates = vec![
+            aggregate(AggregateFunction::Count, Some("id")),
+            aggregate(AggregateFunction::Sum, Some("id")),
+        ];
+        let aggregate = HashAggregate::new(&schema, &[], &aggregates);
+        assert_eq!(
+            aggregate.finish()?,
+            vec![Tuple {
+                values: vec![Value::Int(0), Value::Null],
+            }]
+        );
+        let group_by = vec![ExprAst::Column("name".to_string())];
+        let aggregate = HashAggregate::new(&schema, &group_by, &aggregates);
+        assert_eq!(aggregate.finish()?, vec![]);
+        Ok(())
+    }
+    #[test]
+    fn rewrite_expression() -> Result<()> {
+        let group_by = vec![ExprAst::Column("name".to_string())];
+        let mut aggregates = Vec::new();
+        let count = aggregate(AggregateFunction::Count, None);
+        collect_aggregates(&count, &mut aggregates)?;
+        collect_aggregates(&count, &mut aggregates)?;
+        assert_eq!(aggregates, vec![count.clone()]);
+        assert_eq!(
+            rewrite(&count, &group_by, &aggregates)?,
+            ExprAst::Column("#aggregate0".to_string())
+        );
+        assert_eq!(
+            rewrite(&group_by[0], &group_by, &aggregates)?,
+            ExprAst::Column("#group0".to_string())
+        );
+        assert!(rewrite(&ExprAst::Column("id".to_string()), &group_by, &aggregates).is_err());
+        let nested = ExprAst::Aggregate {
+            func: AggregateFunction::Sum,
+            arg: Some(Box::new(count)),
+        };
+        assert!(collect_aggregates(&nested, &mut aggregates).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,475 @@
+use std::cmp::Ordering;
+
+use anyhow::Result;
+
+use crate::catalog::{ColumnType, Schema};
+use crate::parser::ast::{AggregateFunction, BinaryOperator, ExprAst, UnaryOperator};
+use crate::storage::tuple::Tuple;
+use crate::value::Value;
+
+pub fn evaluate(expr: &ExprAst, tuple: &Tuple, schema: &Schema) -> Result<Value> {
+    match expr {
+        ExprAst::Column(name) => Ok(tuple.values[schema.resolve_column(name)?].clone()),
+        ExprAst::Lit(v) => Ok(v.clone()),
+        ExprAst::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match evaluate(expr, tuple, schema)? {
+            Value::Int(v) => v
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| anyhow!("integer out of range")),
+            Value::Null => Ok(Value::Null),
+            v => Err(anyhow!("cannot negate {:?}", v)),
+        },
+        ExprAst::BinaryOp {
+            op:
+                op @ (BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div),
+            left,
+            right,
+        } => {
+            let l = evaluate(left, tuple, schema)?;
+            let r = evaluate(right, tuple, schema)?;
+            arithmetic(*op, &l, &r)
+        }
+        _ => Err(anyhow!("expected value expression")),
+    }
+}
+
+// Evaluates an expression which refers to no column, such as a value of VALUES.
+pub fn evaluate_constant(expr: &ExprAst) -> Result<Value> {
+    evaluate(
+        expr,
+        &Tuple { values: Vec::new() },
+        &Schema {
+            columns: Vec::new(),
+        },
+    )
+}
+
+pub fn check_column_references(expr: &ExprAst, schema: &Schema) -> Result<()> {
+    match expr {
+        ExprAst::Column(name) => schema.resolve_column(name).map(|_| ()),
+        ExprAst::Lit(_) => Ok(()),
+        ExprAst::UnaryOp { expr, .. } => check_column_references(expr, schema),
+        ExprAst::BinaryOp { left, right, .. } => {
+            check_column_references(left, schema)?;
+            check_column_references(right, schema)
+        }
+        ExprAst::Aggregate { arg, .. } => match arg {
+            Some(arg) => check_column_references(arg, schema),
+            None => Ok(()),
+        },
+        ExprAst::IsNull { expr, .. } => check_column_references(expr, schema),
+    }
+}
+
+// Replaces every column reference with the full name of the column it resolves to,
+// so that equal references compare equal however they are qualified.
+pub fn resolve_columns(expr: &ExprAst, schema: &Schema) -> Result<ExprAst> {
+    match expr {
+        ExprAst::Column(name) => Ok(ExprAst::Column(
+            schema.columns[schema.resolve_column(name)?].name.clone(),
+        )),
+        ExprAst::Lit(_) => Ok(expr.clone()),
+        ExprAst::UnaryOp { op, expr } => Ok(ExprAst::UnaryOp {
+            op: *op,
+            expr: Box::new(resolve_columns(expr, schema)?),
+        }),
+        ExprAst::BinaryOp { op, left, right } => Ok(ExprAst::BinaryOp {
+            op: *op,
+            left: Box::new(resolve_columns(left, schema)?),
+            right: Box::new(resolve_columns(right, schema)?),
+        }),
+        ExprAst::Aggregate { func, arg } => Ok(ExprAst::Aggregate {
+            func: *func,
+            arg: match arg {
+                Some(arg) => Some(Box::new(resolve_columns(arg, schema)?)),
+                None => None,
+            },
+        }),
+        ExprAst::IsNull { expr, negated } => Ok(ExprAst::IsNull {
+            expr: Box::new(resolve_columns(expr, schema)?),
+            negated: *negated,
+        }),
+    }
+}
+
+// Type of the value `evaluate` returns for `expr`.
+pub fn infer_type(expr: &ExprAst, schema: &Schema) -> Result<ColumnType> {
+    match expr {
+        ExprAst::Column(name) => Ok(schema.columns[schema.resolve_column(name)?]
+            .column_type
+            .clone()),
+        // NULL has no type of its own, so it is treated as text like in PostgreSQL.
+        ExprAst::Lit(Value::Varchar(_) | Value::Null) => Ok(ColumnType::Varchar),
+        ExprAst::Lit(_) => Ok(ColumnType::Int),
+        ExprAst::UnaryOp {
+            op: UnaryOperator::Minus,
+            ..
+        }
+        | ExprAst::BinaryOp {
+            op:
+                BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div,
+            ..
+        } => Ok(ColumnType::Int),
+        ExprAst::Aggregate { func, arg } => {
+            let arg_type = match arg {
+                Some(arg) => Some(infer_type(arg, schema)?),
+                None => None,
+            };
+            match (func, arg_type) {
+                (AggregateFunction::Count, _) => Ok(ColumnType::Int),
+                (AggregateFunction::Sum | AggregateFunction::Avg, Some(ColumnType::Int)) => {
+                    Ok(ColumnType::Int)
+                }
+                (AggregateFunction::Min | AggregateFunction::Max, Some(column_type)) => {
+                    Ok(column_type)
+                }
+                (func, column_type) => {
+                    Err(anyhow!("{:?} is not defined for {:?}", func, column_type))
+                }
+            }
+        }
+        _ => Err(anyhow!("expected value expression")),
+    }
+}
+
+// Rows are kept only when the condition is true, so an unknown result counts as false.
+pub fn evaluate_predicate(expr: &ExprAst, tuple: &Tuple, schema: &Schema) -> Result<bool> {
+    Ok(evaluate_condition(expr, tuple, schema)? == Some(true))
+}
+
+// Evaluates `expr` with SQL three-valued logic, where None is unknown.
+pub fn evaluate_condition(expr: &ExprAst, tuple: &Tuple, schema: &Schema) -> Result<Option<bool>> {
+    match expr {
+        ExprAst::UnaryOp {
+            op: UnaryOperator::Not,
+            expr,
+        } => Ok(evaluate_condition(expr, tuple, schema)?.map(|v| !v)),
+        ExprAst::IsNull { expr, negated } => {
+            let is_null = evaluate(expr, tuple, schema)? == Value::Null;
+            Ok(Some(is_null != *negated))
+        }
+        ExprAst::BinaryOp { op, left, right } => match op {
+            BinaryOperator::And => {
+                let l = evaluate_condition(left, tuple, schema)?;
+                if l == Some(false) {
+                    return Ok(l);
+                }
+                let r = evaluate_condition(right, tuple, schema)?;
+                Ok(match (l, r) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                })
+            }
+            BinaryOperator::Or => {
+                let l = evaluate_condition(left, tuple, schema)?;
+                if l == Some(true) {
+                    return Ok(l);
+                }
+                let r = evaluate_condition(right, tuple, schema)?;
+                Ok(match (l, r) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                })
+            }
+            _ => {
+                let l = evaluate(left, tuple, schema)?;
+                let r = evaluate(right, tuple, schema)?;
+                // A comparison with NULL is unknown.
+                if l == Value::Null || r == Value::Null {
+                    return Ok(None);
+                }
+                let ordering = compare(&l, &r)?;
+                Ok(Some(match op {
+                    BinaryOperator::Eq => ordering == Ordering::Equal,
+                    BinaryOperator::NotEq => ordering != Ordering::Equal,
+                    BinaryOperator::Lt => ordering == Ordering::Less,
+                    BinaryOperator::LtEq => ordering != Ordering::Greater,
+                    BinaryOperator::Gt => ordering == Ordering::Greater,
+                    BinaryOperator::GtEq => ordering != Ordering::Less,
+                    _ => unreachable!(),
+                }))
+            }
+        },
+        _ => Err(anyhow!("argument of WHERE must be a condition")),
+    }
+}
+
+fn arithmetic(op: BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
+    let (l, r) = match (left, right) {
+        (Value::Int(l), Value::Int(r)) => (*l, *r),
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        _ => {
+            return Err(anyhow!(
+                "cannot apply {:?} to {:?} and {:?}",
+                op,
+                left,
+                right
+            ))
+        }
+    };
+    if op == BinaryOperator::Div && r == 0 {
+        return Err(anyhow!("division by zero"));
+    }
+    let v = match op {
+        BinaryOperator::Add => l.checked_add(r),
+        BinaryOperator::Sub => l.checked_sub(r),
+        BinaryOperator::Mul => l.checked_mul(r),
+        BinaryOperator::Div => l.checked_div(r),
+        _ => unreachable!(),
+    };
+    v.map(Value::Int)
+        .ok_or_else(|| anyhow!("integer out of range"))
+}
+
+fn compare(left: &Value, right: &Value) -> Result<Ordering> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Ok(l.cmp(r)),
+        (Value::Varchar(l), Value::Varchar(r)) => Ok(l.cmp(r)),
+        _ => Err(anyhow!("cannot compare {:?} with {:?}", left, right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{Column, ColumnType, Schema};
+    use crate::execution::expression::{
+        check_column_references, evaluate, evaluate_condition, evaluate_predicate,
+    };
+    use crate::parser::ast::{BinaryOperator, ExprAst, UnaryOperator};
+    use crate::storage::tuple::Tuple;
+    use crate::value::Value;
+    fn schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "name".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+            ],
+        }
+    }
+    fn binary(op: BinaryOperator, left: ExprAst, right: ExprAst) -> ExprAst {
+        ExprAst::BinaryOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    #[test]
+    fn comparison() {
+        let tuple = Tuple {
+            values: vec![Value::Int(2), Value::Varchar("foo".to_string())],
+        };
+        let id = || ExprAst::Column("id".to_string());
+        let two = || ExprAst::Lit(Value::Int(2));
+        let cases = vec![
+            (BinaryOperator::Eq, true),
+            (BinaryOperator::NotEq, false),
+            (BinaryOperator::Lt, false),
+            (BinaryOperator::LtEq, true),
+            (BinaryOperator::Gt, false),
+            (BinaryOperator::GtEq, true),
+        ];
+        for (op, expected) in cases {
+            assert_eq!(
+                evaluate_predicate(&binary(op, id(), two()), &tuple, &schema()).unwrap(),
+                expected
+            );
+        }
+    }
+    #[test]
+    fn logical_operators() {
+        let tuple = Tuple {
+            values: vec![Value::Int(2), Value::Varchar("foo".to_string())],
+        };
+        // NOT (id = 1 OR name = 'foo') AND id > 0
+        let expr = binary(
+            BinaryOperator::And,
+            ExprAst::UnaryOp {
+                op: UnaryOperator::Not,
+                expr: Box::new(binary(
+                    BinaryOperator::Or,
+                    binary(
+                        BinaryOperator::Eq,
+                        ExprAst::Column("id".to_string()),
+                        ExprAst::Lit(Value::Int(1)),
+                    ),
+                    binary(
+                        BinaryOperator::Eq,
+                        ExprAst::Column("name".to_string()),
+                        ExprAst::Lit(Value::Varchar("foo".to_string())),
+                    ),
+                )),
+            },
+            binary(
+                BinaryOperator::Gt,
+                ExprAst::Column("id".to_string()),
+                ExprAst::Lit(Value::Int(0)),
+            ),
+        );
+        assert!(!evaluate_predicate(&expr, &tuple, &schema()).unwrap());
+    }
+    #[test]
+    fn three_valued_logic() {
+        let tuple = Tuple {
+            values: vec![Value::Null, Value::Varchar("foo".to_string())],
+        };
+        let unknown = || {
+            binary(
+                BinaryOperator::Eq,
+                ExprAst::Column("id".to_string()),
+                ExprAst::Lit(Value::Int(1)),
+            )
+        };
+        let truth = |v: bool| {
+            binary(
+                BinaryOperator::Eq,
+                ExprAst::Column("name".to_string()),
+                ExprAst::Lit(Value::Varchar(if v { "foo" } else { "bar" }.to_string())),
+            )
+        };
+        let cases = vec![
+            (unknown(), None),
+            (
+                ExprAst::UnaryOp {
+                    op: UnaryOperator::Not,
+                    expr: Box::new(unknown()),
+                },
+                None,
+            ),
+            (binary(BinaryOperator::And, unknown(), truth(true)), None),
+            (
+                binary(BinaryOperator::And, unknown(), truth(false)),
+                Some(false),
+            ),
+            (
+                binary(BinaryOperator::Or, unknown(), truth(true)),
+                Some(true),
+            ),
+            (binary(BinaryOperator::Or, truth(false), unknown()), None),
+            (
+                ExprAst::IsNull {
+                    expr: Box::new(ExprAst::Column("id".to_string())),
+                    negated: false,
+                },
+                Some(true),
+            ),
+            (
+                ExprAst::IsNull {
+                    expr: Box::new(ExprAst::Column("name".to_string())),
+                    negated: false,
+                },
+                Some(false),
+            ),
+            (
+                ExprAst::IsNull {
+                    expr: Box::new(ExprAst::Column("name".to_string())),
+                    negated: true,
+                },
+                Some(true),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                evaluate_condition(&expr, &tuple, &schema()).unwrap(),
+                expected
+            );
+            assert_eq!(
+                evaluate_predicate(&expr, &tuple, &schema()).unwrap(),
+                expected == Some(true)
+            );
+        }
+    }
+    #[test]
+    fn arithmetic() {
+        let tuple = Tuple {
+            values: vec![Value::Int(7), Value::Varchar("foo".to_string())],
+        };
+        // -id * 2 + id / 2 - 1
+        let expr = binary(
+            BinaryOperator::Sub,
+            binary(
+                BinaryOperator::Add,
+                binary(
+                    BinaryOperator::Mul,
+                    ExprAst::UnaryOp {
+                        op: UnaryOperator::Minus,
+                        expr: Box::new(ExprAst::Column("id".to_string())),
+                    },
+                    ExprAst::Lit(Value::Int(2)),
+                ),
+                binary(
+                    BinaryOperator::Div,
+                    ExprAst::Column("id".to_string()),
+                    ExprAst::Lit(Value::Int(2)),
+                ),
+            ),
+            ExprAst::Lit(Value::Int(1)),
+        );
+        assert_eq!(evaluate(&expr, &tuple, &schema()).unwrap(), Value::Int(-12));
+    }
+    #[test]
+    fn arithmetic_errors() {
+        let tuple = Tuple {
+            values: vec![Value::Int(i32::MAX), Value::Varchar("foo".to_string())],
+        };
+        let id = || ExprAst::Column("id".to_string());
+        for expr in [
+            binary(BinaryOperator::Div, id(), ExprAst::Lit(Value::Int(0))),
+            binary(BinaryOperator::Add, id(), ExprAst::Lit(Value::Int(1))),
+            binary(
+                BinaryOperator::Add,
+                id(),
+                ExprAst::Column("name".to_string()),
+            ),
+        ] {
+            assert!(evaluate(&expr, &tuple, &schema()).is_err());
+        }
+    }
+    #[test]
+    fn column_references() {
+        let expr = binary(
+            BinaryOperator::Add,
+            ExprAst::Column("id".to_string()),
+            ExprAst::Column("age".to_string()),
+        );
+        assert!(check_column_references(&expr, &schema()).is_err());
+        assert!(check_column_references(&ExprAst::Column("name".to_string()), &schema()).is_ok());
+    }
+    #[test]
+    fn unknown_column() {
+        let tuple = Tuple {
+            values: vec![Value::Int(2), Value::Varchar("foo".to_string())],
+        };
+        let expr = binary(
+            BinaryOperator::Eq,
+            ExprAst::Column("age".to_string()),
+            ExprAst::Lit(Value::Int(1)),
+        );
+        assert!(evaluate_predicate(&expr, &tuple, &schema()).is_err());
+    }
+    #[test]
+    fn type_mismatch() {
+        let tuple = Tuple {
+            values: vec![Value::Int(2), Value::Varchar("foo".to_string())],
+        };
+        let expr = binary(
+            BinaryOperator::Eq,
+            ExprAst::Column("id".to_string()),
+            ExprAst::Lit(Value::Varchar("2".to_string())),
+        );
+        assert!(evaluate_predicate(&expr, &tuple, &schema()).is_err());
+    }
+}
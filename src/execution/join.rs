@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::expression::{check_column_references, evaluate, evaluate_predicate};
+use super::operator::{BoxedOperator, Operator};
+use crate::catalog::Schema;
+use crate::parser::ast::{BinaryOperator, ExprAst, JoinType};
+use crate::storage::tuple::Tuple;
+use crate::value::Value;
+
+// Splits the conjuncts of `on` into pairs of expressions over either side that must be equal,
+// which are used as hash keys, and the rest, which is checked on every joined tuple.
+pub fn split_equi_join(
+    on: &ExprAst,
+    left_schema: &Schema,
+    right_schema: &Schema,
+    left_keys: &mut Vec<ExprAst>,
+    right_keys: &mut Vec<ExprAst>,
+    residual: &mut Vec<ExprAst>,
+) {
+    let refers_to = |expr: &ExprAst, schema: &Schema| check_column_references(expr, schema).is_ok();
+    match on {
+        ExprAst::BinaryOp {
+            op: BinaryOperator::And,
+            left,
+            right,
+        } => {
+            for expr in [left, right] {
+                split_equi_join(
+                    expr,
+                    left_schema,
+                    right_schema,
+                    left_keys,
+                    right_keys,
+                    residual,
+                );
+            }
+        }
+        ExprAst::BinaryOp {
+            op: BinaryOperator::Eq,
+            left,
+            right,
+        } if refers_to(left, left_schema) && refers_to(right, right_schema) => {
+            left_keys.push(*left.clone());
+            right_keys.push(*right.clone());
+        }
+        ExprAst::BinaryOp {
+            op: BinaryOperator::Eq,
+            left,
+            right,
+        } if refers_to(left, right_schema) && refers_to(right, left_schema) => {
+            left_keys.push(*right.clone());
+            right_keys.push(*left.clone());
+        }
+        _ => residual.push(on.clone()),
+    }
+}
+
+fn concat(left: &Tuple, right: &Tuple) -> Tuple {
+    Tuple {
+        values: [left.values.clone(), right.values.clone()].concat(),
+    }
+}
+
+fn concat_schema(left: &Schema, right: &Schema) -> Schema {
+    Schema {
+        columns: [left.columns.clone(), right.columns.clone()].concat(),
+    }
+}
+
+// Joins an unmatched tuple of a LEFT JOIN with NULLs.
+fn pad(left: Tuple, right_width: usize) -> Tuple {
+    let mut values = left.values;
+    values.resize(values.len() + right_width, Value::Null);
+    Tuple { values }
+}
+
+fn satisfies(on: &Option<ExprAst>, tuple: &Tuple, schema: &Schema) -> Result<bool> {
+    match on {
+        Some(on) => evaluate_predicate(on, tuple, schema),
+        None => Ok(true),
+    }
+}
+
+// Restarts the right child for every left tuple.
+pub struct NestedLoopJoin {
+    join_type: JoinType,
+    left: BoxedOperator,
+    right: BoxedOperator,
+    on: Option<ExprAst>,
+    schema: Schema,
+    // the left tuple being joined and whether it matched
+    current: Option<(Tuple, bool)>,
+}
+
+impl NestedLoopJoin {
+    pub fn new(
+        join_type: JoinType,
+        left: BoxedOperator,
+        right: BoxedOperator,
+        on: Option<ExprAst>,
+    ) -> Self {
+        let schema = concat_schema(left.schema(), right.schema());
+        NestedLoopJoin {
+            join_type,
+            left,
+            right,
+            on,
+            schema,
+            current: None,
+        }
+    }
+}
+
+impl Operator for NestedLoopJoin {
+    fn init(&mut self) -> Result<()> {
+        self.current = None;
+        self.left.init()
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        loop {
+            if self.current.is_none() {
+                match self.left.next()? {
+                    Some(left) => {
+                        self.right.init()?;
+                        self.current = Some((left, false));
+                    }
+                    None => return Ok(None),
+                }
+            }
+            if let Some((left, matched)) = self.current.as_mut() {
+                if let Some(right) = self.right.next()? {
+                    let tuple = concat(left, &right);
+                    if satisfies(&self.on, &tuple, &self.schema)? {
+                        *matched = true;
+                        return Ok(Some(tuple));
+                    }
+                    continue;
+                }
+            }
+            if let Some((left, matched)) = self.current.take() {
+                if !matched && self.join_type == JoinType::Left {
+                    return Ok(Some(pad(left, self.right.schema().columns.len())));
+                }
+            }
+        }
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+// Builds a hash table from the right child in `init` and probes it with every left tuple.
+pub struct HashJoin {
+    join_type: JoinType,
+    left: BoxedOperator,
+    right: BoxedOperator,
+    left_keys: Vec<ExprAst>,
+    right_keys: Vec<ExprAst>,
+    // the conditions other than the key equalities
+    residual: Option<ExprAst>,
+    schema: Schema,
+    hash_table: HashMap<Vec<Value>, Vec<Tuple>>,
+    // joined tuples of the current left tuple not returned yet, in reverse order
+    pending: Vec<Tuple>,
+}
+
+impl HashJoin {
+    pub fn new(
+        join_type: JoinType,
+        left: BoxedOperator,
+        right: BoxedOperator,
+        left_keys: Vec<ExprAst>,
+        right_keys: Vec<ExprAst>,
+        residual: Option<ExprAst>,
+    ) -> Self {
+        let schema = concat_schema(left.schema(), right.schema());
+        HashJoin {
+            join_type,
+            left,
+            right,
+            left_keys,
+            right_keys,
+            residual,
+            schema,
+            hash_table: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Operator for HashJoin {
+    fn init(&mut self) -> Result<()> {
+        self.left.init()?;
+        self.right.init()?;
+        self.hash_table.clear();
+        self.pending.clear();
+        while let Some(tuple) = self.right.next()? {
+            let keys = self
+                .right_keys
+                .iter()
+                .map(|expr| evaluate(expr, &tuple, self.right.schema()))
+                .collect::<Result<Vec<Value>>>()?;
+            // NULL never equals anything, so such tuples can only be padding.
+            if keys.contains(&Value::Null) {
+                continue;
+            }
+            self.hash_table.entry(keys).or_default().push(tuple);
+        }
+        Ok(())
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        loop {
+            if let Some(tuple) = self.pending.pop() {
+                return Ok(Some(tuple));
+            }
+            let left = match self.left.next()? {
+                Some(tuple) => tuple,
+                None => return Ok(None),
+            };
+            let keys = self
+                .left_keys
+                .iter()
+                .map(|expr| evaluate(expr, &left, self.left.schema()))
+                .collect::<Result<Vec<Value>>>()?;
+            if let Some(rights) = self.hash_table.get(&keys) {
+                for right in rights.iter() {
+                    let tuple = concat(&left, right);
+                    if satisfies(&self.residual, &tuple, &self.schema)? {
+                        self.pending.push(tuple);
+                    }
+                }
+            }
+            if self.pending.is_empty() && self.join_type == JoinType::Left {
+                return Ok(Some(pad(left, self.right.schema().columns.len())));
+            }
+            self.pending.reverse();
+        }
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,347 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::expression::{evaluate, evaluate_predicate, infer_type};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Column, Schema};
+use crate::parser::ast::ExprAst;
+use crate::storage::btree::{BTree, BTreeScan};
+use crate::storage::table::{Table, TableScan};
+use crate::storage::tuple::Tuple;
+use crate::transaction::Snapshot;
+use crate::value::Value;
+
+// An operator of a query plan, which pulls tuples from its children (the Volcano model).
+// `init` must be called before the first `next` and may be called again to restart the
+// operator. `next` returns None once the tuples run out.
+pub trait Operator {
+    fn init(&mut self) -> Result<()>;
+    fn next(&mut self) -> Result<Option<Tuple>>;
+    // Schema of the tuples returned by `next`.
+    fn schema(&self) -> &Schema;
+}
+
+pub type BoxedOperator = Box<dyn Operator>;
+
+pub struct SeqScan {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    schema: Schema,
+    first_block_number: usize,
+    snapshot: Snapshot,
+    scan: Option<TableScan>,
+}
+
+impl SeqScan {
+    pub fn new(
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        schema: Schema,
+        first_block_number: usize,
+        snapshot: Snapshot,
+    ) -> Self {
+        SeqScan {
+            buffer_pool_manager,
+            schema,
+            first_block_number,
+            snapshot,
+            scan: None,
+        }
+    }
+}
+
+impl Operator for SeqScan {
+    fn init(&mut self) -> Result<()> {
+        let table = Table::new(
+            &self.buffer_pool_manager,
+            &self.schema,
+            self.first_block_number,
+        );
+        self.scan = Some(table.scan_tuples(&self.snapshot));
+        Ok(())
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        match self.scan.as_mut().and_then(|scan| scan.next()) {
+            Some(item) => Ok(Some(item?.1)),
+            None => Ok(None),
+        }
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+// Returns the tuples whose index keys lie between `lower` and `upper`, in key order.
+// Bounds may cover only a prefix of the key columns. The index has entries for every
+// version of the tuples, of which only those visible to the snapshot are returned.
+pub struct IndexScan {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    schema: Schema,
+    first_block_number: usize,
+    snapshot: Snapshot,
+    key_schema: Schema,
+    root_block_number: usize,
+    lower: Bound<Vec<Value>>,
+    upper: Bound<Vec<Value>>,
+    scan: Option<BTreeScan>,
+}
+
+impl IndexScan {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        schema: Schema,
+        first_block_number: usize,
+        snapshot: Snapshot,
+        key_schema: Schema,
+        root_block_number: usize,
+        lower: Bound<Vec<Value>>,
+        upper: Bound<Vec<Value>>,
+    ) -> Self {
+        IndexScan {
+            buffer_pool_manager,
+            schema,
+            first_block_number,
+            snapshot,
+            key_schema,
+            root_block_number,
+            lower,
+            upper,
+            scan: None,
+        }
+    }
+}
+
+impl Operator for IndexScan {
+    fn init(&mut self) -> Result<()> {
+        let tree = BTree::new(
+            &self.buffer_pool_manager,
+            &self.key_schema,
+            self.root_block_number,
+        );
+        self.scan = Some(tree.scan(self.lower.clone(), self.upper.clone())?);
+        Ok(())
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        let table = Table::new(
+            &self.buffer_pool_manager,
+            &self.schema,
+            self.first_block_number,
+        );
+        while let Some(entry) = self.scan.as_mut().and_then(|scan| scan.next()) {
+            if let Some(tuple) = table.get_tuple(&self.snapshot, &entry?.1)? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+pub struct Filter {
+    child: BoxedOperator,
+    predicate: ExprAst,
+}
+
+impl Filter {
+    pub fn new(child: BoxedOperator, predicate: ExprAst) -> Self {
+        Filter { child, predicate }
+    }
+}
+
+impl Operator for Filter {
+    fn init(&mut self) -> Result<()> {
+        self.child.init()
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        while let Some(tuple) = self.child.next()? {
+            if evaluate_predicate(&self.predicate, &tuple, self.child.schema())? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+    fn schema(&self) -> &Schema {
+        self.child.schema()
+    }
+}
+
+pub struct Projection {
+    child: BoxedOperator,
+    exprs: Vec<ExprAst>,
+    schema: Schema,
+}
+
+impl Projection {
+    // `names` are the names of the output columns, one for each of `exprs`.
+    pub fn new(child: BoxedOperator, exprs: Vec<ExprAst>, names: Vec<String>) -> Result<Self> {
+        let mut columns = Vec::new();
+        for (expr, name) in exprs.iter().zip(names) {
+            columns.push(Column {
+                name,
+                column_type: infer_type(expr, child.schema())?,
+            });
+        }
+        Ok(Projection {
+            child,
+            exprs,
+            schema: Schema { columns },
+        })
+    }
+}
+
+impl Operator for Projection {
+    fn init(&mut self) -> Result<()> {
+        self.child.init()
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        match self.child.next()? {
+            Some(tuple) => {
+                let mut values = Vec::new();
+                for expr in self.exprs.iter() {
+                    values.push(evaluate(expr, &tuple, self.child.schema())?);
+                }
+                Ok(Some(Tuple { values }))
+            }
+            None => Ok(None),
+        }
+    }
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+// Stops pulling from its child once `limit` tuples are returned, so that a scan below
+// does not read further pages.
+pub struct Limit {
+    child: BoxedOperator,
+    limit: Option<usize>,
+    offset: usize,
+    skipped: usize,
+    produced: usize,
+}
+
+impl Limit {
+    pub fn new(child: BoxedOperator, limit: Option<usize>, offset: usize) -> Self {
+        Limit {
+            child,
+            limit,
+            offset,
+            skipped: 0,
+            produced: 0,
+        }
+    }
+}
+
+impl Operator for Limit {
+    fn init(&mut self) -> Result<()> {
+        self.skipped = 0;
+        self.produced = 0;
+        self.child.init()
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if Some(self.produced) == self.limit {
+            return Ok(None);
+        }
+        while self.skipped < self.offset {
+            if self.child.next()?.is_none() {
+                return Ok(None);
+            }
+            self.skipped += 1;
+        }
+        let tuple = self.child.next()?;
+        if tuple.is_some() {
+            self.produced += 1;
+        }
+        Ok(tuple)
+    }
+    fn schema(&self) -> &Schema {
+        self.child.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use anyhow::Result;
+
+    use super::{Limit, Operator};
+    use crate::catalog::{Column, ColumnType, Schema};
+    use crate::storage::tuple::Tuple;
+    use crate::value::Value;
+
+    // Returns the integers 0..count and counts how many times `next` is called.
+    struct Counter {
+        schema: Schema,
+        count: i32,
+        next_calls: Rc<Cell<usize>>,
+    }
+
+    impl Operator for Counter {
+        fn init(&mut self) -> Result<()> {
+            Ok(())
+        }
+        fn next(&mut self) -> Result<Option<Tuple>> {
+            let calls = self.next_calls.get();
+            self.next_calls.set(calls + 1);
+            if calls as i32 >= self.count {
+                return Ok(None);
+            }
+            Ok(Some(Tuple {
+                values: vec![Value::Int(calls as i32)],
+            }))
+        }
+        fn schema(&self) -> &Schema {
+            &self.schema
+        }
+    }
+
+    fn limit(limit: Option<usize>, offset: usize) -> (Limit, Rc<Cell<usize>>) {
+        let next_calls = Rc::new(Cell::new(0));
+        let child = Counter {
+            schema: Schema {
+                columns: vec![Column {
+                    name: "n".to_string(),
+                    column_type: ColumnType::Int,
+                }],
+            },
+            count: 100,
+            next_calls: next_calls.clone(),
+        };
+        (Limit::new(Box::new(child), limit, offset), next_calls)
+    }
+
+    fn drain(operator: &mut dyn Operator) -> Result<Vec<Value>> {
+        operator.init()?;
+        let mut values = Vec::new();
+        while let Some(mut tuple) = operator.next()? {
+            values.push(tuple.values.remove(0));
+        }
+        // Pulling again after the end keeps returning None.
+        assert_eq!(operator.next()?, None);
+        Ok(values)
+    }
+
+    #[test]
+    fn limit_stops_pulling() -> Result<()> {
+        let (mut operator, next_calls) = limit(Some(3), 5);
+        assert_eq!(
+            drain(&mut operator)?,
+            vec![Value::Int(5), Value::Int(6), Value::Int(7)]
+        );
+        // Only the skipped and the returned tuples are pulled from the child.
+        assert_eq!(next_calls.get(), 8);
+        let (mut operator, next_calls) = limit(Some(0), 0);
+        assert_eq!(drain(&mut operator)?, vec![]);
+        assert_eq!(next_calls.get(), 0);
+        // Without LIMIT the child is read to the end.
+        let (mut operator, next_calls) = limit(None, 95);
+        assert_eq!(drain(&mut operator)?.len(), 5);
+        assert_eq!(next_calls.get(), 102);
+        Ok(())
+    }
+}
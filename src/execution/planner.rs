@@ -0,0 +1,553 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::aggregation::{collect_aggregates, contains_aggregate, rewrite, Aggregate};
+use super::expression::{check_column_references, resolve_columns};
+use super::join::{split_equi_join, HashJoin, NestedLoopJoin};
+use super::operator::{BoxedOperator, Filter, IndexScan, Limit, Projection, SeqScan};
+use super::sort::Sort;
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Catalog, Column, ColumnType, Schema};
+use crate::parser::ast::{
+    BinaryOperator, ExprAst, OrderByItemAst, SelectItemAst, SelectStmtAst, TableRefAst,
+};
+use crate::transaction::{Snapshot, Transaction};
+use crate::value::Value;
+
+// Builds the operator tree of a SELECT statement, which is, from the bottom,
+// FROM -> WHERE -> GROUP BY -> HAVING -> ORDER BY -> LIMIT -> the select list.
+pub fn plan_select(
+    ast: &SelectStmtAst,
+    txn: &Transaction,
+    catalog: &Catalog,
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+) -> Result<BoxedOperator> {
+    let mut plan = plan_table_ref(&ast.from, txn, catalog, buffer_pool_manager)?;
+    let schema = plan.schema().clone();
+
+    let (mut exprs, names) = plan_select_list(&ast.select_list, &schema)?;
+    let mut where_clause = None;
+    if let Some(expr) = &ast.where_clause {
+        if contains_aggregate(expr) {
+            return Err(anyhow!("aggregate functions are not allowed in WHERE"));
+        }
+        where_clause = Some(resolve_columns(expr, &schema)?);
+    }
+    let mut group_by = Vec::new();
+    for expr in ast.group_by.iter() {
+        if contains_aggregate(expr) {
+            return Err(anyhow!("aggregate functions are not allowed in GROUP BY"));
+        }
+        group_by.push(resolve_columns(expr, &schema)?);
+    }
+    let mut having = None;
+    if let Some(expr) = &ast.having {
+        having = Some(resolve_columns(expr, &schema)?);
+    }
+    let mut order_by = Vec::new();
+    for item in ast.order_by.iter() {
+        // ORDER BY may refer to an output column by its alias.
+        let alias_expr = match &item.expr {
+            ExprAst::Column(name) if schema.resolve_column(name).is_err() => ast
+                .select_list
+                .iter()
+                .find_map(|select_item| match select_item {
+                    SelectItemAst::Expr {
+                        expr,
+                        alias: Some(alias),
+                    } if alias == name => Some(expr.clone()),
+                    _ => None,
+                }),
+            _ => None,
+        };
+        let expr = alias_expr.unwrap_or_else(|| item.expr.clone());
+        order_by.push(OrderByItemAst {
+            expr: resolve_columns(&expr, &schema)?,
+            asc: item.asc,
+        });
+    }
+
+    if let Some(where_clause) = where_clause {
+        // The filter stays on top of an index scan, which may return more tuples than
+        // the WHERE clause accepts.
+        if let TableRefAst::Table { table_name, .. } = &ast.from {
+            if let Some(index_scan) = plan_index_scan(
+                table_name,
+                plan.schema(),
+                &where_clause,
+                &txn.snapshot,
+                catalog,
+                buffer_pool_manager,
+            )? {
+                plan = index_scan;
+            }
+        }
+        plan = Box::new(Filter::new(plan, where_clause));
+    }
+    let aggregating = !group_by.is_empty()
+        || having.is_some()
+        || exprs.iter().any(contains_aggregate)
+        || order_by.iter().any(|item| contains_aggregate(&item.expr));
+    if aggregating {
+        // The expressions after the aggregation are evaluated against its output,
+        // which holds the group keys followed by the aggregate values.
+        let mut aggregates = Vec::new();
+        for expr in exprs.iter() {
+            collect_aggregates(expr, &mut aggregates)?;
+        }
+        if let Some(expr) = &having {
+            collect_aggregates(expr, &mut aggregates)?;
+        }
+        for item in order_by.iter() {
+            collect_aggregates(&item.expr, &mut aggregates)?;
+        }
+        for expr in exprs.iter_mut() {
+            *expr = rewrite(expr, &group_by, &aggregates)?;
+        }
+        for item in order_by.iter_mut() {
+            item.expr = rewrite(&item.expr, &group_by, &aggregates)?;
+        }
+        let having = match &having {
+            Some(expr) => Some(rewrite(expr, &group_by, &aggregates)?),
+            None => None,
+        };
+        plan = Box::new(Aggregate::new(plan, group_by, aggregates)?);
+        if let Some(having) = having {
+            plan = Box::new(Filter::new(plan, having));
+        }
+    }
+    if !order_by.is_empty() {
+        plan = Box::new(Sort::new(plan, buffer_pool_manager.clone(), order_by));
+    }
+    if ast.limit.is_some() || ast.offset.is_some() {
+        plan = Box::new(Limit::new(plan, ast.limit, ast.offset.unwrap_or(0)));
+    }
+    Ok(Box::new(Projection::new(plan, exprs, names)?))
+}
+
+// Returns the expressions of the select list, with the columns resolved against `schema`,
+// and the names of the output columns.
+pub fn plan_select_list(
+    select_list: &[SelectItemAst],
+    schema: &Schema,
+) -> Result<(Vec<ExprAst>, Vec<String>)> {
+    let mut names = Vec::new();
+    let mut exprs = Vec::new();
+    for select_item in select_list.iter() {
+        match select_item {
+            SelectItemAst::Wildcard => {
+                for column in schema.columns.iter() {
+                    names.push(unqualified(&column.name).to_string());
+                    exprs.push(ExprAst::Column(column.name.clone()));
+                }
+            }
+            SelectItemAst::Expr { expr, alias } => {
+                names.push(match (alias, expr) {
+                    (Some(alias), _) => alias.clone(),
+                    (None, ExprAst::Column(name)) => unqualified(name).to_string(),
+                    (None, ExprAst::Aggregate { func, .. }) => format!("{:?}", func).to_lowercase(),
+                    _ => "?column?".to_string(),
+                });
+                exprs.push(resolve_columns(expr, schema)?);
+            }
+        }
+    }
+    Ok((exprs, names))
+}
+
+// The columns of the output schema are named `table.column`, where `table` is the alias
+// of the table if it has one.
+fn plan_table_ref(
+    table_ref: &TableRefAst,
+    txn: &Transaction,
+    catalog: &Catalog,
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+) -> Result<BoxedOperator> {
+    match table_ref {
+        TableRefAst::Table { table_name, alias } => {
+            let schema = match catalog.get_schema(table_name)? {
+                Some(schema) => schema,
+                None => return Err(anyhow!("Table({}) not found", table_name)),
+            };
+            let first_block_number = match catalog.get_first_block_number(table_name)? {
+                Some(first_block_number) => first_block_number,
+                None => return Err(anyhow!("Table({}) not found", table_name)),
+            };
+            txn.lock_table_for_read(first_block_number)?;
+            let qualifier = alias.as_ref().unwrap_or(table_name);
+            let columns = schema
+                .columns
+                .into_iter()
+                .map(|column| Column {
+                    name: format!("{}.{}", qualifier, column.name),
+                    column_type: column.column_type,
+                })
+                .collect();
+            Ok(Box::new(SeqScan::new(
+                buffer_pool_manager.clone(),
+                Schema { columns },
+                first_block_number,
+                txn.snapshot.clone(),
+            )))
+        }
+        TableRefAst::Join {
+            join_type,
+            left,
+            right,
+            on,
+        } => {
+            let left = plan_table_ref(left, txn, catalog, buffer_pool_manager)?;
+            let right = plan_table_ref(right, txn, catalog, buffer_pool_manager)?;
+            if let Some(column) = right.schema().columns.first() {
+                let table_name = qualifier(&column.name);
+                if left
+                    .schema()
+                    .columns
+                    .iter()
+                    .any(|c| qualifier(&c.name) == table_name)
+                {
+                    return Err(anyhow!("Table({}) specified more than once", table_name));
+                }
+            }
+            let on = match on {
+                Some(on) => on,
+                None => return Ok(Box::new(NestedLoopJoin::new(*join_type, left, right, None))),
+            };
+            let schema = Schema {
+                columns: [
+                    left.schema().columns.clone(),
+                    right.schema().columns.clone(),
+                ]
+                .concat(),
+            };
+            check_column_references(on, &schema)?;
+            if contains_aggregate(on) {
+                return Err(anyhow!("aggregate functions are not allowed in JOIN"));
+            }
+            // Equi-joins use a hash join, anything else falls back to a nested loop.
+            let mut left_keys = Vec::new();
+            let mut right_keys = Vec::new();
+            let mut residual = Vec::new();
+            split_equi_join(
+                on,
+                left.schema(),
+                right.schema(),
+                &mut left_keys,
+                &mut right_keys,
+                &mut residual,
+            );
+            if left_keys.is_empty() {
+                return Ok(Box::new(NestedLoopJoin::new(
+                    *join_type,
+                    left,
+                    right,
+                    Some(on.clone()),
+                )));
+            }
+            let residual = residual
+                .into_iter()
+                .reduce(|left, right| ExprAst::BinaryOp {
+                    op: BinaryOperator::And,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                });
+            Ok(Box::new(HashJoin::new(
+                *join_type, left, right, left_keys, right_keys, residual,
+            )))
+        }
+    }
+}
+
+fn plan_index_scan(
+    table_name: &str,
+    schema: &Schema,
+    predicate: &ExprAst,
+    snapshot: &Snapshot,
+    catalog: &Catalog,
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+) -> Result<Option<BoxedOperator>> {
+    let range = match plan_index_range(table_name, schema, predicate, catalog)? {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    let first_block_number = match catalog.get_first_block_number(table_name)? {
+        Some(first_block_number) => first_block_number,
+        None => return Ok(None),
+    };
+    Ok(Some(Box::new(IndexScan::new(
+        buffer_pool_manager.clone(),
+        schema.clone(),
+        first_block_number,
+        snapshot.clone(),
+        range.key_schema,
+        range.root_block_number,
+        range.lower,
+        range.upper,
+    ))))
+}
+
+// The entries of an index between `lower` and `upper` point to every tuple that may
+// satisfy the predicate it was planned for.
+pub struct IndexRange {
+    pub key_schema: Schema,
+    pub root_block_number: usize,
+    pub lower: Bound<Vec<Value>>,
+    pub upper: Bound<Vec<Value>>,
+}
+
+// Picks the index whose key columns are best restricted by the conjuncts of `predicate`
+// that compare a column with a literal: equalities on a prefix of the key columns,
+// optionally followed by a range on the next one.
+pub fn plan_index_range(
+    table_name: &str,
+    schema: &Schema,
+    predicate: &ExprAst,
+    catalog: &Catalog,
+) -> Result<Option<IndexRange>> {
+    let mut conjuncts = Vec::new();
+    collect_conjuncts(predicate, &mut conjuncts);
+    // (column name, operator, literal), with the column on the left
+    let mut comparisons = Vec::new();
+    for conjunct in conjuncts {
+        if let ExprAst::BinaryOp { op, left, right } = conjunct {
+            let comparison = match (left.as_ref(), right.as_ref()) {
+                (ExprAst::Column(name), ExprAst::Lit(value)) => (name, *op, value),
+                (ExprAst::Lit(value), ExprAst::Column(name)) => match op {
+                    BinaryOperator::Lt => (name, BinaryOperator::Gt, value),
+                    BinaryOperator::LtEq => (name, BinaryOperator::GtEq, value),
+                    BinaryOperator::Gt => (name, BinaryOperator::Lt, value),
+                    BinaryOperator::GtEq => (name, BinaryOperator::LtEq, value),
+                    op => (name, *op, value),
+                },
+                _ => continue,
+            };
+            let column = &schema.columns[schema.resolve_column(comparison.0)?];
+            match (&column.column_type, comparison.2) {
+                (ColumnType::Int, Value::Int(_)) | (ColumnType::Varchar, Value::Varchar(_)) => {
+                    comparisons.push((unqualified(comparison.0), comparison.1, comparison.2))
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    let table_schema = match catalog.get_schema(table_name)? {
+        Some(schema) => schema,
+        None => return Ok(None),
+    };
+    let mut best = None;
+    for index in catalog.get_indexes(table_name)? {
+        let mut prefix = Vec::new();
+        for column_name in index.column_names.iter() {
+            match comparisons
+                .iter()
+                .find(|(name, op, _)| name == column_name && *op == BinaryOperator::Eq)
+            {
+                Some((_, _, value)) => prefix.push((*value).clone()),
+                None => break,
+            }
+        }
+        let mut lower = Bound::Included(prefix.clone());
+        let mut upper = Bound::Included(prefix.clone());
+        let mut has_range = false;
+        if let Some(column_name) = index.column_names.get(prefix.len()) {
+            let bound = |value: &Value| [prefix.clone(), vec![value.clone()]].concat();
+            for (_, op, value) in comparisons
+                .iter()
+                .filter(|(name, _, _)| name == column_name)
+            {
+                match (op, &lower, &upper) {
+                    (BinaryOperator::Gt, Bound::Included(_), _) => {
+                        lower = Bound::Excluded(bound(value))
+                    }
+                    (BinaryOperator::GtEq, Bound::Included(_), _) => {
+                        lower = Bound::Included(bound(value))
+                    }
+                    (BinaryOperator::Lt, _, Bound::Included(_)) => {
+                        upper = Bound::Excluded(bound(value))
+                    }
+                    (BinaryOperator::LtEq, _, Bound::Included(_)) => {
+                        upper = Bound::Included(bound(value))
+                    }
+                    _ => continue,
+                }
+                has_range = true;
+            }
+            // NULL sorts first and never satisfies a comparison.
+            if has_range && lower == Bound::Included(prefix.clone()) {
+                lower = Bound::Excluded(bound(&Value::Null));
+            }
+        }
+        if prefix.is_empty() && !has_range {
+            continue;
+        }
+        let score = (prefix.len(), has_range);
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _, _, _)| score > *best_score)
+        {
+            best = Some((score, index, lower, upper));
+        }
+    }
+
+    let (_, index, lower, upper) = match best {
+        Some(best) => best,
+        None => return Ok(None),
+    };
+    Ok(Some(IndexRange {
+        key_schema: index.key_schema(&table_schema)?,
+        root_block_number: index.root_block_number,
+        lower,
+        upper,
+    }))
+}
+
+fn collect_conjuncts<'a>(expr: &'a ExprAst, conjuncts: &mut Vec<&'a ExprAst>) {
+    match expr {
+        ExprAst::BinaryOp {
+            op: BinaryOperator::And,
+            left,
+            right,
+        } => {
+            collect_conjuncts(left, conjuncts);
+            collect_conjuncts(right, conjuncts);
+        }
+        _ => conjuncts.push(expr),
+    }
+}
+
+fn qualifier(name: &str) -> &str {
+    name.split_once('.')
+        .map_or("", |(table_name, _)| table_name)
+}
+
+// Output name of a column, which drops the table name of a qualified column.
+fn unqualified(name: &str) -> &str {
+    name.split_once('.').map_or(name, |(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{open_catalog, Catalog, Column, ColumnType, Schema};
+    use crate::execution::planner::plan_select;
+    use crate::parser::token::tokenize;
+    use crate::parser::{Parser, Stmt};
+    use crate::storage::table::Table;
+    use crate::storage::tuple::Tuple;
+    use crate::transaction::{Transaction, TransactionManager};
+    use crate::value::Value;
+    use anyhow::Result;
+    use std::sync::Arc;
+    fn query(
+        sql: &str,
+        txn: &Transaction,
+        catalog: &Catalog,
+        buffer_pool_manager: &Arc<BufferPoolManager>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let tokens = tokenize(&mut sql.chars().peekable())?;
+        let ast = match Parser::new(tokens).parse()? {
+            Stmt::SelectStmt(ast) => ast,
+            stmt => panic!("unexpected statement {:?}", stmt),
+        };
+        let mut plan = plan_select(&ast, txn, catalog, buffer_pool_manager)?;
+        plan.init()?;
+        let mut ret = Vec::new();
+        while let Some(tuple) = plan.next()? {
+            ret.push(tuple.values);
+        }
+        Ok(ret)
+    }
+    #[test]
+    fn select_with_join_and_aggregation() -> Result<()> {
+        let catalog = open_catalog("tmp/select_with_join_and_aggregation/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let txn_id = transaction_manager.begin();
+        let txn = transaction_manager.transaction(txn_id)?;
+        let column = |name: &str, column_type| Column {
+            name: name.to_string(),
+            column_type,
+        };
+        let users = Schema {
+            columns: vec![
+                column("id", ColumnType::Int),
+                column("name", ColumnType::Varchar),
+            ],
+        };
+        let posts = Schema {
+            columns: vec![
+                column("id", ColumnType::Int),
+                column("user_id", ColumnType::Int),
+            ],
+        };
+        catalog.create_table(txn_id, "users", &users)?;
+        catalog.create_table(txn_id, "posts", &posts)?;
+        let insert = |table_name: &str, schema: &Schema, values: Vec<Value>| -> Result<()> {
+            let first_block_number = catalog.get_first_block_number(table_name)?.unwrap();
+            Table::new(&buffer_pool_manager, schema, first_block_number)
+                .insert_tuple(txn_id, Tuple { values })?;
+            Ok(())
+        };
+        for (id, name) in [(1, "alice"), (2, "bob"), (3, "carol")] {
+            insert(
+                "users",
+                &users,
+                vec![Value::Int(id), Value::Varchar(name.to_string())],
+            )?;
+        }
+        for (id, user_id) in [(10, 1), (11, 1), (12, 2)] {
+            insert("posts", &posts, vec![Value::Int(id), Value::Int(user_id)])?;
+        }
+        let name = |name: &str| Value::Varchar(name.to_string());
+
+        let ret = query(
+            "SELECT u.name, p.id FROM users u JOIN posts p ON u.id = p.user_id ORDER BY p.id DESC LIMIT 2;",
+            &txn,
+            &catalog,
+            &buffer_pool_manager,
+        )?;
+        assert_eq!(
+            ret,
+            vec![
+                vec![name("bob"), Value::Int(12)],
+                vec![name("alice"), Value::Int(11)],
+            ]
+        );
+
+        // The nested loop join pads the unmatched user with NULL.
+        let ret = query(
+            "SELECT name, posts.id FROM users LEFT JOIN posts ON users.id = user_id AND posts.id > 10 ORDER BY name;",
+            &txn,
+            &catalog,
+            &buffer_pool_manager,
+        )?;
+        assert_eq!(
+            ret,
+            vec![
+                vec![name("alice"), Value::Int(11)],
+                vec![name("bob"), Value::Int(12)],
+                vec![name("carol"), Value::Null],
+            ]
+        );
+
+        let ret = query(
+            "SELECT name, COUNT(p.id) AS c FROM users, posts p WHERE users.id < p.user_id GROUP BY name HAVING COUNT(*) > 0 ORDER BY c;",
+            &txn,
+            &catalog,
+            &buffer_pool_manager,
+        )?;
+        assert_eq!(ret, vec![vec![name("alice"), Value::Int(1)]]);
+
+        assert!(query(
+            "SELECT id FROM users, posts;",
+            &txn,
+            &catalog,
+            &buffer_pool_manager
+        )
+        .is_err());
+        Ok(())
+    }
+}
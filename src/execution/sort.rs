@@ -0,0 +1,495 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::Write;
+use std::sync::Arc;
+use std::vec;
+
+use anyhow::Result;
+use log::error;
+
+use super::expression::evaluate;
+use super::operator::{BoxedOperator, Operator};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::Schema;
+use crate::disk::PAGE_SIZE;
+use crate::parser::ast::OrderByItemAst;
+use crate::storage::page::TablePage;
+use crate::storage::table::{Table, TableScan};
+use crate::storage::tuple::Tuple;
+use crate::value::Value;
+
+pub const SORT_MEMORY_SIZE: usize = 4 * PAGE_SIZE;
+
+// External merge sort.
+// Tuples are buffered until they exceed `memory_size` bytes, then the buffer is sorted and
+// written out as a run, which is a chain of table pages allocated through the buffer pool.
+// The runs are merged while the sorted tuples are read.
+// The pages of the runs are freed once the sort is dropped, whether it finished or failed.
+#[derive(Debug)]
+pub struct ExternalSort {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    schema: Schema,
+    order_by: Arc<[OrderByItemAst]>,
+    memory_size: usize,
+    buffer: Vec<(Vec<Value>, Tuple)>,
+    buffer_size: usize,
+    // first block numbers of the runs
+    runs: Vec<usize>,
+    spill_pages: SpillPages,
+}
+
+impl ExternalSort {
+    pub fn new(
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        schema: Schema,
+        order_by: Vec<OrderByItemAst>,
+        memory_size: usize,
+    ) -> Self {
+        ExternalSort {
+            spill_pages: SpillPages {
+                buffer_pool_manager: buffer_pool_manager.clone(),
+                block_numbers: Vec::new(),
+            },
+            buffer_pool_manager,
+            schema,
+            order_by: order_by.into(),
+            memory_size,
+            buffer: Vec::new(),
+            buffer_size: 0,
+            runs: Vec::new(),
+        }
+    }
+    pub fn push(&mut self, tuple: Tuple) -> Result<()> {
+        let keys = sort_keys(&self.order_by, &tuple, &self.schema)?;
+        self.buffer_size += tuple.serialize()?.len();
+        self.buffer.push((keys, tuple));
+        if self.buffer_size > self.memory_size {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+    pub fn finish(mut self) -> Result<SortedTuples> {
+        if self.runs.is_empty() {
+            self.sort_buffer();
+            return Ok(SortedTuples {
+                order_by: self.order_by,
+                schema: self.schema,
+                buffer: self.buffer.into_iter(),
+                runs: Vec::new(),
+                heap: BinaryHeap::new(),
+                _spill_pages: self.spill_pages,
+            });
+        }
+        if !self.buffer.is_empty() {
+            self.write_run()?;
+        }
+        let runs = self
+            .runs
+            .iter()
+            .map(|first_block_number| {
+                Table::new(&self.buffer_pool_manager, &self.schema, *first_block_number)
+                    .scan_all_versions()
+            })
+            .collect();
+        let mut sorted_tuples = SortedTuples {
+            order_by: self.order_by,
+            schema: self.schema,
+            buffer: Vec::new().into_iter(),
+            runs,
+            heap: BinaryHeap::new(),
+            _spill_pages: self.spill_pages,
+        };
+        for run in 0..sorted_tuples.runs.len() {
+            sorted_tuples.fill(run)?;
+        }
+        Ok(sorted_tuples)
+    }
+    fn sort_buffer(&mut self) {
+        let order_by = &self.order_by;
+        self.buffer
+            .sort_by(|(a, _), (b, _)| compare_sort_keys(order_by, a, b));
+    }
+    fn write_run(&mut self) -> Result<()> {
+        self.sort_buffer();
+        let empty_page = TablePage::new().serialize()?;
+        let first_block_number = self.spill_pages.allocate(&empty_page)?;
+        let mut block_number = first_block_number;
+        let mut page = TablePage::new();
+        for (_, tuple) in self.buffer.drain(..) {
+            if page.insert_tuple(0, &tuple)?.is_some() {
+                continue;
+            }
+            let next_block_number = self.spill_pages.allocate(&empty_page)?;
+            page.header.next_block_number = next_block_number as i32;
+            write_page(&self.buffer_pool_manager, block_number, &page)?;
+            block_number = next_block_number;
+            page = TablePage::new();
+            if page.insert_tuple(0, &tuple)?.is_none() {
+                return Err(anyhow!("tuple is too large"));
+            }
+        }
+        write_page(&self.buffer_pool_manager, block_number, &page)?;
+        self.buffer_size = 0;
+        self.runs.push(first_block_number);
+        Ok(())
+    }
+}
+
+// Pages allocated for the runs, which are freed when this is dropped.
+#[derive(Debug)]
+struct SpillPages {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    block_numbers: Vec<usize>,
+}
+
+impl SpillPages {
+    fn allocate(&mut self, data: &[u8]) -> Result<usize> {
+        let res = self.buffer_pool_manager.new_spill_page(data)?;
+        self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        self.block_numbers.push(res.block_number);
+        Ok(res.block_number)
+    }
+}
+
+impl Drop for SpillPages {
+    fn drop(&mut self) {
+        for &block_number in self.block_numbers.iter() {
+            if let Err(e) = self.buffer_pool_manager.free_spill_page(block_number) {
+                error!("Failed to free a page of a sort run. Error: {:?}", e);
+            }
+        }
+    }
+}
+
+// The pages are not logged, see `BufferPoolManager::new_spill_page`.
+fn write_page(
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+    block_number: usize,
+    page: &TablePage,
+) -> Result<()> {
+    let data = page.serialize()?;
+    let res = buffer_pool_manager.fetch_page(block_number)?;
+    let mut page_data = res.data.write().unwrap();
+    page_data.clear();
+    page_data.write_all(&data)?;
+    buffer_pool_manager.unpin_frame(res.frame_id, true);
+    Ok(())
+}
+
+fn sort_keys(order_by: &[OrderByItemAst], tuple: &Tuple, schema: &Schema) -> Result<Vec<Value>> {
+    order_by
+        .iter()
+        .map(|item| evaluate(&item.expr, tuple, schema))
+        .collect()
+}
+
+fn compare_sort_keys(order_by: &[OrderByItemAst], a: &[Value], b: &[Value]) -> Ordering {
+    for (item, (a, b)) in order_by.iter().zip(a.iter().zip(b.iter())) {
+        let ordering = if item.asc { a.cmp(b) } else { b.cmp(a) };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+#[derive(Debug)]
+pub struct SortedTuples {
+    order_by: Arc<[OrderByItemAst]>,
+    schema: Schema,
+    // used when every tuple fitted in memory
+    buffer: vec::IntoIter<(Vec<Value>, Tuple)>,
+    runs: Vec<TableScan>,
+    // the smallest unread tuple of each run
+    heap: BinaryHeap<MergeEntry>,
+    // freed when the sorted tuples are dropped
+    _spill_pages: SpillPages,
+}
+
+impl SortedTuples {
+    fn fill(&mut self, run: usize) -> Result<()> {
+        if let Some(item) = self.runs[run].next() {
+            let (_, tuple) = item?;
+            let keys = sort_keys(&self.order_by, &tuple, &self.schema)?;
+            self.heap.push(MergeEntry {
+                order_by: self.order_by.clone(),
+                keys,
+                tuple,
+                run,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SortedTuples {
+    type Item = Result<Tuple>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.runs.is_empty() {
+            return self.buffer.next().map(|(_, tuple)| Ok(tuple));
+        }
+        let entry = self.heap.pop()?;
+        if let Err(e) = self.fill(entry.run) {
+            return Some(Err(e));
+        }
+        Some(Ok(entry.tuple))
+    }
+}
+
+#[derive(Debug)]
+struct MergeEntry {
+    order_by: Arc<[OrderByItemAst]>,
+    keys: Vec<Value>,
+    tuple: Tuple,
+    run: usize,
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, so the order is reversed to pop the smallest entry first.
+        compare_sort_keys(&self.order_by, &other.keys, &self.keys)
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeEntry {}
+
+// Sorts every tuple of its child with an external merge sort in `init`.
+pub struct Sort {
+    child: BoxedOperator,
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    order_by: Vec<OrderByItemAst>,
+    sorted_tuples: Option<SortedTuples>,
+}
+
+impl Sort {
+    pub fn new(
+        child: BoxedOperator,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        order_by: Vec<OrderByItemAst>,
+    ) -> Self {
+        Sort {
+            child,
+            buffer_pool_manager,
+            order_by,
+            sorted_tuples: None,
+        }
+    }
+}
+
+impl Operator for Sort {
+    fn init(&mut self) -> Result<()> {
+        self.child.init()?;
+        let mut sort = ExternalSort::new(
+            self.buffer_pool_manager.clone(),
+            self.child.schema().clone(),
+            self.order_by.clone(),
+            SORT_MEMORY_SIZE,
+        );
+        while let Some(tuple) = self.child.next()? {
+            sort.push(tuple)?;
+        }
+        self.sorted_tuples = Some(sort.finish()?);
+        Ok(())
+    }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        self.sorted_tuples
+            .as_mut()
+            .and_then(|sorted_tuples| sorted_tuples.next())
+            .transpose()
+    }
+    fn schema(&self) -> &Schema {
+        self.child.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{Column, ColumnType, Schema};
+    use crate::disk::{DiskManager, PAGE_SIZE};
+    use crate::execution::sort::ExternalSort;
+    use crate::parser::ast::{ExprAst, OrderByItemAst};
+    use crate::storage::tuple::Tuple;
+    use crate::value::Value;
+    use crate::wal::recovery::recover;
+    use anyhow::Result;
+    use std::fs;
+    use std::mem;
+    use std::sync::Arc;
+    fn schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "name".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+            ],
+        }
+    }
+    fn tuples() -> Vec<Tuple> {
+        // ids are a permutation of 0..1000, names cycle through 7 values.
+        (0..1000)
+            .map(|i| Tuple {
+                values: vec![
+                    Value::Int((i * 389) % 1000),
+                    Value::Varchar(format!("name{}", i % 7)),
+                ],
+            })
+            .collect()
+    }
+    fn sort(dir: &str, order_by: Vec<OrderByItemAst>, memory_size: usize) -> Result<Vec<Tuple>> {
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+        let mut sort = ExternalSort::new(buffer_pool_manager, schema(), order_by, memory_size);
+        for tuple in tuples() {
+            sort.push(tuple)?;
+        }
+        if memory_size < 1000 {
+            assert!(sort.run_count() > 1);
+        } else {
+            assert_eq!(sort.run_count(), 0);
+        }
+        let sorted = sort.finish()?.collect();
+        // Every page of the runs is freed once the sorted tuples are dropped.
+        let page_count = fs::metadata(format!("{}data", dir))?.len() as usize / PAGE_SIZE;
+        assert_eq!(disk_manager.free_page_count()?, page_count);
+        sorted
+    }
+    #[test]
+    fn free_runs_of_unfinished_sort() -> Result<()> {
+        let dir = "tmp/free_runs_of_unfinished_sort/";
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+        let order_by = vec![OrderByItemAst {
+            expr: ExprAst::Column("id".to_string()),
+            asc: true,
+        }];
+        let page_count = || -> Result<usize> {
+            Ok(fs::metadata(format!("{}data", dir))?.len() as usize / PAGE_SIZE)
+        };
+        let mut sort =
+            ExternalSort::new(buffer_pool_manager.clone(), schema(), order_by.clone(), 512);
+        for tuple in tuples() {
+            sort.push(tuple)?;
+        }
+        assert!(sort.run_count() > 1);
+        // The runs are not logged.
+        assert_eq!(disk_manager.log_size()?, 0);
+        drop(sort);
+        assert_eq!(disk_manager.free_page_count()?, page_count()?);
+
+        // The runs of a sort cut off by a crash are freed by the recovery.
+        let mut sort = ExternalSort::new(buffer_pool_manager.clone(), schema(), order_by, 512);
+        for tuple in tuples() {
+            sort.push(tuple)?;
+        }
+        mem::forget(sort);
+        drop(buffer_pool_manager);
+        assert!(disk_manager.free_page_count()? < page_count()?);
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager.clone());
+        recover(&buffer_pool_manager)?;
+        assert_eq!(disk_manager.free_page_count()?, page_count()?);
+        Ok(())
+    }
+    #[test]
+    fn sort_in_memory() -> Result<()> {
+        let order_by = vec![OrderByItemAst {
+            expr: ExprAst::Column("id".to_string()),
+            asc: false,
+        }];
+        let sorted = sort("tmp/sort_in_memory/", order_by, usize::MAX)?;
+        let ids: Vec<Value> = sorted.into_iter().map(|t| t.values[0].clone()).collect();
+        assert_eq!(ids, (0..1000).rev().map(Value::Int).collect::<Vec<_>>());
+        Ok(())
+    }
+    #[test]
+    fn sort_with_runs() -> Result<()> {
+        let order_by = vec![
+            OrderByItemAst {
+                expr: ExprAst::Column("name".to_string()),
+                asc: false,
+            },
+            OrderByItemAst {
+                expr: ExprAst::Column("id".to_string()),
+                asc: true,
+            },
+        ];
+        let sorted = sort("tmp/sort_with_runs/", order_by, 512)?;
+        let mut expected = tuples();
+        expected.sort_by(|a, b| {
+            b.values[1]
+                .cmp(&a.values[1])
+                .then(a.values[0].cmp(&b.values[0]))
+        });
+        assert_eq!(sorted, expected);
+        Ok(())
+    }
+    #[test]
+    fn sort_with_nulls() -> Result<()> {
+        let dir = "tmp/sort_with_nulls/";
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+        let order_by = vec![OrderByItemAst {
+            expr: ExprAst::Column("id".to_string()),
+            asc: true,
+        }];
+        let mut sort = ExternalSort::new(buffer_pool_manager, schema(), order_by, 64);
+        let tuples: Vec<Tuple> = (0..100)
+            .map(|i| Tuple {
+                values: vec![
+                    if i % 3 == 0 {
+                        Value::Null
+                    } else {
+                        Value::Int(i)
+                    },
+                    if i % 2 == 0 {
+                        Value::Null
+                    } else {
+                        Value::Varchar(format!("name{}", i))
+                    },
+                ],
+            })
+            .collect();
+        for tuple in tuples.iter() {
+            sort.push(tuple.clone())?;
+        }
+        assert!(sort.run_count() > 1);
+        let sorted = sort.finish()?.collect::<Result<Vec<Tuple>>>()?;
+        let mut expected = tuples;
+        expected.sort_by(|a, b| a.values[0].cmp(&b.values[0]));
+        assert_eq!(sorted.len(), expected.len());
+        // NULL ids are equal to each other, so only the non-NULL part has a fixed order.
+        assert_eq!(sorted[34..], expected[34..]);
+        assert!(sorted[..34].iter().all(|t| t.values[0] == Value::Null));
+        // Every page of the runs is freed once the sorted tuples are dropped.
+        let page_count = fs::metadata(format!("{}data", dir))?.len() as usize / PAGE_SIZE;
+        assert_eq!(disk_manager.free_page_count()?, page_count);
+        Ok(())
+    }
+}
@@ -1,15 +1,24 @@
 #[macro_use]
 extern crate anyhow;
+extern crate log;
 extern crate rand;
 extern crate reqwest;
+extern crate serde;
 extern crate serde_json;
+extern crate toml;
 extern crate warp;
 
 pub mod buffer;
 pub mod catalog;
 pub mod cli;
+pub mod config;
+pub mod database;
 pub mod disk;
 pub mod execution;
+pub mod lock;
 pub mod parser;
+pub mod pgwire;
 pub mod storage;
+pub mod transaction;
 pub mod value;
+pub mod wal;
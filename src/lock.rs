@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex};
+
+use anyhow::Result;
+
+use crate::storage::tuple::TupleId;
+use crate::wal::TxnId;
+
+// Modes of the locks on tables and rows. A transaction takes an intention lock on a table
+// before it locks the rows of the table in the same kind of mode.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum LockMode {
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    // Shared and IntentionExclusive at once, taken to read a table and write some of its rows.
+    SharedIntentionExclusive,
+    Exclusive,
+}
+
+impl LockMode {
+    pub fn is_compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match self {
+            IntentionShared => other != Exclusive,
+            IntentionExclusive => matches!(other, IntentionShared | IntentionExclusive),
+            Shared => matches!(other, IntentionShared | Shared),
+            SharedIntentionExclusive => other == IntentionShared,
+            Exclusive => false,
+        }
+    }
+    // Returns whether holding the mode grants `other` too.
+    fn covers(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match self {
+            IntentionShared => other == IntentionShared,
+            IntentionExclusive => matches!(other, IntentionShared | IntentionExclusive),
+            Shared => matches!(other, IntentionShared | Shared),
+            SharedIntentionExclusive => other != Exclusive,
+            Exclusive => true,
+        }
+    }
+    // The weakest mode which covers both.
+    fn combine(self, other: LockMode) -> LockMode {
+        if self.covers(other) {
+            self
+        } else if other.covers(self) {
+            other
+        } else if self == LockMode::Exclusive || other == LockMode::Exclusive {
+            LockMode::Exclusive
+        } else {
+            LockMode::SharedIntentionExclusive
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum LockTarget {
+    // A table identified by its first block number.
+    Table(usize),
+    Row(TupleId),
+}
+
+#[derive(Debug, Default)]
+struct LockTable {
+    // target -> the transactions holding a lock on it with their modes
+    granted: HashMap<LockTarget, HashMap<TxnId, LockMode>>,
+    // transaction -> the targets it holds locks on
+    held: HashMap<TxnId, HashSet<LockTarget>>,
+    // transaction -> the lock it waits for
+    waiting: HashMap<TxnId, (LockTarget, LockMode)>,
+    // transactions chosen to break a deadlock, which must abort
+    victims: HashSet<TxnId>,
+}
+
+impl LockTable {
+    fn conflicts(&self, txn_id: TxnId, target: &LockTarget, mode: LockMode) -> Vec<TxnId> {
+        self.granted
+            .get(target)
+            .map(|holders| {
+                holders
+                    .iter()
+                    .filter(|(&holder, &held)| holder != txn_id && !held.is_compatible(mode))
+                    .map(|(&holder, _)| holder)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    // Looks for a cycle through the transaction in the waits-for graph, where a waiting
+    // transaction waits for the holders of conflicting locks, and returns the youngest
+    // transaction of the cycle.
+    fn find_deadlock(&self, txn_id: TxnId) -> Option<TxnId> {
+        let mut path = vec![txn_id];
+        let mut visited = HashSet::new();
+        self.find_cycle(txn_id, &mut path, &mut visited)
+            .then(|| path.into_iter().max().unwrap())
+    }
+    fn find_cycle(
+        &self,
+        txn_id: TxnId,
+        path: &mut Vec<TxnId>,
+        visited: &mut HashSet<TxnId>,
+    ) -> bool {
+        // A victim is about to stop waiting.
+        let waiter = *path.last().unwrap();
+        if self.victims.contains(&waiter) {
+            return false;
+        }
+        let (target, mode) = match self.waiting.get(&waiter) {
+            Some(waiting) => waiting,
+            None => return false,
+        };
+        for holder in self.conflicts(waiter, target, *mode) {
+            if holder == txn_id {
+                return true;
+            }
+            if !visited.insert(holder) {
+                continue;
+            }
+            path.push(holder);
+            if self.find_cycle(txn_id, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+}
+
+// Shared and exclusive locks on tables and rows, held until the transaction ends (strict
+// two-phase locking). A transaction which would close a cycle of waits aborts the youngest
+// transaction of the cycle.
+#[derive(Debug, Default)]
+pub struct LockManager {
+    lock_table: Mutex<LockTable>,
+    released: Condvar,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Waits until the lock is granted. Fails if the transaction is chosen as the victim of a
+    // deadlock, in which case it must abort to release its locks.
+    pub fn lock(&self, txn_id: TxnId, target: LockTarget, mode: LockMode) -> Result<()> {
+        let mut lock_table = self.lock_table.lock().unwrap();
+        loop {
+            if lock_table.victims.contains(&txn_id) {
+                lock_table.waiting.remove(&txn_id);
+                return Err(anyhow!("deadlock detected"));
+            }
+            let held = lock_table
+                .granted
+                .get(&target)
+                .and_then(|holders| holders.get(&txn_id))
+                .copied();
+            let mode = held.map_or(mode, |held| held.combine(mode));
+            if held == Some(mode) {
+                return Ok(());
+            }
+            if lock_table.conflicts(txn_id, &target, mode).is_empty() {
+                lock_table.waiting.remove(&txn_id);
+                lock_table
+                    .granted
+                    .entry(target)
+                    .or_default()
+                    .insert(txn_id, mode);
+                lock_table.held.entry(txn_id).or_default().insert(target);
+                return Ok(());
+            }
+            lock_table.waiting.insert(txn_id, (target, mode));
+            if let Some(victim) = lock_table.find_deadlock(txn_id) {
+                lock_table.victims.insert(victim);
+                self.released.notify_all();
+                if victim == txn_id {
+                    continue;
+                }
+            }
+            lock_table = self.released.wait(lock_table).unwrap();
+        }
+    }
+    pub fn is_victim(&self, txn_id: TxnId) -> bool {
+        self.lock_table.lock().unwrap().victims.contains(&txn_id)
+    }
+    // Releases every lock of the ending transaction.
+    pub fn unlock_all(&self, txn_id: TxnId) {
+        let mut lock_table = self.lock_table.lock().unwrap();
+        for target in lock_table.held.remove(&txn_id).unwrap_or_default() {
+            if let Some(holders) = lock_table.granted.get_mut(&target) {
+                holders.remove(&txn_id);
+                if holders.is_empty() {
+                    lock_table.granted.remove(&target);
+                }
+            }
+        }
+        lock_table.waiting.remove(&txn_id);
+        lock_table.victims.remove(&txn_id);
+        self.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock::{LockManager, LockMode, LockTarget};
+    use crate::storage::tuple::TupleId;
+    use anyhow::Result;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    #[test]
+    fn lock_modes() {
+        use LockMode::*;
+        assert!(IntentionShared.is_compatible(SharedIntentionExclusive));
+        assert!(IntentionExclusive.is_compatible(IntentionExclusive));
+        assert!(!IntentionExclusive.is_compatible(Shared));
+        assert!(Shared.is_compatible(Shared));
+        assert!(!SharedIntentionExclusive.is_compatible(IntentionExclusive));
+        assert!(!Exclusive.is_compatible(IntentionShared));
+        assert_eq!(Shared.combine(IntentionExclusive), SharedIntentionExclusive);
+        assert_eq!(Shared.combine(IntentionShared), Shared);
+        assert_eq!(SharedIntentionExclusive.combine(Exclusive), Exclusive);
+    }
+    #[test]
+    fn lock_and_unlock() -> Result<()> {
+        let lock_manager = Arc::new(LockManager::new());
+        let table = LockTarget::Table(1);
+        let row = LockTarget::Row(TupleId {
+            block_number: 1,
+            slot_number: 0,
+        });
+        lock_manager.lock(1, table, LockMode::IntentionExclusive)?;
+        lock_manager.lock(1, row, LockMode::Exclusive)?;
+        lock_manager.lock(2, table, LockMode::IntentionExclusive)?;
+        // The reader waits until both writers end.
+        let (sender, receiver) = mpsc::channel();
+        let handle = {
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let result = lock_manager.lock(3, table, LockMode::Shared);
+                sender.send(()).unwrap();
+                result
+            })
+        };
+        lock_manager.unlock_all(1);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        lock_manager.unlock_all(2);
+        receiver.recv()?;
+        handle.join().unwrap()?;
+        // A holder upgrades its lock once the others are released.
+        lock_manager.lock(4, table, LockMode::IntentionShared)?;
+        lock_manager.unlock_all(3);
+        lock_manager.lock(4, table, LockMode::Exclusive)?;
+        Ok(())
+    }
+    #[test]
+    fn detect_deadlock() -> Result<()> {
+        let lock_manager = Arc::new(LockManager::new());
+        let row = |slot_number| {
+            LockTarget::Row(TupleId {
+                block_number: 1,
+                slot_number,
+            })
+        };
+        lock_manager.lock(1, row(0), LockMode::Exclusive)?;
+        lock_manager.lock(2, row(1), LockMode::Exclusive)?;
+        let handle = {
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let result = lock_manager.lock(2, row(0), LockMode::Shared);
+                lock_manager.unlock_all(2);
+                result
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        // The younger transaction is aborted, and the older one gets the lock.
+        lock_manager.lock(1, row(1), LockMode::Shared)?;
+        assert!(handle.join().unwrap().is_err());
+        assert!(!lock_manager.is_victim(1));
+        assert!(!lock_manager.is_victim(2));
+        Ok(())
+    }
+}
@@ -1,46 +1,174 @@
+#[macro_use]
+extern crate anyhow;
 extern crate toy_db;
 
 use std::collections::HashMap;
-use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use anyhow::Result;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use log::{error, info};
 use serde_json::json;
 use signal_hook::consts::TERM_SIGNALS;
 use signal_hook::flag;
 use signal_hook::iterator::Signals;
+use tokio::net::TcpListener;
+use warp::http::StatusCode;
 use warp::Filter;
 
 use toy_db::buffer::BufferPoolManager;
 use toy_db::catalog::Catalog;
 use toy_db::cli::Cli;
+use toy_db::config::Config;
+use toy_db::database::{sqlstate, Database};
 use toy_db::disk::DiskManager;
-use toy_db::execution::{CreateTableExecutor, Executor, InsertExecutor, SelectExecutor};
-use toy_db::parser::token;
-use toy_db::parser::{Parser, Stmt};
+use toy_db::pgwire;
+use toy_db::transaction::TransactionManager;
+
+fn command() -> Command<'static> {
+    // The settings may be given before or after the subcommand.
+    let setting = |name: &'static str, value_name: &'static str, help: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .takes_value(true)
+            .value_name(value_name)
+            .help(help)
+            .global(true)
+    };
+    Command::new("toy_db")
+        .about("A small RDBMS")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            setting(
+                "config",
+                "FILE",
+                "TOML config file [default: toy_db.toml if it exists]",
+            )
+            .short('c'),
+        )
+        .arg(setting("data-dir", "DIR", "Directory of the data files"))
+        .arg(
+            setting("pool-size", "FRAMES", "Number of pages in the buffer pool")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(setting(
+            "replacer",
+            "POLICY",
+            "Page replacement policy: lru, lru-k or clock",
+        ))
+        .arg(
+            setting(
+                "checkpoint-log-size",
+                "BYTES",
+                "Size of the log file from which a checkpoint empties it",
+            )
+            .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            setting(
+                "session-timeout",
+                "SECONDS",
+                "Time after which an idle transaction opened by BEGIN is aborted",
+            )
+            .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            setting("host", "ADDRESS", "Address the server listens on")
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(setting("port", "PORT", "Port the server listens on").value_parser(value_parser!(u16)))
+        .arg(
+            setting(
+                "pg-port",
+                "PORT",
+                "Port the server listens on for PostgreSQL clients",
+            )
+            .value_parser(value_parser!(u16)),
+        )
+        .arg(setting(
+            "log-level",
+            "LEVEL",
+            "off, error, warn, info, debug or trace",
+        ))
+        .subcommand(Command::new("init").about("Creates empty data files"))
+        .subcommand(Command::new("start").about("Starts the server"))
+        .subcommand(Command::new("cli").about("Runs queries against the server"))
+}
+
+// The flags override the config file.
+fn load_config(matches: &ArgMatches) -> Result<Config> {
+    let mut config = Config::load(matches.get_one::<String>("config").map(Path::new))?;
+    if let Some(data_dir) = matches.get_one::<String>("data-dir") {
+        config.data_dir = data_dir.clone();
+    }
+    if let Some(&pool_size) = matches.get_one::<usize>("pool-size") {
+        config.set_pool_size(pool_size)?;
+    }
+    if let Some(replacer) = matches.get_one::<String>("replacer") {
+        config.replacer_policy = replacer.parse()?;
+    }
+    if let Some(&checkpoint_log_size) = matches.get_one::<u64>("checkpoint-log-size") {
+        config.checkpoint_log_size = checkpoint_log_size;
+    }
+    if let Some(&session_timeout) = matches.get_one::<u64>("session-timeout") {
+        config.set_session_timeout(session_timeout)?;
+    }
+    if let Some(&host) = matches.get_one::<IpAddr>("host") {
+        config.host = host;
+    }
+    if let Some(&port) = matches.get_one::<u16>("port") {
+        config.port = port;
+    }
+    if let Some(&pg_port) = matches.get_one::<u16>("pg-port") {
+        config.pg_port = pg_port;
+    }
+    if let Some(log_level) = matches.get_one::<String>("log-level") {
+        config.set_log_level(log_level)?;
+    }
+    Ok(config)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = env::args().collect::<Vec<String>>();
-    if &*args[1] == "cli" {
-        Cli::new().start().await?;
+    let matches = command().get_matches();
+    let (subcommand, matches) = matches.subcommand().unwrap();
+    let config = load_config(matches)?;
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .init();
+    if subcommand == "cli" {
+        Cli::new(config.address()).start().await?;
         return Ok(());
     }
-    let disk_manager = Arc::new(DiskManager::new("data/".to_string()));
-    let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+    let disk_manager = Arc::new(DiskManager::new(config.data_dir.clone()));
+    let buffer_pool_manager = Arc::new(BufferPoolManager::with_pool(
+        disk_manager.clone(),
+        config.pool_size,
+        config.replacer_policy,
+    ));
     let catalog = Arc::new(Catalog::new(buffer_pool_manager.clone()));
-    match &*args[1] {
+    let transaction_manager = Arc::new(TransactionManager::with_checkpoint_log_size(
+        buffer_pool_manager.clone(),
+        config.checkpoint_log_size,
+    ));
+    match subcommand {
         "init" => {
+            fs::create_dir_all(&config.data_dir)?;
             disk_manager.init_data_file()?;
             catalog.initialize()?;
             return Ok(());
         }
         "start" => {
-            catalog.bootstrap();
+            catalog.bootstrap()?;
         }
-        _ => panic!("unknown subcomand"),
+        _ => unreachable!(),
     }
 
     // Make sure double CTRL+C and similar kills
@@ -58,56 +186,97 @@ async fn main() -> Result<()> {
     let buffer_pool_manager_for_signals = buffer_pool_manager.clone();
     thread::spawn(move || {
         for _ in signals.forever() {
-            match buffer_pool_manager_for_signals.flush_all_pages() {
+            match buffer_pool_manager_for_signals.checkpoint() {
                 Ok(()) => {
-                    println!("Succeeded to flush pages.");
+                    info!("Succeeded to flush pages.");
                 }
                 Err(e) => {
-                    eprintln!("Failed to flush pages. Error: {:?}", e);
+                    error!("Failed to flush pages. Error: {:?}", e);
                 }
             }
         }
     });
 
-    let routes = warp::post()
-        .and(warp::body::json())
-        .map(move |body: HashMap<String, String>| {
-            let catalog_clone = catalog.clone();
-            let buffer_pool_manager_clone = buffer_pool_manager.clone();
-            handle(body, catalog_clone, buffer_pool_manager_clone)
-                .unwrap_or_else(|e| format!("Error: {:?}", e))
-        });
-    warp::serve(routes).run(([127, 0, 0, 1], 3305)).await;
-    Ok(())
-}
-
-fn handle(
-    body: HashMap<String, String>,
-    catalog: Arc<Catalog>,
-    buffer_pool_manager: Arc<BufferPoolManager>,
-) -> Result<String> {
-    let query = body.get("query").unwrap();
-    let tokens = token::tokenize(&mut query.chars().peekable())?;
-    let mut parser = Parser::new(tokens);
-    let stmt = parser.parse()?;
-
-    let result = match stmt {
-        Stmt::CreateTableStmt(_) => CreateTableExecutor { stmt, catalog }.execute()?,
-        Stmt::InsertStmt(_) => InsertExecutor {
-            stmt,
-            catalog,
-            buffer_pool_manager,
+    // Aborts the transactions left open by the clients which went away without COMMIT.
+    let transaction_manager_for_sessions = transaction_manager.clone();
+    let session_timeout = config.session_timeout;
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        match transaction_manager_for_sessions.abort_idle_sessions(session_timeout) {
+            Ok(session_ids) => {
+                for session_id in session_ids {
+                    info!("Aborted the idle session {}.", session_id);
+                }
+            }
+            Err(e) => {
+                error!("Failed to abort idle sessions. Error: {:?}", e);
+            }
         }
-        .execute()?,
-        Stmt::SelectStmt(_) => SelectExecutor {
-            stmt,
-            catalog,
-            buffer_pool_manager,
+    });
+
+    let database = Arc::new(Database::new(
+        catalog,
+        buffer_pool_manager,
+        transaction_manager,
+    ));
+    let listener = TcpListener::bind(config.pg_address()).await?;
+    let database_for_pgwire = database.clone();
+    tokio::spawn(async move {
+        if let Err(e) = pgwire::serve(listener, database_for_pgwire).await {
+            error!("Failed to serve PostgreSQL clients. Error: {:?}", e);
         }
-        .execute()?,
-    };
-    let resp = json!({
-        "result": result,
     });
-    Ok(resp.to_string())
+    let routes =
+        warp::post()
+            .and(warp::body::json())
+            .and_then(move |body: HashMap<String, String>| {
+                let database = database.clone();
+                async move {
+                    // Queries may block waiting for locks, so they run off the async workers.
+                    // The reply carries the id of the session while its transaction is open:
+                    // the one issued by BEGIN, or the one of the request.
+                    let (result, session_id) = tokio::task::spawn_blocking(move || {
+                        let query = match body.get("query") {
+                            Some(query) => query,
+                            None => return (Err(anyhow!("query is missing")), None),
+                        };
+                        let session_id =
+                            body.get("session_id").map(|session_id| session_id.as_str());
+                        let result = database.execute_query(query, session_id);
+                        let session_id = database.open_session(&result, session_id);
+                        (result, session_id)
+                    })
+                    .await
+                    .unwrap_or_else(|e| (Err(anyhow!("query panicked: {}", e)), None));
+                    Ok::<_, warp::Rejection>(match result {
+                        Ok(result) => {
+                            let mut reply = result.to_json();
+                            reply["session_id"] = json!(session_id);
+                            warp::reply::with_status(warp::reply::json(&reply), StatusCode::OK)
+                        }
+                        // The SQLSTATE tells the errors apart, and a conflict with another
+                        // transaction is worth a retry.
+                        Err(e) => {
+                            let code = sqlstate(&e);
+                            let status = match code {
+                                "40001" | "40P01" => StatusCode::CONFLICT,
+                                _ => StatusCode::BAD_REQUEST,
+                            };
+                            warp::reply::with_status(
+                                warp::reply::json(&json!({
+                                    "error": {
+                                        "code": code,
+                                        "message": format!("{:#}", e),
+                                    },
+                                    "session_id": session_id,
+                                })),
+                                status,
+                            )
+                        }
+                    })
+                }
+            });
+    info!("Listening on {}", config.address());
+    warp::serve(routes).run(config.address()).await;
+    Ok(())
 }
@@ -5,13 +5,21 @@ use anyhow::Result;
 
 use self::token::Token;
 use crate::catalog::ColumnType;
+use crate::transaction::IsolationLevel;
 use crate::value::Value;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum Stmt {
     CreateTableStmt(ast::CreateTableStmtAst),
+    CreateIndexStmt(ast::CreateIndexStmtAst),
+    DropTableStmt(ast::DropTableStmtAst),
     InsertStmt(ast::InsertStmtAst),
     SelectStmt(ast::SelectStmtAst),
+    DeleteStmt(ast::DeleteStmtAst),
+    UpdateStmt(ast::UpdateStmtAst),
+    BeginStmt(ast::BeginStmtAst),
+    CommitStmt,
+    RollbackStmt,
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
@@ -62,18 +70,40 @@ impl Parser {
             Err(anyhow!("expected value"))
         }
     }
+    fn consume_count_or_err(&mut self) -> Result<usize> {
+        match self.consume_lit_or_err()? {
+            Value::Int(v) if v >= 0 => Ok(v as usize),
+            _ => Err(anyhow!("expected non-negative integer")),
+        }
+    }
+    // Parses a single statement, which may end with a semicolon.
     pub fn parse(&mut self) -> Result<Stmt> {
-        self.stmt()
+        let stmt = self.stmt()?;
+        self.consume(Token::Semicolon);
+        if !self.consume(Token::EOF) {
+            return Err(anyhow!(
+                "unexpected {:?} after the statement",
+                self.tokens[self.position]
+            ));
+        }
+        Ok(stmt)
     }
+    // The statement is chosen by its first keywords, so that its own error is reported.
     fn stmt(&mut self) -> Result<Stmt> {
-        if let Ok(ast) = self.create_table_stmt() {
-            Ok(Stmt::CreateTableStmt(ast))
-        } else if let Ok(ast) = self.insert_stmt() {
-            Ok(Stmt::InsertStmt(ast))
-        } else if let Ok(ast) = self.select_stmt() {
-            Ok(Stmt::SelectStmt(ast))
-        } else {
-            Err(anyhow!("invalid query"))
+        match (self.tokens.first(), self.tokens.get(1)) {
+            (Some(Token::KeywordCreate), Some(Token::KeywordUnique | Token::KeywordIndex)) => {
+                Ok(Stmt::CreateIndexStmt(self.create_index_stmt()?))
+            }
+            (Some(Token::KeywordCreate), _) => Ok(Stmt::CreateTableStmt(self.create_table_stmt()?)),
+            (Some(Token::KeywordDrop), _) => Ok(Stmt::DropTableStmt(self.drop_table_stmt()?)),
+            (Some(Token::KeywordInsert), _) => Ok(Stmt::InsertStmt(self.insert_stmt()?)),
+            (Some(Token::KeywordSelect), _) => Ok(Stmt::SelectStmt(self.select_stmt()?)),
+            (Some(Token::KeywordDelete), _) => Ok(Stmt::DeleteStmt(self.delete_stmt()?)),
+            (Some(Token::KeywordUpdate), _) => Ok(Stmt::UpdateStmt(self.update_stmt()?)),
+            (Some(Token::KeywordBegin | Token::KeywordCommit | Token::KeywordRollback), _) => {
+                self.transaction_stmt()
+            }
+            _ => Err(anyhow!("invalid query")),
         }
     }
     fn create_table_stmt(&mut self) -> Result<ast::CreateTableStmtAst> {
@@ -81,42 +111,182 @@ impl Parser {
         self.consume_or_err(Token::KeywordCreate)?;
         self.consume_or_err(Token::KeywordTable)?;
         let table_name = self.consume_ident_or_err()?;
-        let table_element_list = self.table_element_list()?;
+        if self.consume(Token::KeywordAs) {
+            let query = self.query()?;
+            return Ok(ast::CreateTableStmtAst {
+                table_name,
+                table_element_list: Vec::new(),
+                constraints: Vec::new(),
+                query: Some(Box::new(query)),
+            });
+        }
+        let (table_element_list, constraints) = self.table_element_list()?;
         Ok(ast::CreateTableStmtAst {
             table_name,
             table_element_list,
+            constraints,
+            query: None,
         })
     }
-    fn table_element_list(&mut self) -> Result<Vec<ast::TableElementAst>> {
+    fn table_element_list(
+        &mut self,
+    ) -> Result<(Vec<ast::TableElementAst>, Vec<ast::TableConstraintAst>)> {
         self.consume_or_err(Token::LeftParen)?;
         let mut ret: Vec<ast::TableElementAst> = Vec::new();
-        let table_element = self.table_element()?;
-        ret.push(table_element);
+        let mut constraints = Vec::new();
         loop {
-            if self.consume(Token::Comma) {
-                let table_element = self.table_element()?;
-                ret.push(table_element);
+            if let Some(constraint) = self.table_constraint()? {
+                constraints.push(constraint);
             } else {
+                let table_element = self.table_element(&mut constraints)?;
+                ret.push(table_element);
+            }
+            if !self.consume(Token::Comma) {
                 break;
             }
         }
         self.consume_or_err(Token::RightParen)?;
-        Ok(ret)
+        Ok((ret, constraints))
     }
-    fn table_element(&mut self) -> Result<ast::TableElementAst> {
+    // The PRIMARY KEY and UNIQUE after the column are added to `constraints`.
+    fn table_element(
+        &mut self,
+        constraints: &mut Vec<ast::TableConstraintAst>,
+    ) -> Result<ast::TableElementAst> {
         let column_name = self.consume_ident_or_err()?;
-        if self.consume(Token::KeywordInt) {
-            Ok(ast::TableElementAst {
-                column_name,
-                column_type: ColumnType::Int,
-            })
+        let column_type = if self.consume(Token::KeywordInt) {
+            ColumnType::Int
         } else if self.consume(Token::KeywordVarchar) {
-            Ok(ast::TableElementAst {
-                column_name,
-                column_type: ColumnType::Varchar,
-            })
+            ColumnType::Varchar
+        } else {
+            return Err(anyhow!("invalid column type"));
+        };
+        let mut not_null = false;
+        let mut default = None;
+        // given by CONSTRAINT <name> before PRIMARY KEY or UNIQUE
+        let mut name = None;
+        loop {
+            if self.consume(Token::KeywordDefault) {
+                default = Some(self.expr()?);
+            } else if self.consume(Token::KeywordConstraint) {
+                name = Some(self.consume_ident_or_err()?);
+            } else if self.consume(Token::KeywordNot) {
+                self.consume_or_err(Token::Lit(Value::Null))?;
+                not_null = true;
+            } else if self.consume(Token::Lit(Value::Null)) {
+                // nullable, which is the default
+            } else if self.consume(Token::KeywordPrimary) {
+                self.consume_or_err(Token::KeywordKey)?;
+                constraints.push(ast::TableConstraintAst {
+                    name: name.take(),
+                    primary_key: true,
+                    column_names: vec![column_name.clone()],
+                });
+            } else if self.consume(Token::KeywordUnique) {
+                constraints.push(ast::TableConstraintAst {
+                    name: name.take(),
+                    primary_key: false,
+                    column_names: vec![column_name.clone()],
+                });
+            } else {
+                break;
+            }
+        }
+        Ok(ast::TableElementAst {
+            column_name,
+            column_type,
+            not_null,
+            default,
+        })
+    }
+    // [CONSTRAINT <name>] {PRIMARY KEY | UNIQUE} (<column>, ...), or None if the element is a
+    // column.
+    fn table_constraint(&mut self) -> Result<Option<ast::TableConstraintAst>> {
+        let name = if self.consume(Token::KeywordConstraint) {
+            Some(self.consume_ident_or_err()?)
+        } else {
+            None
+        };
+        let primary_key = if self.consume(Token::KeywordPrimary) {
+            self.consume_or_err(Token::KeywordKey)?;
+            true
+        } else if self.consume(Token::KeywordUnique) {
+            false
+        } else if name.is_some() {
+            return Err(anyhow!("expected PRIMARY KEY or UNIQUE"));
+        } else {
+            return Ok(None);
+        };
+        self.consume_or_err(Token::LeftParen)?;
+        let mut column_names = vec![self.consume_ident_or_err()?];
+        while self.consume(Token::Comma) {
+            column_names.push(self.consume_ident_or_err()?);
+        }
+        self.consume_or_err(Token::RightParen)?;
+        Ok(Some(ast::TableConstraintAst {
+            name,
+            primary_key,
+            column_names,
+        }))
+    }
+    fn create_index_stmt(&mut self) -> Result<ast::CreateIndexStmtAst> {
+        self.reset_position();
+        self.consume_or_err(Token::KeywordCreate)?;
+        let unique = self.consume(Token::KeywordUnique);
+        self.consume_or_err(Token::KeywordIndex)?;
+        let index_name = self.consume_ident_or_err()?;
+        self.consume_or_err(Token::KeywordOn)?;
+        let table_name = self.consume_ident_or_err()?;
+        self.consume_or_err(Token::LeftParen)?;
+        let mut column_names = vec![self.consume_ident_or_err()?];
+        while self.consume(Token::Comma) {
+            column_names.push(self.consume_ident_or_err()?);
+        }
+        self.consume_or_err(Token::RightParen)?;
+        Ok(ast::CreateIndexStmtAst {
+            index_name,
+            table_name,
+            column_names,
+            unique,
+        })
+    }
+    fn drop_table_stmt(&mut self) -> Result<ast::DropTableStmtAst> {
+        self.reset_position();
+        self.consume_or_err(Token::KeywordDrop)?;
+        self.consume_or_err(Token::KeywordTable)?;
+        let if_exists = if self.consume(Token::KeywordIf) {
+            self.consume_or_err(Token::KeywordExists)?;
+            true
+        } else {
+            false
+        };
+        let table_name = self.consume_ident_or_err()?;
+        Ok(ast::DropTableStmtAst {
+            table_name,
+            if_exists,
+        })
+    }
+    fn transaction_stmt(&mut self) -> Result<Stmt> {
+        self.reset_position();
+        if self.consume(Token::KeywordBegin) {
+            // REPEATABLE READ unless specified
+            let mut isolation_level = IsolationLevel::RepeatableRead;
+            if self.consume(Token::KeywordIsolation) {
+                self.consume_or_err(Token::KeywordLevel)?;
+                if self.consume(Token::KeywordSerializable) {
+                    isolation_level = IsolationLevel::Serializable;
+                } else {
+                    self.consume_or_err(Token::KeywordRepeatable)?;
+                    self.consume_or_err(Token::KeywordRead)?;
+                }
+            }
+            Ok(Stmt::BeginStmt(ast::BeginStmtAst { isolation_level }))
+        } else if self.consume(Token::KeywordCommit) {
+            Ok(Stmt::CommitStmt)
+        } else if self.consume(Token::KeywordRollback) {
+            Ok(Stmt::RollbackStmt)
         } else {
-            Err(anyhow!("invalid column type"))
+            Err(anyhow!("expected BEGIN, COMMIT or ROLLBACK"))
         }
     }
     fn insert_stmt(&mut self) -> Result<ast::InsertStmtAst> {
@@ -124,33 +294,452 @@ impl Parser {
         self.consume_or_err(Token::KeywordInsert)?;
         self.consume_or_err(Token::KeywordInto)?;
         let table_name = self.consume_ident_or_err()?;
-        let values = self.table_value_constructor()?;
-        Ok(ast::InsertStmtAst { table_name, values })
+        let column_names = if self.consume(Token::LeftParen) {
+            let mut column_names = vec![self.consume_ident_or_err()?];
+            while self.consume(Token::Comma) {
+                column_names.push(self.consume_ident_or_err()?);
+            }
+            self.consume_or_err(Token::RightParen)?;
+            Some(column_names)
+        } else {
+            None
+        };
+        let source = if self.tokens[self.position] == Token::KeywordSelect {
+            ast::InsertSourceAst::Select(Box::new(self.query()?))
+        } else {
+            ast::InsertSourceAst::Values(self.table_value_constructor()?)
+        };
+        let on_conflict = if self.consume(Token::KeywordOn) {
+            Some(self.on_conflict()?)
+        } else {
+            None
+        };
+        let returning = if self.consume(Token::KeywordReturning) {
+            Some(self.select_list()?)
+        } else {
+            None
+        };
+        Ok(ast::InsertStmtAst {
+            table_name,
+            column_names,
+            source,
+            on_conflict,
+            returning,
+        })
     }
-    fn table_value_constructor(&mut self) -> Result<Vec<Value>> {
+    // ON CONFLICT [(<column name>, ...)] DO NOTHING | DO UPDATE SET <assignment>, ...
+    fn on_conflict(&mut self) -> Result<ast::OnConflictAst> {
+        self.consume_or_err(Token::KeywordConflict)?;
+        let column_names = if self.consume(Token::LeftParen) {
+            let mut column_names = vec![self.consume_ident_or_err()?];
+            while self.consume(Token::Comma) {
+                column_names.push(self.consume_ident_or_err()?);
+            }
+            self.consume_or_err(Token::RightParen)?;
+            Some(column_names)
+        } else {
+            None
+        };
+        self.consume_or_err(Token::KeywordDo)?;
+        let action = if self.consume(Token::KeywordNothing) {
+            ast::ConflictActionAst::Nothing
+        } else {
+            self.consume_or_err(Token::KeywordUpdate)?;
+            if column_names.is_none() {
+                return Err(anyhow!(
+                    "ON CONFLICT DO UPDATE requires the columns of a unique index"
+                ));
+            }
+            self.consume_or_err(Token::KeywordSet)?;
+            ast::ConflictActionAst::Update(self.assignment_list()?)
+        };
+        Ok(ast::OnConflictAst {
+            column_names,
+            action,
+        })
+    }
+    // VALUES (<expr or DEFAULT>, ...), ...
+    fn table_value_constructor(&mut self) -> Result<Vec<Vec<Option<ast::ExprAst>>>> {
         self.consume_or_err(Token::KeywordValues)?;
-        self.consume_or_err(Token::LeftParen)?;
-        let mut ret: Vec<Value> = Vec::new();
-        let value = self.consume_lit_or_err()?;
-        ret.push(value);
+        let mut rows = Vec::new();
+        loop {
+            self.consume_or_err(Token::LeftParen)?;
+            let mut row = Vec::new();
+            loop {
+                if self.consume(Token::KeywordDefault) {
+                    row.push(None);
+                } else {
+                    row.push(Some(self.expr()?));
+                }
+                if !self.consume(Token::Comma) {
+                    break;
+                }
+            }
+            self.consume_or_err(Token::RightParen)?;
+            rows.push(row);
+            if !self.consume(Token::Comma) {
+                break;
+            }
+        }
+        Ok(rows)
+    }
+    fn select_stmt(&mut self) -> Result<ast::SelectStmtAst> {
+        self.reset_position();
+        self.query()
+    }
+    // SELECT, which may also be a part of CREATE TABLE or INSERT.
+    fn query(&mut self) -> Result<ast::SelectStmtAst> {
+        self.consume_or_err(Token::KeywordSelect)?;
+        let select_list = self.select_list()?;
+        self.consume_or_err(Token::KeywordFrom)?;
+        let from = self.table_refs()?;
+        let where_clause = self.where_clause()?;
+        let group_by = self.group_by_clause()?;
+        let having = if self.consume(Token::KeywordHaving) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let order_by = self.order_by_clause()?;
+        let limit = if self.consume(Token::KeywordLimit) {
+            Some(self.consume_count_or_err()?)
+        } else {
+            None
+        };
+        let offset = if self.consume(Token::KeywordOffset) {
+            Some(self.consume_count_or_err()?)
+        } else {
+            None
+        };
+        Ok(ast::SelectStmtAst {
+            select_list,
+            from,
+            where_clause,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+    fn select_list(&mut self) -> Result<Vec<ast::SelectItemAst>> {
+        let mut ret: Vec<ast::SelectItemAst> = Vec::new();
+        let select_item = self.select_item()?;
+        ret.push(select_item);
         loop {
             if self.consume(Token::Comma) {
-                let value = self.consume_lit_or_err()?;
-                ret.push(value);
+                let select_item = self.select_item()?;
+                ret.push(select_item);
             } else {
                 break;
             }
         }
-        self.consume_or_err(Token::RightParen)?;
         Ok(ret)
     }
-    fn select_stmt(&mut self) -> Result<ast::SelectStmtAst> {
+    fn select_item(&mut self) -> Result<ast::SelectItemAst> {
+        if self.consume(Token::Asterisk) {
+            return Ok(ast::SelectItemAst::Wildcard);
+        }
+        let expr = self.expr()?;
+        let alias = if self.consume(Token::KeywordAs) {
+            Some(self.consume_ident_or_err()?)
+        } else {
+            None
+        };
+        Ok(ast::SelectItemAst::Expr { expr, alias })
+    }
+    fn table_refs(&mut self) -> Result<ast::TableRefAst> {
+        let mut ret = self.table_ref()?;
+        loop {
+            let join_type = if self.consume(Token::Comma) {
+                let right = self.table_ref()?;
+                ret = ast::TableRefAst::Join {
+                    join_type: ast::JoinType::Inner,
+                    left: Box::new(ret),
+                    right: Box::new(right),
+                    on: None,
+                };
+                continue;
+            } else if self.consume(Token::KeywordJoin) {
+                ast::JoinType::Inner
+            } else if self.consume(Token::KeywordInner) {
+                self.consume_or_err(Token::KeywordJoin)?;
+                ast::JoinType::Inner
+            } else if self.consume(Token::KeywordLeft) {
+                self.consume(Token::KeywordOuter);
+                self.consume_or_err(Token::KeywordJoin)?;
+                ast::JoinType::Left
+            } else {
+                break;
+            };
+            let right = self.table_ref()?;
+            self.consume_or_err(Token::KeywordOn)?;
+            let on = self.expr()?;
+            ret = ast::TableRefAst::Join {
+                join_type,
+                left: Box::new(ret),
+                right: Box::new(right),
+                on: Some(on),
+            };
+        }
+        Ok(ret)
+    }
+    fn table_ref(&mut self) -> Result<ast::TableRefAst> {
+        let table_name = self.consume_ident_or_err()?;
+        let alias = if self.consume(Token::KeywordAs) {
+            Some(self.consume_ident_or_err()?)
+        } else if let Token::Ident(_) = self.tokens[self.position] {
+            Some(self.consume_ident_or_err()?)
+        } else {
+            None
+        };
+        Ok(ast::TableRefAst::Table { table_name, alias })
+    }
+    fn group_by_clause(&mut self) -> Result<Vec<ast::ExprAst>> {
+        let mut ret: Vec<ast::ExprAst> = Vec::new();
+        if !self.consume(Token::KeywordGroup) {
+            return Ok(ret);
+        }
+        self.consume_or_err(Token::KeywordBy)?;
+        let expr = self.expr()?;
+        ret.push(expr);
+        loop {
+            if self.consume(Token::Comma) {
+                let expr = self.expr()?;
+                ret.push(expr);
+            } else {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+    fn order_by_clause(&mut self) -> Result<Vec<ast::OrderByItemAst>> {
+        let mut ret: Vec<ast::OrderByItemAst> = Vec::new();
+        if !self.consume(Token::KeywordOrder) {
+            return Ok(ret);
+        }
+        self.consume_or_err(Token::KeywordBy)?;
+        let order_by_item = self.order_by_item()?;
+        ret.push(order_by_item);
+        loop {
+            if self.consume(Token::Comma) {
+                let order_by_item = self.order_by_item()?;
+                ret.push(order_by_item);
+            } else {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+    fn order_by_item(&mut self) -> Result<ast::OrderByItemAst> {
+        let expr = self.expr()?;
+        let asc = !self.consume(Token::KeywordDesc);
+        if asc {
+            self.consume(Token::KeywordAsc);
+        }
+        Ok(ast::OrderByItemAst { expr, asc })
+    }
+    fn delete_stmt(&mut self) -> Result<ast::DeleteStmtAst> {
         self.reset_position();
-        self.consume_or_err(Token::KeywordSelect)?;
-        self.consume_or_err(Token::Asterisk)?;
+        self.consume_or_err(Token::KeywordDelete)?;
         self.consume_or_err(Token::KeywordFrom)?;
         let table_name = self.consume_ident_or_err()?;
-        Ok(ast::SelectStmtAst { table_name })
+        let where_clause = self.where_clause()?;
+        Ok(ast::DeleteStmtAst {
+            table_name,
+            where_clause,
+        })
+    }
+    fn update_stmt(&mut self) -> Result<ast::UpdateStmtAst> {
+        self.reset_position();
+        self.consume_or_err(Token::KeywordUpdate)?;
+        let table_name = self.consume_ident_or_err()?;
+        self.consume_or_err(Token::KeywordSet)?;
+        let assignments = self.assignment_list()?;
+        let where_clause = self.where_clause()?;
+        Ok(ast::UpdateStmtAst {
+            table_name,
+            assignments,
+            where_clause,
+        })
+    }
+    fn assignment_list(&mut self) -> Result<Vec<ast::AssignmentAst>> {
+        let mut assignments: Vec<ast::AssignmentAst> = Vec::new();
+        let assignment = self.assignment()?;
+        assignments.push(assignment);
+        loop {
+            if self.consume(Token::Comma) {
+                let assignment = self.assignment()?;
+                assignments.push(assignment);
+            } else {
+                break;
+            }
+        }
+        Ok(assignments)
+    }
+    fn assignment(&mut self) -> Result<ast::AssignmentAst> {
+        let column_name = self.consume_ident_or_err()?;
+        self.consume_or_err(Token::Equal)?;
+        let expr = self.expr()?;
+        Ok(ast::AssignmentAst { column_name, expr })
+    }
+    fn where_clause(&mut self) -> Result<Option<ast::ExprAst>> {
+        if self.consume(Token::KeywordWhere) {
+            Ok(Some(self.expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+    fn expr(&mut self) -> Result<ast::ExprAst> {
+        self.or_expr()
+    }
+    fn or_expr(&mut self) -> Result<ast::ExprAst> {
+        let mut left = self.and_expr()?;
+        while self.consume(Token::KeywordOr) {
+            let right = self.and_expr()?;
+            left = ast::ExprAst::BinaryOp {
+                op: ast::BinaryOperator::Or,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+    fn and_expr(&mut self) -> Result<ast::ExprAst> {
+        let mut left = self.not_expr()?;
+        while self.consume(Token::KeywordAnd) {
+            let right = self.not_expr()?;
+            left = ast::ExprAst::BinaryOp {
+                op: ast::BinaryOperator::And,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+    fn not_expr(&mut self) -> Result<ast::ExprAst> {
+        if self.consume(Token::KeywordNot) {
+            let expr = self.not_expr()?;
+            Ok(ast::ExprAst::UnaryOp {
+                op: ast::UnaryOperator::Not,
+                expr: Box::new(expr),
+            })
+        } else {
+            self.comparison_expr()
+        }
+    }
+    fn comparison_expr(&mut self) -> Result<ast::ExprAst> {
+        let left = self.additive_expr()?;
+        if self.consume(Token::KeywordIs) {
+            let negated = self.consume(Token::KeywordNot);
+            self.consume_or_err(Token::Lit(Value::Null))?;
+            return Ok(ast::ExprAst::IsNull {
+                expr: Box::new(left),
+                negated,
+            });
+        }
+        let op = match self.tokens[self.position] {
+            Token::Equal => ast::BinaryOperator::Eq,
+            Token::NotEqual => ast::BinaryOperator::NotEq,
+            Token::Less => ast::BinaryOperator::Lt,
+            Token::LessOrEqual => ast::BinaryOperator::LtEq,
+            Token::Greater => ast::BinaryOperator::Gt,
+            Token::GreaterOrEqual => ast::BinaryOperator::GtEq,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.additive_expr()?;
+        Ok(ast::ExprAst::BinaryOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+    fn additive_expr(&mut self) -> Result<ast::ExprAst> {
+        let mut left = self.multiplicative_expr()?;
+        loop {
+            let op = match self.tokens[self.position] {
+                Token::Plus => ast::BinaryOperator::Add,
+                Token::Minus => ast::BinaryOperator::Sub,
+                _ => return Ok(left),
+            };
+            self.position += 1;
+            let right = self.multiplicative_expr()?;
+            left = ast::ExprAst::BinaryOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+    }
+    fn multiplicative_expr(&mut self) -> Result<ast::ExprAst> {
+        let mut left = self.unary_expr()?;
+        loop {
+            let op = match self.tokens[self.position] {
+                Token::Asterisk => ast::BinaryOperator::Mul,
+                Token::Slash => ast::BinaryOperator::Div,
+                _ => return Ok(left),
+            };
+            self.position += 1;
+            let right = self.unary_expr()?;
+            left = ast::ExprAst::BinaryOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+    }
+    fn unary_expr(&mut self) -> Result<ast::ExprAst> {
+        if self.consume(Token::Minus) {
+            let expr = self.unary_expr()?;
+            Ok(ast::ExprAst::UnaryOp {
+                op: ast::UnaryOperator::Minus,
+                expr: Box::new(expr),
+            })
+        } else {
+            self.primary_expr()
+        }
+    }
+    fn primary_expr(&mut self) -> Result<ast::ExprAst> {
+        match self.tokens[self.position] {
+            Token::Ident(_) => {
+                let name = self.consume_ident_or_err()?;
+                if self.consume(Token::LeftParen) {
+                    self.function_call(&name)
+                } else if self.consume(Token::Dot) {
+                    let column_name = self.consume_ident_or_err()?;
+                    Ok(ast::ExprAst::Column(format!("{}.{}", name, column_name)))
+                } else {
+                    Ok(ast::ExprAst::Column(name))
+                }
+            }
+            Token::Lit(_) => Ok(ast::ExprAst::Lit(self.consume_lit_or_err()?)),
+            Token::LeftParen => {
+                self.position += 1;
+                let expr = self.expr()?;
+                self.consume_or_err(Token::RightParen)?;
+                Ok(expr)
+            }
+            _ => Err(anyhow!("expected expression")),
+        }
+    }
+    // The function name and the left paren are already consumed.
+    fn function_call(&mut self, name: &str) -> Result<ast::ExprAst> {
+        let func = match name.to_uppercase().as_str() {
+            "COUNT" => ast::AggregateFunction::Count,
+            "SUM" => ast::AggregateFunction::Sum,
+            "MIN" => ast::AggregateFunction::Min,
+            "MAX" => ast::AggregateFunction::Max,
+            "AVG" => ast::AggregateFunction::Avg,
+            _ => return Err(anyhow!("unknown function: {}", name)),
+        };
+        let arg = if func == ast::AggregateFunction::Count && self.consume(Token::Asterisk) {
+            None
+        } else {
+            Some(Box::new(self.expr()?))
+        };
+        self.consume_or_err(Token::RightParen)?;
+        Ok(ast::ExprAst::Aggregate { func, arg })
     }
 }
 
@@ -158,8 +747,9 @@ impl Parser {
 mod tests {
     use crate::catalog::ColumnType;
     use crate::parser::ast;
-    use crate::parser::token::Token;
+    use crate::parser::token::{tokenize, Token};
     use crate::parser::{Parser, Stmt};
+    use crate::transaction::IsolationLevel;
     use crate::value::Value;
     use anyhow::Result;
     #[test]
@@ -176,7 +766,607 @@ mod tests {
         assert_eq!(
             ret,
             Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_where() -> Result<()> {
+        // SELECT * FROM users WHERE NOT id = 1 AND (name <> 'foo' OR id >= 3);
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Asterisk,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordWhere,
+            Token::KeywordNot,
+            Token::Ident("id".to_string()),
+            Token::Equal,
+            Token::Lit(Value::Int(1)),
+            Token::KeywordAnd,
+            Token::LeftParen,
+            Token::Ident("name".to_string()),
+            Token::NotEqual,
+            Token::Lit(Value::Varchar("foo".to_string())),
+            Token::KeywordOr,
+            Token::Ident("id".to_string()),
+            Token::GreaterOrEqual,
+            Token::Lit(Value::Int(3)),
+            Token::RightParen,
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: Some(ast::ExprAst::BinaryOp {
+                    op: ast::BinaryOperator::And,
+                    left: Box::new(ast::ExprAst::UnaryOp {
+                        op: ast::UnaryOperator::Not,
+                        expr: Box::new(ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::Eq,
+                            left: Box::new(ast::ExprAst::Column("id".to_string())),
+                            right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                        }),
+                    }),
+                    right: Box::new(ast::ExprAst::BinaryOp {
+                        op: ast::BinaryOperator::Or,
+                        left: Box::new(ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::NotEq,
+                            left: Box::new(ast::ExprAst::Column("name".to_string())),
+                            right: Box::new(ast::ExprAst::Lit(Value::Varchar("foo".to_string()))),
+                        }),
+                        right: Box::new(ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::GtEq,
+                            left: Box::new(ast::ExprAst::Column("id".to_string())),
+                            right: Box::new(ast::ExprAst::Lit(Value::Int(3))),
+                        }),
+                    }),
+                }),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_is_null() -> Result<()> {
+        // SELECT * FROM users WHERE id IS NULL OR name IS NOT NULL;
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Asterisk,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordWhere,
+            Token::Ident("id".to_string()),
+            Token::KeywordIs,
+            Token::Lit(Value::Null),
+            Token::KeywordOr,
+            Token::Ident("name".to_string()),
+            Token::KeywordIs,
+            Token::KeywordNot,
+            Token::Lit(Value::Null),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: Some(ast::ExprAst::BinaryOp {
+                    op: ast::BinaryOperator::Or,
+                    left: Box::new(ast::ExprAst::IsNull {
+                        expr: Box::new(ast::ExprAst::Column("id".to_string())),
+                        negated: false,
+                    }),
+                    right: Box::new(ast::ExprAst::IsNull {
+                        expr: Box::new(ast::ExprAst::Column("name".to_string())),
+                        negated: true,
+                    }),
+                }),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_projection() -> Result<()> {
+        // SELECT id, name AS n, -id * 2 + 1 FROM users;
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Ident("id".to_string()),
+            Token::Comma,
+            Token::Ident("name".to_string()),
+            Token::KeywordAs,
+            Token::Ident("n".to_string()),
+            Token::Comma,
+            Token::Minus,
+            Token::Ident("id".to_string()),
+            Token::Asterisk,
+            Token::Lit(Value::Int(2)),
+            Token::Plus,
+            Token::Lit(Value::Int(1)),
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Column("id".to_string()),
+                        alias: None,
+                    },
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Column("name".to_string()),
+                        alias: Some("n".to_string()),
+                    },
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::Add,
+                            left: Box::new(ast::ExprAst::BinaryOp {
+                                op: ast::BinaryOperator::Mul,
+                                left: Box::new(ast::ExprAst::UnaryOp {
+                                    op: ast::UnaryOperator::Minus,
+                                    expr: Box::new(ast::ExprAst::Column("id".to_string())),
+                                }),
+                                right: Box::new(ast::ExprAst::Lit(Value::Int(2))),
+                            }),
+                            right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                        },
+                        alias: None,
+                    },
+                ],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn create_index_stmt() -> Result<()> {
+        let mut parser = Parser::new(vec![
+            Token::KeywordCreate,
+            Token::KeywordUnique,
+            Token::KeywordIndex,
+            Token::Ident("users_name".to_string()),
+            Token::KeywordOn,
+            Token::Ident("users".to_string()),
+            Token::LeftParen,
+            Token::Ident("name".to_string()),
+            Token::Comma,
+            Token::Ident("id".to_string()),
+            Token::RightParen,
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::CreateIndexStmt(ast::CreateIndexStmtAst {
+                index_name: "users_name".to_string(),
+                table_name: "users".to_string(),
+                column_names: vec!["name".to_string(), "id".to_string()],
+                unique: true,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn drop_table_stmt() -> Result<()> {
+        let mut parser = Parser::new(vec![
+            Token::KeywordDrop,
+            Token::KeywordTable,
+            Token::KeywordIf,
+            Token::KeywordExists,
+            Token::Ident("users".to_string()),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::DropTableStmt(ast::DropTableStmtAst {
+                table_name: "users".to_string(),
+                if_exists: true,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn transaction_stmt() -> Result<()> {
+        let begin = |isolation_level| Stmt::BeginStmt(ast::BeginStmtAst { isolation_level });
+        for (tokens, stmt) in [
+            (
+                vec![Token::KeywordBegin],
+                begin(IsolationLevel::RepeatableRead),
+            ),
+            (
+                vec![
+                    Token::KeywordBegin,
+                    Token::KeywordIsolation,
+                    Token::KeywordLevel,
+                    Token::KeywordSerializable,
+                ],
+                begin(IsolationLevel::Serializable),
+            ),
+            (
+                vec![
+                    Token::KeywordBegin,
+                    Token::KeywordIsolation,
+                    Token::KeywordLevel,
+                    Token::KeywordRepeatable,
+                    Token::KeywordRead,
+                ],
+                begin(IsolationLevel::RepeatableRead),
+            ),
+            (vec![Token::KeywordCommit], Stmt::CommitStmt),
+            (vec![Token::KeywordRollback], Stmt::RollbackStmt),
+        ] {
+            let mut tokens = tokens;
+            tokens.extend([Token::Semicolon, Token::EOF]);
+            assert_eq!(Parser::new(tokens).parse()?, stmt);
+        }
+        let mut parser = Parser::new(vec![
+            Token::KeywordBegin,
+            Token::KeywordIsolation,
+            Token::KeywordLevel,
+            Token::KeywordRead,
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        assert!(parser.parse().is_err());
+        Ok(())
+    }
+    #[test]
+    fn delete_stmt() -> Result<()> {
+        let mut parser = Parser::new(vec![
+            Token::KeywordDelete,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordWhere,
+            Token::Ident("id".to_string()),
+            Token::Equal,
+            Token::Lit(Value::Int(1)),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::DeleteStmt(ast::DeleteStmtAst {
                 table_name: "users".to_string(),
+                where_clause: Some(ast::ExprAst::BinaryOp {
+                    op: ast::BinaryOperator::Eq,
+                    left: Box::new(ast::ExprAst::Column("id".to_string())),
+                    right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                }),
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn update_stmt() -> Result<()> {
+        let mut parser = Parser::new(vec![
+            Token::KeywordUpdate,
+            Token::Ident("users".to_string()),
+            Token::KeywordSet,
+            Token::Ident("name".to_string()),
+            Token::Equal,
+            Token::Lit(Value::Varchar("bar".to_string())),
+            Token::Comma,
+            Token::Ident("id".to_string()),
+            Token::Equal,
+            Token::Ident("id".to_string()),
+            Token::Plus,
+            Token::Lit(Value::Int(1)),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::UpdateStmt(ast::UpdateStmtAst {
+                table_name: "users".to_string(),
+                assignments: vec![
+                    ast::AssignmentAst {
+                        column_name: "name".to_string(),
+                        expr: ast::ExprAst::Lit(Value::Varchar("bar".to_string())),
+                    },
+                    ast::AssignmentAst {
+                        column_name: "id".to_string(),
+                        expr: ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::Add,
+                            left: Box::new(ast::ExprAst::Column("id".to_string())),
+                            right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                        },
+                    },
+                ],
+                where_clause: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_order_by() -> Result<()> {
+        // SELECT * FROM users ORDER BY name DESC, id + 1, id ASC;
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Asterisk,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordOrder,
+            Token::KeywordBy,
+            Token::Ident("name".to_string()),
+            Token::KeywordDesc,
+            Token::Comma,
+            Token::Ident("id".to_string()),
+            Token::Plus,
+            Token::Lit(Value::Int(1)),
+            Token::Comma,
+            Token::Ident("id".to_string()),
+            Token::KeywordAsc,
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![
+                    ast::OrderByItemAst {
+                        expr: ast::ExprAst::Column("name".to_string()),
+                        asc: false,
+                    },
+                    ast::OrderByItemAst {
+                        expr: ast::ExprAst::BinaryOp {
+                            op: ast::BinaryOperator::Add,
+                            left: Box::new(ast::ExprAst::Column("id".to_string())),
+                            right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                        },
+                        asc: true,
+                    },
+                    ast::OrderByItemAst {
+                        expr: ast::ExprAst::Column("id".to_string()),
+                        asc: true,
+                    },
+                ],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_limit() -> Result<()> {
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Asterisk,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordLimit,
+            Token::Lit(Value::Int(10)),
+            Token::KeywordOffset,
+            Token::Lit(Value::Int(20)),
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: Some(10),
+                offset: Some(20),
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_group_by() -> Result<()> {
+        // SELECT name, COUNT(*), max(id) FROM users GROUP BY name HAVING SUM(id) > 1;
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Ident("name".to_string()),
+            Token::Comma,
+            Token::Ident("COUNT".to_string()),
+            Token::LeftParen,
+            Token::Asterisk,
+            Token::RightParen,
+            Token::Comma,
+            Token::Ident("max".to_string()),
+            Token::LeftParen,
+            Token::Ident("id".to_string()),
+            Token::RightParen,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordGroup,
+            Token::KeywordBy,
+            Token::Ident("name".to_string()),
+            Token::KeywordHaving,
+            Token::Ident("SUM".to_string()),
+            Token::LeftParen,
+            Token::Ident("id".to_string()),
+            Token::RightParen,
+            Token::Greater,
+            Token::Lit(Value::Int(1)),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Column("name".to_string()),
+                        alias: None,
+                    },
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Aggregate {
+                            func: ast::AggregateFunction::Count,
+                            arg: None,
+                        },
+                        alias: None,
+                    },
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Aggregate {
+                            func: ast::AggregateFunction::Max,
+                            arg: Some(Box::new(ast::ExprAst::Column("id".to_string()))),
+                        },
+                        alias: None,
+                    },
+                ],
+                from: ast::TableRefAst::Table {
+                    table_name: "users".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![ast::ExprAst::Column("name".to_string())],
+                having: Some(ast::ExprAst::BinaryOp {
+                    op: ast::BinaryOperator::Gt,
+                    left: Box::new(ast::ExprAst::Aggregate {
+                        func: ast::AggregateFunction::Sum,
+                        arg: Some(Box::new(ast::ExprAst::Column("id".to_string()))),
+                    }),
+                    right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                }),
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_stmt_with_join() -> Result<()> {
+        // SELECT * FROM users AS u JOIN posts p ON u.id = p.user_id LEFT JOIN tags ON id = 1, stars;
+        let mut parser = Parser::new(vec![
+            Token::KeywordSelect,
+            Token::Asterisk,
+            Token::KeywordFrom,
+            Token::Ident("users".to_string()),
+            Token::KeywordAs,
+            Token::Ident("u".to_string()),
+            Token::KeywordJoin,
+            Token::Ident("posts".to_string()),
+            Token::Ident("p".to_string()),
+            Token::KeywordOn,
+            Token::Ident("u".to_string()),
+            Token::Dot,
+            Token::Ident("id".to_string()),
+            Token::Equal,
+            Token::Ident("p".to_string()),
+            Token::Dot,
+            Token::Ident("user_id".to_string()),
+            Token::KeywordLeft,
+            Token::KeywordJoin,
+            Token::Ident("tags".to_string()),
+            Token::KeywordOn,
+            Token::Ident("id".to_string()),
+            Token::Equal,
+            Token::Lit(Value::Int(1)),
+            Token::Comma,
+            Token::Ident("stars".to_string()),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+        let ret = parser.parse()?;
+        let table = |name: &str, alias: Option<&str>| ast::TableRefAst::Table {
+            table_name: name.to_string(),
+            alias: alias.map(|alias| alias.to_string()),
+        };
+        let users_posts = ast::TableRefAst::Join {
+            join_type: ast::JoinType::Inner,
+            left: Box::new(table("users", Some("u"))),
+            right: Box::new(table("posts", Some("p"))),
+            on: Some(ast::ExprAst::BinaryOp {
+                op: ast::BinaryOperator::Eq,
+                left: Box::new(ast::ExprAst::Column("u.id".to_string())),
+                right: Box::new(ast::ExprAst::Column("p.user_id".to_string())),
+            }),
+        };
+        let with_tags = ast::TableRefAst::Join {
+            join_type: ast::JoinType::Left,
+            left: Box::new(users_posts),
+            right: Box::new(table("tags", None)),
+            on: Some(ast::ExprAst::BinaryOp {
+                op: ast::BinaryOperator::Eq,
+                left: Box::new(ast::ExprAst::Column("id".to_string())),
+                right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+            }),
+        };
+        assert_eq!(
+            ret,
+            Stmt::SelectStmt(ast::SelectStmtAst {
+                select_list: vec![ast::SelectItemAst::Wildcard],
+                from: ast::TableRefAst::Join {
+                    join_type: ast::JoinType::Inner,
+                    left: Box::new(with_tags),
+                    right: Box::new(table("stars", None)),
+                    on: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
             })
         );
         Ok(())
@@ -206,14 +1396,85 @@ mod tests {
                     ast::TableElementAst {
                         column_name: "id".to_string(),
                         column_type: ColumnType::Int,
+                        not_null: false,
+                        default: None,
                     },
                     ast::TableElementAst {
                         column_name: "name".to_string(),
                         column_type: ColumnType::Varchar,
+                        not_null: false,
+                        default: None,
                     }
-                ]
+                ],
+                constraints: vec![],
+                query: None,
+            })
+        );
+        Ok(())
+    }
+    #[test]
+    fn create_table_stmt_with_constraints() -> Result<()> {
+        let sql = "CREATE TABLE users (
+            id INT PRIMARY KEY,
+            name VARCHAR NOT NULL UNIQUE,
+            email VARCHAR NULL DEFAULT 'none',
+            CONSTRAINT users_name_email UNIQUE (name, email)
+        );";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        let ret = parser.parse()?;
+        assert_eq!(
+            ret,
+            Stmt::CreateTableStmt(ast::CreateTableStmtAst {
+                table_name: "users".to_string(),
+                table_element_list: vec![
+                    ast::TableElementAst {
+                        column_name: "id".to_string(),
+                        column_type: ColumnType::Int,
+                        not_null: false,
+                        default: None,
+                    },
+                    ast::TableElementAst {
+                        column_name: "name".to_string(),
+                        column_type: ColumnType::Varchar,
+                        not_null: true,
+                        default: None,
+                    },
+                    ast::TableElementAst {
+                        column_name: "email".to_string(),
+                        column_type: ColumnType::Varchar,
+                        not_null: false,
+                        default: Some(ast::ExprAst::Lit(Value::Varchar("none".to_string()))),
+                    },
+                ],
+                constraints: vec![
+                    ast::TableConstraintAst {
+                        name: None,
+                        primary_key: true,
+                        column_names: vec!["id".to_string()],
+                    },
+                    ast::TableConstraintAst {
+                        name: None,
+                        primary_key: false,
+                        column_names: vec!["name".to_string()],
+                    },
+                    ast::TableConstraintAst {
+                        name: Some("users_name_email".to_string()),
+                        primary_key: false,
+                        column_names: vec!["name".to_string(), "email".to_string()],
+                    },
+                ],
+                query: None,
             })
         );
+        for sql in [
+            "CREATE TABLE users (id INT NOT);",
+            "CREATE TABLE users (id INT PRIMARY);",
+            "CREATE TABLE users (id INT, CONSTRAINT users_id (id));",
+            "CREATE TABLE users (id INT, UNIQUE ());",
+        ] {
+            let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+            assert!(parser.parse().is_err(), "{}", sql);
+        }
         Ok(())
     }
     #[test]
@@ -236,9 +1497,183 @@ mod tests {
             ret,
             Stmt::InsertStmt(ast::InsertStmtAst {
                 table_name: "users".to_string(),
-                values: vec![Value::Int(1), Value::Varchar("foo".to_string()),]
+                column_names: None,
+                source: ast::InsertSourceAst::Values(vec![vec![
+                    Some(ast::ExprAst::Lit(Value::Int(1))),
+                    Some(ast::ExprAst::Lit(Value::Varchar("foo".to_string()))),
+                ]]),
+                on_conflict: None,
+                returning: None,
             })
         );
         Ok(())
     }
+    #[test]
+    fn insert_stmt_with_columns_and_rows() -> Result<()> {
+        let sql = "INSERT INTO users (id, name) VALUES (1, DEFAULT), (-2, 'b');";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        assert_eq!(
+            parser.parse()?,
+            Stmt::InsertStmt(ast::InsertStmtAst {
+                table_name: "users".to_string(),
+                column_names: Some(vec!["id".to_string(), "name".to_string()]),
+                source: ast::InsertSourceAst::Values(vec![
+                    vec![Some(ast::ExprAst::Lit(Value::Int(1))), None],
+                    vec![
+                        Some(ast::ExprAst::UnaryOp {
+                            op: ast::UnaryOperator::Minus,
+                            expr: Box::new(ast::ExprAst::Lit(Value::Int(2))),
+                        }),
+                        Some(ast::ExprAst::Lit(Value::Varchar("b".to_string()))),
+                    ],
+                ]),
+                on_conflict: None,
+                returning: None,
+            })
+        );
+        for sql in [
+            "INSERT INTO users () VALUES (1);",
+            "INSERT INTO users VALUES (1), ;",
+            "INSERT INTO users VALUES ();",
+        ] {
+            let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+            assert!(parser.parse().is_err(), "{}", sql);
+        }
+        Ok(())
+    }
+    #[test]
+    fn insert_select_and_create_table_as() -> Result<()> {
+        let query = "SELECT id, name FROM users WHERE id > 1 ORDER BY id LIMIT 2;";
+        let mut parser = Parser::new(tokenize(&mut query.chars().peekable())?);
+        let query = match parser.parse()? {
+            Stmt::SelectStmt(ast) => Box::new(ast),
+            stmt => panic!("unexpected statement {:?}", stmt),
+        };
+        let sql = "INSERT INTO archive (id, name) SELECT id, name FROM users WHERE id > 1 ORDER BY id LIMIT 2;";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        assert_eq!(
+            parser.parse()?,
+            Stmt::InsertStmt(ast::InsertStmtAst {
+                table_name: "archive".to_string(),
+                column_names: Some(vec!["id".to_string(), "name".to_string()]),
+                source: ast::InsertSourceAst::Select(query.clone()),
+                on_conflict: None,
+                returning: None,
+            })
+        );
+        let sql =
+            "CREATE TABLE archive AS SELECT id, name FROM users WHERE id > 1 ORDER BY id LIMIT 2;";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        assert_eq!(
+            parser.parse()?,
+            Stmt::CreateTableStmt(ast::CreateTableStmtAst {
+                table_name: "archive".to_string(),
+                table_element_list: vec![],
+                constraints: vec![],
+                query: Some(query),
+            })
+        );
+        for sql in [
+            "INSERT INTO archive SELECT;",
+            "CREATE TABLE archive AS (id INT);",
+        ] {
+            let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+            assert!(parser.parse().is_err(), "{}", sql);
+        }
+        Ok(())
+    }
+    #[test]
+    fn insert_stmt_with_on_conflict_and_returning() -> Result<()> {
+        let sql = "INSERT INTO users VALUES (1, 'a') ON CONFLICT (id) DO UPDATE SET name = excluded.name, visits = visits + 1 RETURNING *, id AS user_id;";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        assert_eq!(
+            parser.parse()?,
+            Stmt::InsertStmt(ast::InsertStmtAst {
+                table_name: "users".to_string(),
+                column_names: None,
+                source: ast::InsertSourceAst::Values(vec![vec![
+                    Some(ast::ExprAst::Lit(Value::Int(1))),
+                    Some(ast::ExprAst::Lit(Value::Varchar("a".to_string()))),
+                ]]),
+                on_conflict: Some(ast::OnConflictAst {
+                    column_names: Some(vec!["id".to_string()]),
+                    action: ast::ConflictActionAst::Update(vec![
+                        ast::AssignmentAst {
+                            column_name: "name".to_string(),
+                            expr: ast::ExprAst::Column("excluded.name".to_string()),
+                        },
+                        ast::AssignmentAst {
+                            column_name: "visits".to_string(),
+                            expr: ast::ExprAst::BinaryOp {
+                                op: ast::BinaryOperator::Add,
+                                left: Box::new(ast::ExprAst::Column("visits".to_string())),
+                                right: Box::new(ast::ExprAst::Lit(Value::Int(1))),
+                            },
+                        },
+                    ]),
+                }),
+                returning: Some(vec![
+                    ast::SelectItemAst::Wildcard,
+                    ast::SelectItemAst::Expr {
+                        expr: ast::ExprAst::Column("id".to_string()),
+                        alias: Some("user_id".to_string()),
+                    },
+                ]),
+            })
+        );
+        let sql = "INSERT INTO users SELECT * FROM guests ON CONFLICT DO NOTHING;";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        match parser.parse()? {
+            Stmt::InsertStmt(ast) => assert_eq!(
+                ast.on_conflict,
+                Some(ast::OnConflictAst {
+                    column_names: None,
+                    action: ast::ConflictActionAst::Nothing,
+                })
+            ),
+            stmt => panic!("unexpected statement {:?}", stmt),
+        }
+        for sql in [
+            "INSERT INTO users VALUES (1) ON CONFLICT DO UPDATE SET id = 2;",
+            "INSERT INTO users VALUES (1) ON CONFLICT (id) DO UPDATE;",
+            "INSERT INTO users VALUES (1) ON CONFLICT () DO NOTHING;",
+            "INSERT INTO users VALUES (1) RETURNING;",
+        ] {
+            let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+            assert!(parser.parse().is_err(), "{}", sql);
+        }
+        Ok(())
+    }
+    #[test]
+    fn trailing_tokens() -> Result<()> {
+        for (sql, message) in [
+            (
+                "DELETE FROM users WHER id = 1;",
+                "unexpected Ident(\"WHER\") after the statement",
+            ),
+            (
+                "UPDATE users SET age = 0 WHER id = 1;",
+                "unexpected Ident(\"WHER\") after the statement",
+            ),
+            (
+                "SELECT * FROM users LIMIT 1 2;",
+                "unexpected Lit(Int(2)) after the statement",
+            ),
+            (
+                "SELECT * FROM users; DELETE FROM users;",
+                "unexpected KeywordDelete after the statement",
+            ),
+            // The error is that of the statement the keyword begins.
+            ("DELETE users;", "expected KeywordFrom"),
+            ("DROP users;", "expected KeywordTable"),
+            ("users;", "invalid query"),
+        ] {
+            let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+            assert_eq!(parser.parse().unwrap_err().to_string(), message, "{}", sql);
+        }
+        let sql = "DELETE FROM users";
+        let mut parser = Parser::new(tokenize(&mut sql.chars().peekable())?);
+        assert!(matches!(parser.parse()?, Stmt::DeleteStmt(_)));
+        Ok(())
+    }
 }
@@ -1,22 +1,188 @@
 use crate::catalog::ColumnType;
+use crate::transaction::IsolationLevel;
 use crate::value::Value;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct CreateTableStmtAst {
     pub table_name: String,
     pub table_element_list: Vec<TableElementAst>,
+    // PRIMARY KEY and UNIQUE, whether declared after a column or apart from them
+    pub constraints: Vec<TableConstraintAst>,
+    // given by CREATE TABLE ... AS SELECT, in which case the columns are those of the query
+    // and the table is filled with its rows
+    pub query: Option<Box<SelectStmtAst>>,
 }
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct TableElementAst {
     pub column_name: String,
     pub column_type: ColumnType,
+    pub not_null: bool,
+    pub default: Option<ExprAst>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct TableConstraintAst {
+    // given by CONSTRAINT <name>
+    pub name: Option<String>,
+    // UNIQUE unless it is the primary key
+    pub primary_key: bool,
+    pub column_names: Vec<String>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct CreateIndexStmtAst {
+    pub index_name: String,
+    pub table_name: String,
+    pub column_names: Vec<String>,
+    pub unique: bool,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct DropTableStmtAst {
+    pub table_name: String,
+    pub if_exists: bool,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct BeginStmtAst {
+    pub isolation_level: IsolationLevel,
 }
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct InsertStmtAst {
     pub table_name: String,
-    pub values: Vec<Value>,
+    // The values of each row are for these columns if they are listed, and for all the
+    // columns in order otherwise.
+    pub column_names: Option<Vec<String>>,
+    pub source: InsertSourceAst,
+    pub on_conflict: Option<OnConflictAst>,
+    // the select list of RETURNING, evaluated against the rows written
+    pub returning: Option<Vec<SelectItemAst>>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum InsertSourceAst {
+    // None for DEFAULT
+    Values(Vec<Vec<Option<ExprAst>>>),
+    Select(Box<SelectStmtAst>),
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct OnConflictAst {
+    // The columns of the unique indexes whose conflicts are resolved. DO NOTHING may leave
+    // them out to resolve those of every unique index.
+    pub column_names: Option<Vec<String>>,
+    pub action: ConflictActionAst,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum ConflictActionAst {
+    Nothing,
+    // The expressions refer to the existing row by the column names, and to the row
+    // proposed for insertion by `excluded.<column name>`.
+    Update(Vec<AssignmentAst>),
 }
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct SelectStmtAst {
+    pub select_list: Vec<SelectItemAst>,
+    pub from: TableRefAst,
+    pub where_clause: Option<ExprAst>,
+    pub group_by: Vec<ExprAst>,
+    pub having: Option<ExprAst>,
+    pub order_by: Vec<OrderByItemAst>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum TableRefAst {
+    Table {
+        table_name: String,
+        alias: Option<String>,
+    },
+    // Joins are left-deep, so `right` is always a table.
+    // A comma join is an inner join without `on`.
+    Join {
+        join_type: JoinType,
+        left: Box<TableRefAst>,
+        right: Box<TableRefAst>,
+        on: Option<ExprAst>,
+    },
+}
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum JoinType {
+    Inner,
+    Left,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct OrderByItemAst {
+    pub expr: ExprAst,
+    pub asc: bool,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct DeleteStmtAst {
     pub table_name: String,
+    pub where_clause: Option<ExprAst>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct UpdateStmtAst {
+    pub table_name: String,
+    pub assignments: Vec<AssignmentAst>,
+    pub where_clause: Option<ExprAst>,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct AssignmentAst {
+    pub column_name: String,
+    pub expr: ExprAst,
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum SelectItemAst {
+    Wildcard,
+    Expr {
+        expr: ExprAst,
+        alias: Option<String>,
+    },
+}
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum ExprAst {
+    Column(String),
+    Lit(Value),
+    UnaryOp {
+        op: UnaryOperator,
+        expr: Box<ExprAst>,
+    },
+    BinaryOp {
+        op: BinaryOperator,
+        left: Box<ExprAst>,
+        right: Box<ExprAst>,
+    },
+    // `arg` is None for COUNT(*).
+    Aggregate {
+        func: AggregateFunction,
+        arg: Option<Box<ExprAst>>,
+    },
+    // `expr IS NULL`, or `expr IS NOT NULL` when `negated`.
+    IsNull {
+        expr: Box<ExprAst>,
+        negated: bool,
+    },
+}
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum UnaryOperator {
+    Not,
+    Minus,
+}
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum BinaryOperator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Add,
+    Sub,
+    Mul,
+    Div,
 }
@@ -9,11 +9,23 @@ use crate::value::Value;
 pub enum Token {
     Ident(String),
     Lit(Value),
+    // $1, $2, ..., replaced by the values bound to a prepared statement
+    Parameter(usize),
     Asterisk,
+    Plus,
+    Minus,
+    Slash,
     Semicolon,
     Comma,
+    Dot,
     LeftParen,
     RightParen,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
     KeywordCreate,
     KeywordTable,
     KeywordInsert,
@@ -23,6 +35,49 @@ pub enum Token {
     KeywordFrom,
     KeywordInt,
     KeywordVarchar,
+    KeywordWhere,
+    KeywordAnd,
+    KeywordOr,
+    KeywordNot,
+    KeywordAs,
+    KeywordDelete,
+    KeywordUpdate,
+    KeywordSet,
+    KeywordOrder,
+    KeywordBy,
+    KeywordAsc,
+    KeywordDesc,
+    KeywordLimit,
+    KeywordOffset,
+    KeywordGroup,
+    KeywordHaving,
+    KeywordJoin,
+    KeywordInner,
+    KeywordLeft,
+    KeywordOuter,
+    KeywordOn,
+    KeywordIs,
+    KeywordDrop,
+    KeywordIf,
+    KeywordExists,
+    KeywordUnique,
+    KeywordIndex,
+    KeywordBegin,
+    KeywordCommit,
+    KeywordRollback,
+    KeywordIsolation,
+    KeywordLevel,
+    KeywordSerializable,
+    KeywordRepeatable,
+    KeywordRead,
+    KeywordPrimary,
+    KeywordKey,
+    KeywordConstraint,
+    KeywordDefault,
+    KeywordConflict,
+    KeywordDo,
+    KeywordNothing,
+    KeywordReturning,
     EOF,
 }
 
@@ -37,8 +92,8 @@ pub fn tokenize(iter: &mut Peekable<Chars>) -> Result<Vec<Token>> {
                 let mut ret = String::new();
                 loop {
                     match iter.peek() {
-                        Some(cc) if '_' == *cc || cc.is_digit(10) || cc.is_alphabetic() => {
-                            ret = format!("{}{}", ret, cc.to_string());
+                        Some(cc) if '_' == *cc || cc.is_ascii_digit() || cc.is_alphabetic() => {
+                            ret = format!("{}{}", ret, cc);
                             iter.next();
                         }
                         _ => {
@@ -56,26 +111,124 @@ pub fn tokenize(iter: &mut Peekable<Chars>) -> Result<Vec<Token>> {
                     "FROM" => Token::KeywordFrom,
                     "INT" => Token::KeywordInt,
                     "VARCHAR" => Token::KeywordVarchar,
+                    "WHERE" => Token::KeywordWhere,
+                    "AND" => Token::KeywordAnd,
+                    "OR" => Token::KeywordOr,
+                    "NOT" => Token::KeywordNot,
+                    "AS" => Token::KeywordAs,
+                    "DELETE" => Token::KeywordDelete,
+                    "UPDATE" => Token::KeywordUpdate,
+                    "SET" => Token::KeywordSet,
+                    "ORDER" => Token::KeywordOrder,
+                    "BY" => Token::KeywordBy,
+                    "ASC" => Token::KeywordAsc,
+                    "DESC" => Token::KeywordDesc,
+                    "LIMIT" => Token::KeywordLimit,
+                    "OFFSET" => Token::KeywordOffset,
+                    "GROUP" => Token::KeywordGroup,
+                    "HAVING" => Token::KeywordHaving,
+                    "JOIN" => Token::KeywordJoin,
+                    "INNER" => Token::KeywordInner,
+                    "LEFT" => Token::KeywordLeft,
+                    "OUTER" => Token::KeywordOuter,
+                    "ON" => Token::KeywordOn,
+                    "IS" => Token::KeywordIs,
+                    "DROP" => Token::KeywordDrop,
+                    "IF" => Token::KeywordIf,
+                    "EXISTS" => Token::KeywordExists,
+                    "UNIQUE" => Token::KeywordUnique,
+                    "INDEX" => Token::KeywordIndex,
+                    "BEGIN" => Token::KeywordBegin,
+                    "COMMIT" => Token::KeywordCommit,
+                    "ROLLBACK" => Token::KeywordRollback,
+                    "ISOLATION" => Token::KeywordIsolation,
+                    "LEVEL" => Token::KeywordLevel,
+                    "SERIALIZABLE" => Token::KeywordSerializable,
+                    "REPEATABLE" => Token::KeywordRepeatable,
+                    "READ" => Token::KeywordRead,
+                    "PRIMARY" => Token::KeywordPrimary,
+                    "KEY" => Token::KeywordKey,
+                    "CONSTRAINT" => Token::KeywordConstraint,
+                    "DEFAULT" => Token::KeywordDefault,
+                    "CONFLICT" => Token::KeywordConflict,
+                    "DO" => Token::KeywordDo,
+                    "NOTHING" => Token::KeywordNothing,
+                    "RETURNING" => Token::KeywordReturning,
+                    "NULL" => Token::Lit(Value::Null),
                     _ => Token::Ident(ret),
                 })
             }
-            Some(c) if vec![',', '(', ')', '*', ';'].contains(c) => {
+            Some(c) if [',', '.', '(', ')', '*', '+', '-', '/', ';'].contains(c) => {
                 tokens.push(match *c {
                     ',' => Token::Comma,
+                    '.' => Token::Dot,
                     '(' => Token::LeftParen,
                     ')' => Token::RightParen,
                     '*' => Token::Asterisk,
+                    '+' => Token::Plus,
+                    '-' => Token::Minus,
+                    '/' => Token::Slash,
                     ';' => Token::Semicolon,
                     _ => Token::EOF,
                 });
                 iter.next();
             }
-            Some(c) if c.is_digit(10) => {
+            Some('=') => {
+                tokens.push(Token::Equal);
+                iter.next();
+            }
+            Some('<') => {
+                iter.next();
+                match iter.peek() {
+                    Some('=') => {
+                        tokens.push(Token::LessOrEqual);
+                        iter.next();
+                    }
+                    Some('>') => {
+                        tokens.push(Token::NotEqual);
+                        iter.next();
+                    }
+                    _ => tokens.push(Token::Less),
+                }
+            }
+            Some('>') => {
+                iter.next();
+                match iter.peek() {
+                    Some('=') => {
+                        tokens.push(Token::GreaterOrEqual);
+                        iter.next();
+                    }
+                    _ => tokens.push(Token::Greater),
+                }
+            }
+            Some('!') => {
+                iter.next();
+                match iter.peek() {
+                    Some('=') => {
+                        tokens.push(Token::NotEqual);
+                        iter.next();
+                    }
+                    _ => return Err(anyhow!("invalid token: !")),
+                }
+            }
+            Some('$') => {
+                iter.next();
+                let mut ret = String::new();
+                while let Some(c) = iter.peek().filter(|c| c.is_ascii_digit()) {
+                    ret.push(*c);
+                    iter.next();
+                }
+                match ret.parse() {
+                    Ok(v) if v > 0 => tokens.push(Token::Parameter(v)),
+                    _ => return Err(anyhow!("invalid parameter: ${}", ret)),
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
                 let mut ret = String::new();
                 loop {
                     match iter.peek() {
-                        Some(cc) if cc.is_digit(10) => {
-                            ret = format!("{}{}", ret, cc.to_string());
+                        Some(cc) if cc.is_ascii_digit() => {
+                            ret = format!("{}{}", ret, cc);
                             iter.next();
                         }
                         _ => {
@@ -102,7 +255,7 @@ pub fn tokenize(iter: &mut Peekable<Chars>) -> Result<Vec<Token>> {
                             iter.next();
                             match iter.peek() {
                                 Some(cc) if '\'' == *cc => {
-                                    ret = format!("{}{}", ret, cc.to_string());
+                                    ret = format!("{}{}", ret, cc);
                                 }
                                 _ => {
                                     return Err(anyhow!("invalid string literal: {}", ret));
@@ -110,7 +263,7 @@ pub fn tokenize(iter: &mut Peekable<Chars>) -> Result<Vec<Token>> {
                             }
                         }
                         Some(c) => {
-                            ret = format!("{}{}", ret, c.to_string());
+                            ret = format!("{}{}", ret, c);
                             iter.next();
                         }
                         _ => {
@@ -157,6 +310,266 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn select_query_with_where() -> Result<()> {
+        let sql = "SELECT * FROM users WHERE NOT (id >= 1 AND id<>3) OR name = 'foo' AND id != 4;";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Asterisk,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordWhere,
+                Token::KeywordNot,
+                Token::LeftParen,
+                Token::Ident("id".to_string()),
+                Token::GreaterOrEqual,
+                Token::Lit(Value::Int(1)),
+                Token::KeywordAnd,
+                Token::Ident("id".to_string()),
+                Token::NotEqual,
+                Token::Lit(Value::Int(3)),
+                Token::RightParen,
+                Token::KeywordOr,
+                Token::Ident("name".to_string()),
+                Token::Equal,
+                Token::Lit(Value::Varchar("foo".to_string())),
+                Token::KeywordAnd,
+                Token::Ident("id".to_string()),
+                Token::NotEqual,
+                Token::Lit(Value::Int(4)),
+                Token::Semicolon,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_is_null() -> Result<()> {
+        let sql = "SELECT * FROM users WHERE id IS NOT null";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Asterisk,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordWhere,
+                Token::Ident("id".to_string()),
+                Token::KeywordIs,
+                Token::KeywordNot,
+                Token::Lit(Value::Null),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_projection() -> Result<()> {
+        let sql = "SELECT id, -id * 2 + 1 AS x, id/2-1 FROM users";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Ident("id".to_string()),
+                Token::Comma,
+                Token::Minus,
+                Token::Ident("id".to_string()),
+                Token::Asterisk,
+                Token::Lit(Value::Int(2)),
+                Token::Plus,
+                Token::Lit(Value::Int(1)),
+                Token::KeywordAs,
+                Token::Ident("x".to_string()),
+                Token::Comma,
+                Token::Ident("id".to_string()),
+                Token::Slash,
+                Token::Lit(Value::Int(2)),
+                Token::Minus,
+                Token::Lit(Value::Int(1)),
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn delete_query() -> Result<()> {
+        let sql = "DELETE FROM users WHERE id = 1;";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordDelete,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordWhere,
+                Token::Ident("id".to_string()),
+                Token::Equal,
+                Token::Lit(Value::Int(1)),
+                Token::Semicolon,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn update_query() -> Result<()> {
+        let sql = "UPDATE users SET name = 'bar', id = id + 1 WHERE id = 1;";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordUpdate,
+                Token::Ident("users".to_string()),
+                Token::KeywordSet,
+                Token::Ident("name".to_string()),
+                Token::Equal,
+                Token::Lit(Value::Varchar("bar".to_string())),
+                Token::Comma,
+                Token::Ident("id".to_string()),
+                Token::Equal,
+                Token::Ident("id".to_string()),
+                Token::Plus,
+                Token::Lit(Value::Int(1)),
+                Token::KeywordWhere,
+                Token::Ident("id".to_string()),
+                Token::Equal,
+                Token::Lit(Value::Int(1)),
+                Token::Semicolon,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_order_by() -> Result<()> {
+        let sql = "SELECT * FROM users ORDER BY name DESC, id asc";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Asterisk,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordOrder,
+                Token::KeywordBy,
+                Token::Ident("name".to_string()),
+                Token::KeywordDesc,
+                Token::Comma,
+                Token::Ident("id".to_string()),
+                Token::KeywordAsc,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_limit() -> Result<()> {
+        let sql = "SELECT * FROM users LIMIT 10 OFFSET 20";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Asterisk,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordLimit,
+                Token::Lit(Value::Int(10)),
+                Token::KeywordOffset,
+                Token::Lit(Value::Int(20)),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_group_by() -> Result<()> {
+        let sql = "SELECT name, COUNT(*) FROM users GROUP BY name HAVING SUM(id) > 1";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Ident("name".to_string()),
+                Token::Comma,
+                Token::Ident("COUNT".to_string()),
+                Token::LeftParen,
+                Token::Asterisk,
+                Token::RightParen,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordGroup,
+                Token::KeywordBy,
+                Token::Ident("name".to_string()),
+                Token::KeywordHaving,
+                Token::Ident("SUM".to_string()),
+                Token::LeftParen,
+                Token::Ident("id".to_string()),
+                Token::RightParen,
+                Token::Greater,
+                Token::Lit(Value::Int(1)),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn select_query_with_join() -> Result<()> {
+        let sql = "SELECT u.name FROM users u LEFT OUTER JOIN posts ON u.id = posts.user_id";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Ident("u".to_string()),
+                Token::Dot,
+                Token::Ident("name".to_string()),
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::Ident("u".to_string()),
+                Token::KeywordLeft,
+                Token::KeywordOuter,
+                Token::KeywordJoin,
+                Token::Ident("posts".to_string()),
+                Token::KeywordOn,
+                Token::Ident("u".to_string()),
+                Token::Dot,
+                Token::Ident("id".to_string()),
+                Token::Equal,
+                Token::Ident("posts".to_string()),
+                Token::Dot,
+                Token::Ident("user_id".to_string()),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn comparison_operators() -> Result<()> {
+        let ret = tokenize(&mut "= <> != < <= > >=".chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::Equal,
+                Token::NotEqual,
+                Token::NotEqual,
+                Token::Less,
+                Token::LessOrEqual,
+                Token::Greater,
+                Token::GreaterOrEqual,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+    #[test]
     fn create_table_query() -> Result<()> {
         let sql = "
     CREATE TABLE users
@@ -214,4 +627,30 @@ mod tests {
         );
         Ok(())
     }
+    #[test]
+    fn query_with_parameters() -> Result<()> {
+        let sql = "SELECT * FROM users WHERE id = $1 AND name = $12";
+        let ret = tokenize(&mut sql.chars().peekable())?;
+        assert_eq!(
+            ret,
+            vec![
+                Token::KeywordSelect,
+                Token::Asterisk,
+                Token::KeywordFrom,
+                Token::Ident("users".to_string()),
+                Token::KeywordWhere,
+                Token::Ident("id".to_string()),
+                Token::Equal,
+                Token::Parameter(1),
+                Token::KeywordAnd,
+                Token::Ident("name".to_string()),
+                Token::Equal,
+                Token::Parameter(12),
+                Token::EOF,
+            ]
+        );
+        assert!(tokenize(&mut "SELECT $0".chars().peekable()).is_err());
+        assert!(tokenize(&mut "SELECT $".chars().peekable()).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,805 @@
+pub mod message;
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use anyhow::Result;
+use log::{debug, info};
+use rand::{thread_rng, Rng};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use self::message::{
+    BackendMessage, FieldDescription, FrontendMessage, StartupMessage, Target, TransactionStatus,
+};
+use crate::catalog::{Column, ColumnType};
+use crate::database::{sqlstate, Database};
+use crate::execution::QueryResult;
+use crate::parser::token::{self, Token};
+use crate::parser::{Parser, Stmt};
+use crate::value::Value;
+
+const INT8_OID: u32 = 20;
+const INT2_OID: u32 = 21;
+const INT4_OID: u32 = 23;
+const TEXT_OID: u32 = 25;
+const VARCHAR_OID: u32 = 1043;
+
+const TEXT_FORMAT: i16 = 0;
+const BINARY_FORMAT: i16 = 1;
+
+// Bigger messages are rejected rather than buffered.
+const MAX_MESSAGE_LEN: i32 = 1 << 26;
+
+// Serves the clients of PostgreSQL, such as psql, speaking the version 3 of its protocol. Each
+// connection is a session, whose transaction is aborted if it is closed in the middle.
+pub async fn serve(listener: TcpListener, database: Arc<Database>) -> Result<()> {
+    info!(
+        "Listening for PostgreSQL clients on {}",
+        listener.local_addr()?
+    );
+    let mut process_id = 0;
+    loop {
+        let (stream, address) = listener.accept().await?;
+        process_id += 1;
+        let mut connection = Connection::new(stream, database.clone(), process_id);
+        tokio::spawn(async move {
+            debug!("Accepted a PostgreSQL connection from {}", address);
+            if let Err(e) = connection.run().await {
+                debug!("PostgreSQL connection from {} failed: {:#}", address, e);
+            }
+            let database = connection.database.clone();
+            if let Some(session_id) = connection.session_id.clone() {
+                let result =
+                    tokio::task::spawn_blocking(move || database.close_session(&session_id))
+                        .await
+                        .unwrap_or_else(|e| Err(anyhow!("closing the session panicked: {}", e)));
+                if let Err(e) = result {
+                    debug!("Failed to close the session of {}: {:#}", address, e);
+                }
+            }
+        });
+    }
+}
+
+// A statement as the clients send it.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+enum Statement {
+    Empty,
+    // SET, sent by the drivers to configure the session, which is accepted and ignored.
+    Set,
+    Stmt(Box<Stmt>),
+}
+
+impl Statement {
+    fn parse(tokens: Vec<Token>) -> Result<Self> {
+        match tokens.first() {
+            None | Some(Token::EOF) => Ok(Statement::Empty),
+            Some(Token::KeywordSet) => Ok(Statement::Set),
+            _ => Parser::new(tokens)
+                .parse()
+                .map(|stmt| Statement::Stmt(Box::new(stmt))),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PreparedStatement {
+    // tokens of a single statement, with parameters
+    tokens: Vec<Token>,
+    // 0 for the ones whose type is left to the server
+    param_types: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct Portal {
+    statement: Statement,
+    result_formats: Vec<i16>,
+    // the rows of the statement not sent yet once it has run
+    pending: Option<VecDeque<Vec<Value>>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: TcpStream,
+    database: Arc<Database>,
+    process_id: i32,
+    // issued by BEGIN, until the transaction ends
+    session_id: Option<String>,
+    statements: HashMap<String, PreparedStatement>,
+    portals: HashMap<String, Portal>,
+    // After an error, the messages of the extended query protocol are skipped until Sync.
+    skip_until_sync: bool,
+    // messages to send at the next flush
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, database: Arc<Database>, process_id: i32) -> Self {
+        Connection {
+            stream,
+            database,
+            process_id,
+            session_id: None,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            skip_until_sync: false,
+            buf: Vec::new(),
+        }
+    }
+    async fn run(&mut self) -> Result<()> {
+        if !self.startup().await? {
+            return Ok(());
+        }
+        loop {
+            let tag = match self.stream.read_u8().await {
+                Ok(tag) => tag,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let body = self.read_body().await?;
+            let message = match FrontendMessage::decode(tag, &body) {
+                Ok(message) => message,
+                Err(e) => {
+                    // The stream cannot be trusted anymore.
+                    self.send_error("08P01", &e);
+                    self.flush().await?;
+                    return Err(e);
+                }
+            };
+            match message {
+                FrontendMessage::Terminate => return Ok(()),
+                FrontendMessage::Sync => {
+                    self.skip_until_sync = false;
+                    self.send_ready_for_query();
+                    self.flush().await?;
+                }
+                FrontendMessage::Flush => self.flush().await?,
+                FrontendMessage::Query(query) => {
+                    if let Err(e) = self.simple_query(&query).await {
+                        self.send_error(sqlstate(&e), &e);
+                    }
+                    self.skip_until_sync = false;
+                    self.send_ready_for_query();
+                    self.flush().await?;
+                }
+                _ if self.skip_until_sync => {}
+                message => {
+                    if let Err(e) = self.extended_query(message).await {
+                        self.send_error(sqlstate(&e), &e);
+                        self.skip_until_sync = true;
+                    }
+                }
+            }
+        }
+    }
+    // Returns false if the connection is not for a session.
+    async fn startup(&mut self) -> Result<bool> {
+        loop {
+            let body = self.read_body().await?;
+            let message = match StartupMessage::decode(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    self.send_error("08P01", &e);
+                    self.flush().await?;
+                    return Err(e);
+                }
+            };
+            match message {
+                // Encryption is not supported, so the client goes on in plain text.
+                StartupMessage::SslRequest | StartupMessage::GssEncRequest => {
+                    self.stream.write_all(b"N").await?;
+                }
+                // Queries cannot be canceled.
+                StartupMessage::CancelRequest => return Ok(false),
+                StartupMessage::Startup {
+                    protocol_version,
+                    parameters,
+                } => {
+                    // Any user is trusted.
+                    self.send(BackendMessage::AuthenticationOk);
+                    let options: Vec<String> = parameters
+                        .into_iter()
+                        .map(|(name, _)| name)
+                        .filter(|name| name.starts_with("_pq_."))
+                        .collect();
+                    if protocol_version & 0xffff != 0 || !options.is_empty() {
+                        self.send(BackendMessage::NegotiateProtocolVersion {
+                            minor_version: 0,
+                            options,
+                        });
+                    }
+                    for (name, value) in [
+                        ("server_version", "14.0"),
+                        ("server_encoding", "UTF8"),
+                        ("client_encoding", "UTF8"),
+                        ("DateStyle", "ISO, MDY"),
+                        ("integer_datetimes", "on"),
+                        ("standard_conforming_strings", "on"),
+                    ] {
+                        self.send(BackendMessage::ParameterStatus {
+                            name: name.to_string(),
+                            value: value.to_string(),
+                        });
+                    }
+                    self.send(BackendMessage::BackendKeyData {
+                        process_id: self.process_id,
+                        secret_key: thread_rng().gen(),
+                    });
+                    self.send_ready_for_query();
+                    self.flush().await?;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    // Reads a message after its type byte, if any.
+    async fn read_body(&mut self) -> Result<Vec<u8>> {
+        let len = self.stream.read_i32().await?;
+        if !(4..=MAX_MESSAGE_LEN).contains(&len) {
+            return Err(anyhow!("invalid message length: {}", len));
+        }
+        let mut body = vec![0u8; len as usize - 4];
+        self.stream.read_exact(&mut body).await?;
+        Ok(body)
+    }
+    // Runs the statements of the query one after another, until one fails.
+    async fn simple_query(&mut self, query: &str) -> Result<()> {
+        let statements = split_statements(token::tokenize(&mut query.chars().peekable())?);
+        if statements.is_empty() {
+            self.send(BackendMessage::EmptyQueryResponse);
+        }
+        for tokens in statements {
+            match Statement::parse(tokens)? {
+                Statement::Empty => self.send(BackendMessage::EmptyQueryResponse),
+                Statement::Set => self.send(BackendMessage::CommandComplete("SET".to_string())),
+                Statement::Stmt(stmt) => {
+                    let tag = stmt.clone();
+                    let result = self.execute(*stmt).await?;
+                    if returns_rows(&tag) {
+                        self.send(BackendMessage::RowDescription(field_descriptions(
+                            &result.columns,
+                            &[],
+                        )));
+                    }
+                    let rows = result.rows.len();
+                    for row in result.rows {
+                        self.send_data_row(row, &[]);
+                    }
+                    self.send(BackendMessage::CommandComplete(command_tag(
+                        &tag,
+                        result.rows_affected.unwrap_or(rows),
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn extended_query(&mut self, message: FrontendMessage) -> Result<()> {
+        match message {
+            FrontendMessage::Parse {
+                name,
+                query,
+                mut param_types,
+            } => {
+                let mut statements =
+                    split_statements(token::tokenize(&mut query.chars().peekable())?);
+                if statements.len() > 1 {
+                    return Err(anyhow!(
+                        "cannot insert multiple commands into a prepared statement"
+                    ));
+                }
+                let tokens = statements.pop().unwrap_or_else(|| vec![Token::EOF]);
+                let params = tokens
+                    .iter()
+                    .filter_map(|token| match token {
+                        Token::Parameter(n) => Some(*n),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                if param_types.len() > params {
+                    return Err(anyhow!(
+                        "{} parameter types are given for {} parameters",
+                        param_types.len(),
+                        params
+                    ));
+                }
+                param_types.resize(params, 0);
+                // Syntax errors are reported now rather than on Bind.
+                Statement::parse(bind_parameters(&tokens, &vec![Value::Null; params])?)?;
+                self.statements.insert(
+                    name,
+                    PreparedStatement {
+                        tokens,
+                        param_types,
+                    },
+                );
+                self.send(BackendMessage::ParseComplete);
+            }
+            FrontendMessage::Bind {
+                portal,
+                statement,
+                param_formats,
+                params,
+                result_formats,
+            } => {
+                let prepared = self.prepared_statement(&statement)?;
+                if params.len() != prepared.param_types.len() {
+                    return Err(anyhow!(
+                        "bind message supplies {} parameters, but prepared statement \"{}\" requires {}",
+                        params.len(),
+                        statement,
+                        prepared.param_types.len()
+                    ));
+                }
+                let values = params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, param)| {
+                        decode_param(
+                            param.as_deref(),
+                            prepared.param_types[i],
+                            format_of(&param_formats, i)?,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let statement = Statement::parse(bind_parameters(&prepared.tokens, &values)?)?;
+                self.portals.insert(
+                    portal,
+                    Portal {
+                        statement,
+                        result_formats,
+                        pending: None,
+                    },
+                );
+                self.send(BackendMessage::BindComplete);
+            }
+            FrontendMessage::Describe {
+                target: Target::Statement,
+                name,
+            } => {
+                let prepared = self.prepared_statement(&name)?;
+                // The type of a parameter is its text unless the client tells.
+                let param_types = prepared
+                    .param_types
+                    .iter()
+                    .map(|&oid| if oid == 0 { TEXT_OID } else { oid })
+                    .collect();
+                let statement = Statement::parse(bind_parameters(
+                    &prepared.tokens,
+                    &vec![Value::Null; prepared.param_types.len()],
+                )?)?;
+                let columns = self.describe(statement).await?;
+                self.send(BackendMessage::ParameterDescription(param_types));
+                self.send_row_description(columns, &[]);
+            }
+            FrontendMessage::Describe {
+                target: Target::Portal,
+                name,
+            } => {
+                let portal = self.portal(&name)?;
+                let (statement, result_formats) =
+                    (portal.statement.clone(), portal.result_formats.clone());
+                let columns = self.describe(statement).await?;
+                self.send_row_description(columns, &result_formats);
+            }
+            FrontendMessage::Execute { portal, max_rows } => {
+                self.execute_portal(&portal, max_rows).await?
+            }
+            FrontendMessage::Close { target, name } => {
+                // Closing one which does not exist is not an error.
+                match target {
+                    Target::Statement => {
+                        self.statements.remove(&name);
+                    }
+                    Target::Portal => {
+                        self.portals.remove(&name);
+                    }
+                }
+                self.send(BackendMessage::CloseComplete);
+            }
+            FrontendMessage::Query(_)
+            | FrontendMessage::Sync
+            | FrontendMessage::Flush
+            | FrontendMessage::Terminate => unreachable!(),
+        }
+        Ok(())
+    }
+    // Runs the statement of the portal on the first Execute, and sends at most `max_rows` of
+    // its rows at a time, or all of them if it is 0.
+    async fn execute_portal(&mut self, name: &str, max_rows: i32) -> Result<()> {
+        let portal = self.portal(name)?;
+        let stmt = match &portal.statement {
+            Statement::Empty => {
+                self.send(BackendMessage::EmptyQueryResponse);
+                return Ok(());
+            }
+            Statement::Set => {
+                self.send(BackendMessage::CommandComplete("SET".to_string()));
+                return Ok(());
+            }
+            Statement::Stmt(stmt) => stmt.clone(),
+        };
+        let mut rows_affected = None;
+        if portal.pending.is_none() {
+            let result = self.execute(*stmt.clone()).await?;
+            rows_affected = result.rows_affected;
+            self.portal_mut(name)?.pending = Some(result.rows.into());
+        }
+        let portal = self.portal_mut(name)?;
+        let result_formats = portal.result_formats.clone();
+        let pending = portal.pending.as_mut().unwrap();
+        let n = if max_rows > 0 {
+            pending.len().min(max_rows as usize)
+        } else {
+            pending.len()
+        };
+        let rows: Vec<_> = pending.drain(..n).collect();
+        let suspended = !pending.is_empty();
+        for row in rows {
+            self.send_data_row(row, &result_formats);
+        }
+        if suspended {
+            self.send(BackendMessage::PortalSuspended);
+        } else {
+            self.send(BackendMessage::CommandComplete(command_tag(
+                &stmt,
+                rows_affected.unwrap_or(n),
+            )));
+        }
+        Ok(())
+    }
+    fn prepared_statement(&self, name: &str) -> Result<&PreparedStatement> {
+        self.statements
+            .get(name)
+            .ok_or_else(|| anyhow!("prepared statement \"{}\" does not exist", name))
+    }
+    fn portal(&self, name: &str) -> Result<&Portal> {
+        self.portals
+            .get(name)
+            .ok_or_else(|| anyhow!("portal \"{}\" does not exist", name))
+    }
+    fn portal_mut(&mut self, name: &str) -> Result<&mut Portal> {
+        self.portals
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("portal \"{}\" does not exist", name))
+    }
+    // Statements may wait for locks, so they run off the async workers.
+    async fn execute(&mut self, stmt: Stmt) -> Result<QueryResult> {
+        let database = self.database.clone();
+        let session_id = self.session_id.clone();
+        let (result, session_id) = tokio::task::spawn_blocking(move || {
+            let result = database.execute(stmt, session_id.as_deref());
+            let session_id = database.open_session(&result, session_id.as_deref());
+            (result, session_id)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            let session_id = self.session_id.clone();
+            (Err(anyhow!("query panicked: {}", e)), session_id)
+        });
+        self.session_id = session_id;
+        result
+    }
+    async fn describe(&self, statement: Statement) -> Result<Option<Vec<Column>>> {
+        let stmt = match statement {
+            Statement::Stmt(stmt) => stmt,
+            Statement::Empty | Statement::Set => return Ok(None),
+        };
+        let database = self.database.clone();
+        let session_id = self.session_id.clone();
+        tokio::task::spawn_blocking(move || database.describe(&stmt, session_id.as_deref()))
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("query panicked: {}", e)))
+    }
+    fn send(&mut self, message: BackendMessage) {
+        message.encode(&mut self.buf);
+    }
+    fn send_ready_for_query(&mut self) {
+        let status = match &self.session_id {
+            Some(session_id) if self.database.in_transaction(session_id) => {
+                TransactionStatus::InTransaction
+            }
+            _ => TransactionStatus::Idle,
+        };
+        self.send(BackendMessage::ReadyForQuery(status));
+    }
+    fn send_row_description(&mut self, columns: Option<Vec<Column>>, formats: &[i16]) {
+        match columns {
+            Some(columns) => self.send(BackendMessage::RowDescription(field_descriptions(
+                &columns, formats,
+            ))),
+            None => self.send(BackendMessage::NoData),
+        }
+    }
+    fn send_data_row(&mut self, row: Vec<Value>, formats: &[i16]) {
+        let values = row
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                // Invalid formats were refused when the portal was described or bound.
+                encode_value(value, format_of(formats, i).unwrap_or(TEXT_FORMAT))
+            })
+            .collect();
+        self.send(BackendMessage::DataRow(values));
+    }
+    fn send_error(&mut self, code: &str, e: &anyhow::Error) {
+        self.send(BackendMessage::ErrorResponse {
+            code: code.to_string(),
+            message: format!("{:#}", e),
+        });
+    }
+    async fn flush(&mut self) -> Result<()> {
+        self.stream.write_all(&self.buf).await?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+// Splits the tokens of a query into its statements, ending each of them with a semicolon.
+fn split_statements(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    tokens
+        .split(|token| *token == Token::Semicolon || *token == Token::EOF)
+        .filter(|tokens| !tokens.is_empty())
+        .map(|tokens| [tokens, &[Token::Semicolon, Token::EOF]].concat())
+        .collect()
+}
+
+// Replaces the parameters by the values bound to them.
+fn bind_parameters(tokens: &[Token], values: &[Value]) -> Result<Vec<Token>> {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Parameter(n) => match values.get(n - 1) {
+                Some(value) => Ok(Token::Lit(value.clone())),
+                None => Err(anyhow!("there is no parameter ${}", n)),
+            },
+            token => Ok(token.clone()),
+        })
+        .collect()
+}
+
+// The format of the i-th value, given one format for all or one for each.
+fn format_of(formats: &[i16], i: usize) -> Result<i16> {
+    let format = match formats.len() {
+        0 => TEXT_FORMAT,
+        1 => formats[0],
+        _ => match formats.get(i) {
+            Some(&format) => format,
+            None => return Err(anyhow!("format of value {} is missing", i + 1)),
+        },
+    };
+    match format {
+        TEXT_FORMAT | BINARY_FORMAT => Ok(format),
+        format => Err(anyhow!("invalid format code: {}", format)),
+    }
+}
+
+// A parameter of unknown type is an integer if it reads as one, and a string otherwise.
+fn decode_param(param: Option<&[u8]>, type_oid: u32, format: i16) -> Result<Value> {
+    let param = match param {
+        Some(param) => param,
+        None => return Ok(Value::Null),
+    };
+    if format == BINARY_FORMAT {
+        return match (type_oid, param.len()) {
+            (INT2_OID, 2) => Ok(Value::Int(i16::from_be_bytes([param[0], param[1]]) as i32)),
+            (INT4_OID, 4) => Ok(Value::Int(i32::from_be_bytes([
+                param[0], param[1], param[2], param[3],
+            ]))),
+            (INT8_OID, 8) => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(param);
+                let v = i64::from_be_bytes(buf);
+                Ok(Value::Int(v.try_into().map_err(|_| {
+                    anyhow!("parameter {} is out of range for type integer", v)
+                })?))
+            }
+            (TEXT_OID | VARCHAR_OID, _) => Ok(Value::Varchar(String::from_utf8(param.to_vec())?)),
+            _ => Err(anyhow!("unsupported binary parameter of type {}", type_oid)),
+        };
+    }
+    let text = String::from_utf8(param.to_vec())?;
+    match type_oid {
+        INT2_OID | INT4_OID | INT8_OID => {
+            Ok(Value::Int(text.trim().parse().map_err(|_| {
+                anyhow!("invalid input syntax for type integer: \"{}\"", text)
+            })?))
+        }
+        0 => Ok(text.parse().map(Value::Int).unwrap_or(Value::Varchar(text))),
+        _ => Ok(Value::Varchar(text)),
+    }
+}
+
+fn encode_value(value: Value, format: i16) -> Option<Vec<u8>> {
+    match value {
+        Value::Null => None,
+        Value::Int(v) if format == BINARY_FORMAT => Some(v.to_be_bytes().to_vec()),
+        Value::Int(v) => Some(v.to_string().into_bytes()),
+        Value::Varchar(v) => Some(v.into_bytes()),
+    }
+}
+
+fn field_descriptions(columns: &[Column], formats: &[i16]) -> Vec<FieldDescription> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let (type_oid, type_size) = match column.column_type {
+                ColumnType::Int => (INT4_OID, 4),
+                ColumnType::Varchar => (VARCHAR_OID, -1),
+            };
+            FieldDescription {
+                name: column.name.clone(),
+                type_oid,
+                type_size,
+                format: format_of(formats, i).unwrap_or(TEXT_FORMAT),
+            }
+        })
+        .collect()
+}
+
+// Whether the statement returns rows, which are described before they are sent.
+fn returns_rows(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::SelectStmt(_) => true,
+        Stmt::InsertStmt(ast) => ast.returning.is_some(),
+        _ => false,
+    }
+}
+
+fn command_tag(stmt: &Stmt, rows: usize) -> String {
+    match stmt {
+        // CREATE TABLE ... AS SELECT is tagged like the query, with the rows it inserts.
+        Stmt::CreateTableStmt(ast) if ast.query.is_some() => format!("SELECT {}", rows),
+        Stmt::CreateTableStmt(_) => "CREATE TABLE".to_string(),
+        Stmt::CreateIndexStmt(_) => "CREATE INDEX".to_string(),
+        Stmt::DropTableStmt(_) => "DROP TABLE".to_string(),
+        Stmt::InsertStmt(_) => format!("INSERT 0 {}", rows),
+        Stmt::SelectStmt(_) => format!("SELECT {}", rows),
+        Stmt::DeleteStmt(_) => format!("DELETE {}", rows),
+        Stmt::UpdateStmt(_) => format!("UPDATE {}", rows),
+        Stmt::BeginStmt(_) => "BEGIN".to_string(),
+        Stmt::CommitStmt => "COMMIT".to_string(),
+        Stmt::RollbackStmt => "ROLLBACK".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::open_catalog;
+    use crate::database::Database;
+    use crate::pgwire::serve;
+    use crate::transaction::TransactionManager;
+    use anyhow::Result;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    async fn send(stream: &mut TcpStream, tag: Option<u8>, body: &[u8]) -> Result<()> {
+        let mut buf = Vec::new();
+        buf.extend(tag);
+        buf.extend((body.len() as i32 + 4).to_be_bytes());
+        buf.extend(body);
+        stream.write_all(&buf).await?;
+        Ok(())
+    }
+    // Reads the messages up to ReadyForQuery.
+    async fn receive(stream: &mut TcpStream) -> Result<Vec<(char, Vec<u8>)>> {
+        let mut messages = Vec::new();
+        loop {
+            let tag = stream.read_u8().await? as char;
+            let len = stream.read_i32().await?;
+            let mut body = vec![0u8; len as usize - 4];
+            stream.read_exact(&mut body).await?;
+            messages.push((tag, body));
+            if tag == 'Z' {
+                return Ok(messages);
+            }
+        }
+    }
+    fn tags(messages: &[(char, Vec<u8>)]) -> String {
+        messages.iter().map(|(tag, _)| tag).collect()
+    }
+    #[tokio::test]
+    async fn simple_and_extended_query() -> Result<()> {
+        let catalog = open_catalog("tmp/pgwire/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = Arc::new(TransactionManager::new(buffer_pool_manager.clone()));
+        let database = Arc::new(Database::new(
+            catalog,
+            buffer_pool_manager,
+            transaction_manager,
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(serve(listener, database));
+        let mut stream = TcpStream::connect(address).await?;
+
+        // The client asks for SSL first, which is refused.
+        send(&mut stream, None, &80877103i32.to_be_bytes()).await?;
+        assert_eq!(stream.read_u8().await?, b'N');
+        send(
+            &mut stream,
+            None,
+            b"\x00\x03\x00\x00user\x00postgres\x00\x00",
+        )
+        .await?;
+        let messages = receive(&mut stream).await?;
+        assert_eq!(tags(&messages), "RSSSSSSKZ");
+        assert_eq!(messages.last().unwrap().1, b"I");
+
+        send(
+            &mut stream,
+            Some(b'Q'),
+            b"CREATE TABLE t (id INT, name VARCHAR); INSERT INTO t VALUES (1, 'a'); BEGIN;\0",
+        )
+        .await?;
+        let messages = receive(&mut stream).await?;
+        assert_eq!(tags(&messages), "CCCZ");
+        assert_eq!(messages[1].1, b"INSERT 0 1\0");
+        assert_eq!(messages[3].1, b"T");
+
+        // The statement is prepared, then run with 2 as its parameter.
+        send(
+            &mut stream,
+            Some(b'P'),
+            b"s\0INSERT INTO t VALUES ($1, 'b')\0\x00\x00",
+        )
+        .await?;
+        send(
+            &mut stream,
+            Some(b'B'),
+            b"\0s\0\x00\x00\x00\x01\x00\x00\x00\x012\x00\x00",
+        )
+        .await?;
+        send(&mut stream, Some(b'E'), b"\0\x00\x00\x00\x00").await?;
+        send(
+            &mut stream,
+            Some(b'P'),
+            b"\0SELECT * FROM t WHERE id >= $1\0\x00\x00",
+        )
+        .await?;
+        send(&mut stream, Some(b'D'), b"S\0").await?;
+        // id is asked for in binary.
+        send(
+            &mut stream,
+            Some(b'B'),
+            b"\0\0\x00\x00\x00\x01\x00\x00\x00\x011\x00\x02\x00\x01\x00\x00",
+        )
+        .await?;
+        send(&mut stream, Some(b'E'), b"\0\x00\x00\x00\x01").await?;
+        send(&mut stream, Some(b'E'), b"\0\x00\x00\x00\x00").await?;
+        send(&mut stream, Some(b'S'), b"").await?;
+        let messages = receive(&mut stream).await?;
+        assert_eq!(tags(&messages), "12C1tT2DsDCZ");
+        assert_eq!(messages[2].1, b"INSERT 0 1\0");
+        // One parameter of type text
+        assert_eq!(messages[4].1, b"\x00\x01\x00\x00\x00\x19");
+        assert_eq!(
+            messages[7].1,
+            b"\x00\x02\x00\x00\x00\x04\x00\x00\x00\x01\x00\x00\x00\x01a"
+        );
+        assert_eq!(
+            messages[9].1,
+            b"\x00\x02\x00\x00\x00\x04\x00\x00\x00\x02\x00\x00\x00\x01b"
+        );
+        assert_eq!(messages[10].1, b"SELECT 1\0");
+
+        // After an error, the messages are skipped until Sync.
+        send(&mut stream, Some(b'P'), b"\0SELECT FROM\0\x00\x00").await?;
+        send(&mut stream, Some(b'B'), b"\0\0\x00\x00\x00\x00\x00\x00").await?;
+        send(&mut stream, Some(b'S'), b"").await?;
+        let messages = receive(&mut stream).await?;
+        assert_eq!(tags(&messages), "EZ");
+
+        send(&mut stream, Some(b'Q'), b"ROLLBACK; SELECT * FROM t\0").await?;
+        let messages = receive(&mut stream).await?;
+        assert_eq!(tags(&messages), "CTDCZ");
+        assert_eq!(messages.last().unwrap().1, b"I");
+        send(&mut stream, Some(b'X'), b"").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+use anyhow::Result;
+
+// Version 3.0 of the protocol, with the major version in the upper 16 bits.
+pub const PROTOCOL_VERSION: i32 = 3 << 16;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+const SSL_REQUEST_CODE: i32 = 80877103;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+
+// The first message of a connection, which has no type byte.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum StartupMessage {
+    Startup {
+        protocol_version: i32,
+        parameters: Vec<(String, String)>,
+    },
+    SslRequest,
+    GssEncRequest,
+    CancelRequest,
+}
+
+impl StartupMessage {
+    // Decodes the message after its length.
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(body);
+        match reader.i32()? {
+            SSL_REQUEST_CODE => Ok(StartupMessage::SslRequest),
+            GSSENC_REQUEST_CODE => Ok(StartupMessage::GssEncRequest),
+            CANCEL_REQUEST_CODE => Ok(StartupMessage::CancelRequest),
+            protocol_version if protocol_version >> 16 == 3 => {
+                let mut parameters = Vec::new();
+                loop {
+                    let name = reader.cstring()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    parameters.push((name, reader.cstring()?));
+                }
+                Ok(StartupMessage::Startup {
+                    protocol_version,
+                    parameters,
+                })
+            }
+            protocol_version => Err(anyhow!(
+                "unsupported frontend protocol {}.{}",
+                protocol_version >> 16,
+                protocol_version & 0xffff
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Target {
+    Statement,
+    Portal,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum FrontendMessage {
+    Query(String),
+    Parse {
+        name: String,
+        query: String,
+        // 0 for the parameters whose type is left to the server
+        param_types: Vec<u32>,
+    },
+    Bind {
+        portal: String,
+        statement: String,
+        // 0 for text and 1 for binary, one for all the parameters or one for each
+        param_formats: Vec<i16>,
+        // None for NULL
+        params: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    },
+    Describe {
+        target: Target,
+        name: String,
+    },
+    Execute {
+        portal: String,
+        // 0 for no limit
+        max_rows: i32,
+    },
+    Close {
+        target: Target,
+        name: String,
+    },
+    Sync,
+    Flush,
+    Terminate,
+}
+
+impl FrontendMessage {
+    // Decodes the message after its type byte and length.
+    pub fn decode(tag: u8, body: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(body);
+        let message = match tag {
+            b'Q' => FrontendMessage::Query(reader.cstring()?),
+            b'P' => {
+                let name = reader.cstring()?;
+                let query = reader.cstring()?;
+                let param_types = (0..reader.i16()?)
+                    .map(|_| reader.i32().map(|oid| oid as u32))
+                    .collect::<Result<_>>()?;
+                FrontendMessage::Parse {
+                    name,
+                    query,
+                    param_types,
+                }
+            }
+            b'B' => {
+                let portal = reader.cstring()?;
+                let statement = reader.cstring()?;
+                let param_formats = (0..reader.i16()?)
+                    .map(|_| reader.i16())
+                    .collect::<Result<_>>()?;
+                let params = (0..reader.i16()?)
+                    .map(|_| match reader.i32()? {
+                        -1 => Ok(None),
+                        len if len >= 0 => Ok(Some(reader.bytes(len as usize)?.to_vec())),
+                        len => Err(anyhow!("invalid parameter length: {}", len)),
+                    })
+                    .collect::<Result<_>>()?;
+                let result_formats = (0..reader.i16()?)
+                    .map(|_| reader.i16())
+                    .collect::<Result<_>>()?;
+                FrontendMessage::Bind {
+                    portal,
+                    statement,
+                    param_formats,
+                    params,
+                    result_formats,
+                }
+            }
+            b'D' => FrontendMessage::Describe {
+                target: reader.target()?,
+                name: reader.cstring()?,
+            },
+            b'E' => FrontendMessage::Execute {
+                portal: reader.cstring()?,
+                max_rows: reader.i32()?,
+            },
+            b'C' => FrontendMessage::Close {
+                target: reader.target()?,
+                name: reader.cstring()?,
+            },
+            b'S' => FrontendMessage::Sync,
+            b'H' => FrontendMessage::Flush,
+            b'X' => FrontendMessage::Terminate,
+            tag => return Err(anyhow!("unsupported message type: {:?}", tag as char)),
+        };
+        Ok(message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum TransactionStatus {
+    Idle,
+    InTransaction,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct FieldDescription {
+    pub name: String,
+    pub type_oid: u32,
+    // -1 for variable length types
+    pub type_size: i16,
+    pub format: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum BackendMessage {
+    AuthenticationOk,
+    // The newest minor version supported and the protocol options not recognized.
+    NegotiateProtocolVersion {
+        minor_version: i32,
+        options: Vec<String>,
+    },
+    ParameterStatus {
+        name: String,
+        value: String,
+    },
+    BackendKeyData {
+        process_id: i32,
+        secret_key: i32,
+    },
+    ReadyForQuery(TransactionStatus),
+    RowDescription(Vec<FieldDescription>),
+    // None for NULL
+    DataRow(Vec<Option<Vec<u8>>>),
+    CommandComplete(String),
+    EmptyQueryResponse,
+    ErrorResponse {
+        // SQLSTATE
+        code: String,
+        message: String,
+    },
+    ParseComplete,
+    BindComplete,
+    CloseComplete,
+    ParameterDescription(Vec<u32>),
+    NoData,
+    PortalSuspended,
+}
+
+impl BackendMessage {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let tag = match self {
+            BackendMessage::AuthenticationOk => b'R',
+            BackendMessage::NegotiateProtocolVersion { .. } => b'v',
+            BackendMessage::ParameterStatus { .. } => b'S',
+            BackendMessage::BackendKeyData { .. } => b'K',
+            BackendMessage::ReadyForQuery(_) => b'Z',
+            BackendMessage::RowDescription(_) => b'T',
+            BackendMessage::DataRow(_) => b'D',
+            BackendMessage::CommandComplete(_) => b'C',
+            BackendMessage::EmptyQueryResponse => b'I',
+            BackendMessage::ErrorResponse { .. } => b'E',
+            BackendMessage::ParseComplete => b'1',
+            BackendMessage::BindComplete => b'2',
+            BackendMessage::CloseComplete => b'3',
+            BackendMessage::ParameterDescription(_) => b't',
+            BackendMessage::NoData => b'n',
+            BackendMessage::PortalSuspended => b's',
+        };
+        buf.push(tag);
+        // The length counts itself but not the type byte, and is filled in at the end.
+        let start = buf.len();
+        buf.extend_from_slice(&[0; 4]);
+        match self {
+            BackendMessage::AuthenticationOk => put_i32(buf, 0),
+            BackendMessage::NegotiateProtocolVersion {
+                minor_version,
+                options,
+            } => {
+                put_i32(buf, *minor_version);
+                put_i32(buf, options.len() as i32);
+                for option in options {
+                    put_cstring(buf, option);
+                }
+            }
+            BackendMessage::ParameterStatus { name, value } => {
+                put_cstring(buf, name);
+                put_cstring(buf, value);
+            }
+            BackendMessage::BackendKeyData {
+                process_id,
+                secret_key,
+            } => {
+                put_i32(buf, *process_id);
+                put_i32(buf, *secret_key);
+            }
+            BackendMessage::ReadyForQuery(status) => buf.push(match status {
+                TransactionStatus::Idle => b'I',
+                TransactionStatus::InTransaction => b'T',
+            }),
+            BackendMessage::RowDescription(fields) => {
+                put_i16(buf, fields.len() as i16);
+                for field in fields {
+                    put_cstring(buf, &field.name);
+                    // no table oid nor column number
+                    put_i32(buf, 0);
+                    put_i16(buf, 0);
+                    put_i32(buf, field.type_oid as i32);
+                    put_i16(buf, field.type_size);
+                    // no type modifier
+                    put_i32(buf, -1);
+                    put_i16(buf, field.format);
+                }
+            }
+            BackendMessage::DataRow(values) => {
+                put_i16(buf, values.len() as i16);
+                for value in values {
+                    match value {
+                        Some(value) => {
+                            put_i32(buf, value.len() as i32);
+                            buf.extend_from_slice(value);
+                        }
+                        None => put_i32(buf, -1),
+                    }
+                }
+            }
+            BackendMessage::CommandComplete(tag) => put_cstring(buf, tag),
+            BackendMessage::ErrorResponse { code, message } => {
+                for (field, value) in [
+                    (b'S', "ERROR"),
+                    (b'V', "ERROR"),
+                    (b'C', code),
+                    (b'M', message),
+                ] {
+                    buf.push(field);
+                    put_cstring(buf, value);
+                }
+                buf.push(0);
+            }
+            BackendMessage::ParameterDescription(type_oids) => {
+                put_i16(buf, type_oids.len() as i16);
+                for type_oid in type_oids {
+                    put_i32(buf, *type_oid as i32);
+                }
+            }
+            BackendMessage::EmptyQueryResponse
+            | BackendMessage::ParseComplete
+            | BackendMessage::BindComplete
+            | BackendMessage::CloseComplete
+            | BackendMessage::NoData
+            | BackendMessage::PortalSuspended => {}
+        }
+        let len = (buf.len() - start) as i32;
+        buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn put_i16(buf: &mut Vec<u8>, v: i16) {
+    buf.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_i32(buf: &mut Vec<u8>, v: i32) {
+    buf.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_cstring(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!("message is too short"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+    fn i16(&mut self) -> Result<i16> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.bytes(2)?);
+        Ok(i16::from_be_bytes(buf))
+    }
+    fn i32(&mut self) -> Result<i32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(i32::from_be_bytes(buf))
+    }
+    fn cstring(&mut self) -> Result<String> {
+        let len = match self.data.iter().position(|&b| b == 0) {
+            Some(len) => len,
+            None => return Err(anyhow!("string is not terminated")),
+        };
+        let s = String::from_utf8(self.bytes(len)?.to_vec())?;
+        self.bytes(1)?;
+        Ok(s)
+    }
+    fn target(&mut self) -> Result<Target> {
+        match self.bytes(1)?[0] {
+            b'S' => Ok(Target::Statement),
+            b'P' => Ok(Target::Portal),
+            b => Err(anyhow!("invalid target: {:?}", b as char)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgwire::message::{
+        BackendMessage, FieldDescription, FrontendMessage, StartupMessage, Target,
+        TransactionStatus, PROTOCOL_VERSION,
+    };
+    use anyhow::Result;
+    #[test]
+    fn decode_startup_message() -> Result<()> {
+        let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        body.extend_from_slice(b"user\0foo\0database\0bar\0\0");
+        assert_eq!(
+            StartupMessage::decode(&body)?,
+            StartupMessage::Startup {
+                protocol_version: PROTOCOL_VERSION,
+                parameters: vec![
+                    ("user".to_string(), "foo".to_string()),
+                    ("database".to_string(), "bar".to_string()),
+                ],
+            }
+        );
+        assert_eq!(
+            StartupMessage::decode(&80877103i32.to_be_bytes())?,
+            StartupMessage::SslRequest
+        );
+        assert!(StartupMessage::decode(&(2i32 << 16).to_be_bytes()).is_err());
+        Ok(())
+    }
+    #[test]
+    fn decode_frontend_message() -> Result<()> {
+        let mut body = b"p1\0s1\0".to_vec();
+        // one text format, two parameters, one of them NULL, and no result format
+        body.extend_from_slice(&[0, 1, 0, 0, 0, 2]);
+        body.extend_from_slice(&[0, 0, 0, 2, b'4', b'2', 0xff, 0xff, 0xff, 0xff]);
+        body.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            FrontendMessage::decode(b'B', &body)?,
+            FrontendMessage::Bind {
+                portal: "p1".to_string(),
+                statement: "s1".to_string(),
+                param_formats: vec![0],
+                params: vec![Some(b"42".to_vec()), None],
+                result_formats: vec![],
+            }
+        );
+        assert_eq!(
+            FrontendMessage::decode(b'D', b"Ss1\0")?,
+            FrontendMessage::Describe {
+                target: Target::Statement,
+                name: "s1".to_string(),
+            }
+        );
+        assert!(FrontendMessage::decode(b'B', b"p1\0s1\0\0\x01").is_err());
+        assert!(FrontendMessage::decode(b'Q', b"SELECT 1").is_err());
+        assert!(FrontendMessage::decode(b'?', b"").is_err());
+        Ok(())
+    }
+    #[test]
+    fn encode_backend_message() {
+        let mut buf = Vec::new();
+        BackendMessage::ReadyForQuery(TransactionStatus::Idle).encode(&mut buf);
+        BackendMessage::RowDescription(vec![FieldDescription {
+            name: "id".to_string(),
+            type_oid: 23,
+            type_size: 4,
+            format: 0,
+        }])
+        .encode(&mut buf);
+        BackendMessage::DataRow(vec![Some(b"1".to_vec()), None]).encode(&mut buf);
+        assert_eq!(
+            buf,
+            [
+                &[b'Z', 0, 0, 0, 5, b'I'][..],
+                &[b'T', 0, 0, 0, 27, 0, 1, b'i', b'd', 0],
+                &[0, 0, 0, 0, 0, 0, 0, 0, 0, 23, 0, 4, 0xff, 0xff, 0xff, 0xff, 0, 0],
+                &[b'D', 0, 0, 0, 15, 0, 2, 0, 0, 0, 1, b'1', 0xff, 0xff, 0xff, 0xff],
+            ]
+            .concat()
+        );
+    }
+}
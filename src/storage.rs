@@ -1,3 +1,4 @@
+pub mod btree;
 pub mod page;
 pub mod table;
 pub mod tuple;
@@ -0,0 +1,638 @@
+use std::io::{Read, Write};
+use std::mem;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::tuple::{Tuple, TupleId};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::Schema;
+use crate::disk::PAGE_SIZE;
+use crate::value::Value;
+use crate::wal::{TxnId, Undo, PAGE_LSN_SIZE};
+
+const NODE_KIND_INTERNAL: u8 = 0;
+const NODE_KIND_LEAF: u8 = 1;
+// Every node holds at least four entries, so both halves of a split fit in a page.
+const MAX_KEY_SIZE: usize = PAGE_SIZE / 4 - 32;
+
+// Entries are ordered by their key and then by the tuple id, so the duplicate keys of a
+// non-unique index are still totally ordered and each entry can be deleted exactly.
+pub type IndexEntry = (Vec<Value>, TupleId);
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+enum Node {
+    // `keys[i]` is a lower bound of the entries of `children[i + 1]`
+    // and an upper bound (exclusive) of those of `children[i]`.
+    Internal {
+        keys: Vec<IndexEntry>,
+        children: Vec<usize>,
+    },
+    Leaf {
+        entries: Vec<IndexEntry>,
+        next_block_number: i32,
+    },
+}
+
+// A node starts with its LSN (8), kind (1), the number of keys (2) and the next leaf (4).
+impl Node {
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; PAGE_LSN_SIZE];
+        match self {
+            Node::Internal { keys, children } => {
+                buf.write_all(&[NODE_KIND_INTERNAL])?;
+                buf.write_all(&(keys.len() as u16).to_be_bytes())?;
+                buf.write_all(&(-1i32).to_be_bytes())?;
+                buf.write_all(&(children[0] as u32).to_be_bytes())?;
+                for (key, child) in keys.iter().zip(children[1..].iter()) {
+                    write_entry(&mut buf, key)?;
+                    buf.write_all(&(*child as u32).to_be_bytes())?;
+                }
+            }
+            Node::Leaf {
+                entries,
+                next_block_number,
+            } => {
+                buf.write_all(&[NODE_KIND_LEAF])?;
+                buf.write_all(&(entries.len() as u16).to_be_bytes())?;
+                buf.write_all(&next_block_number.to_be_bytes())?;
+                for entry in entries.iter() {
+                    write_entry(&mut buf, entry)?;
+                }
+            }
+        }
+        Ok(buf)
+    }
+    fn deserialize(data: &[u8], key_schema: &Schema) -> Result<Node> {
+        let mut reader = &data[PAGE_LSN_SIZE..];
+        let mut kind = [0u8; 1];
+        reader.read_exact(&mut kind)?;
+        let count = read_u16(&mut reader)? as usize;
+        let next_block_number = read_u32(&mut reader)? as i32;
+        match kind[0] {
+            NODE_KIND_INTERNAL => {
+                let mut keys = Vec::new();
+                let mut children = vec![read_u32(&mut reader)? as usize];
+                for _ in 0..count {
+                    keys.push(read_entry(&mut reader, key_schema)?);
+                    children.push(read_u32(&mut reader)? as usize);
+                }
+                Ok(Node::Internal { keys, children })
+            }
+            NODE_KIND_LEAF => {
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    entries.push(read_entry(&mut reader, key_schema)?);
+                }
+                Ok(Node::Leaf {
+                    entries,
+                    next_block_number,
+                })
+            }
+            kind => Err(anyhow!("unknown index node kind {}", kind)),
+        }
+    }
+}
+
+// An entry is the size of its key (2), the key as a tuple, and the tuple id (4 + 2).
+fn write_entry(buf: &mut Vec<u8>, entry: &IndexEntry) -> Result<()> {
+    let key = Tuple {
+        values: entry.0.clone(),
+    }
+    .serialize()?;
+    buf.write_all(&(key.len() as u16).to_be_bytes())?;
+    buf.write_all(&key)?;
+    buf.write_all(&(entry.1.block_number as u32).to_be_bytes())?;
+    buf.write_all(&(entry.1.slot_number as u16).to_be_bytes())?;
+    Ok(())
+}
+
+fn read_entry(reader: &mut &[u8], key_schema: &Schema) -> Result<IndexEntry> {
+    let size = read_u16(reader)? as usize;
+    let mut key = vec![0u8; size];
+    reader.read_exact(&mut key)?;
+    let key = Tuple::deserialize(&key, key_schema)?.values;
+    let block_number = read_u32(reader)? as usize;
+    let slot_number = read_u16(reader)? as usize;
+    Ok((
+        key,
+        TupleId {
+            block_number,
+            slot_number,
+        },
+    ))
+}
+
+fn read_u16(reader: &mut &[u8]) -> Result<u16> {
+    let mut buf = [0u8; mem::size_of::<u16>()];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0u8; mem::size_of::<u32>()];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_node(
+    buffer_pool_manager: &Arc<BufferPoolManager>,
+    key_schema: &Schema,
+    block_number: usize,
+) -> Result<Node> {
+    let res = buffer_pool_manager.fetch_page(block_number)?;
+    let node = Node::deserialize(&res.data.read().unwrap(), key_schema);
+    buffer_pool_manager.unpin_frame(res.frame_id, false);
+    node
+}
+
+// Index of the split point of a node, chosen so that both halves have about the same size.
+fn split_point(sizes: &[usize]) -> usize {
+    let total: usize = sizes.iter().sum();
+    let mut size = 0;
+    for (i, s) in sizes.iter().enumerate() {
+        size += s;
+        if size * 2 >= total {
+            return i.clamp(1, sizes.len() - 2);
+        }
+    }
+    sizes.len() / 2
+}
+
+fn entry_size(entry: &IndexEntry) -> Result<usize> {
+    let mut buf = Vec::new();
+    write_entry(&mut buf, entry)?;
+    Ok(buf.len())
+}
+
+// Compares the first columns of `key` with a bound on a prefix of the key.
+fn compare_prefix(key: &[Value], bound: &[Value]) -> std::cmp::Ordering {
+    key[..bound.len().min(key.len())].cmp(bound)
+}
+
+fn below_lower(key: &[Value], lower: &Bound<Vec<Value>>) -> bool {
+    match lower {
+        Bound::Included(bound) => compare_prefix(key, bound).is_lt(),
+        Bound::Excluded(bound) => compare_prefix(key, bound).is_le(),
+        Bound::Unbounded => false,
+    }
+}
+
+fn above_upper(key: &[Value], upper: &Bound<Vec<Value>>) -> bool {
+    match upper {
+        Bound::Included(bound) => compare_prefix(key, bound).is_gt(),
+        Bound::Excluded(bound) => compare_prefix(key, bound).is_ge(),
+        Bound::Unbounded => false,
+    }
+}
+
+// A B+tree whose nodes are pages of the buffer pool. The root never moves, so the index
+// is identified by the block number of its root.
+// Deleted entries leave their leaves in place; nodes are never merged.
+#[derive(Debug)]
+pub struct BTree<'a> {
+    buffer_pool_manager: &'a Arc<BufferPoolManager>,
+    key_schema: &'a Schema,
+    pub root_block_number: usize,
+}
+
+impl<'a> BTree<'a> {
+    pub fn new(
+        buffer_pool_manager: &'a Arc<BufferPoolManager>,
+        key_schema: &'a Schema,
+        root_block_number: usize,
+    ) -> Self {
+        BTree {
+            buffer_pool_manager,
+            key_schema,
+            root_block_number,
+        }
+    }
+    pub fn create(
+        buffer_pool_manager: &'a Arc<BufferPoolManager>,
+        key_schema: &'a Schema,
+    ) -> Result<Self> {
+        let mut tree = BTree::new(buffer_pool_manager, key_schema, 0);
+        tree.root_block_number = tree.allocate(&Node::Leaf {
+            entries: Vec::new(),
+            next_block_number: -1,
+        })?;
+        Ok(tree)
+    }
+    pub fn insert(&self, txn_id: TxnId, key: Vec<Value>, tuple_id: TupleId) -> Result<()> {
+        let latch = self.buffer_pool_manager.index_latch(self.root_block_number);
+        let _guard = latch.lock().unwrap();
+        self.insert_entry(txn_id, (key, tuple_id))
+    }
+    // Inserts the entry unless one of the tuples with the same key is live, as told by
+    // `is_live`. Returns that tuple if the key is taken.
+    pub fn insert_unique<F>(
+        &self,
+        txn_id: TxnId,
+        key: Vec<Value>,
+        tuple_id: TupleId,
+        is_live: F,
+    ) -> Result<Option<TupleId>>
+    where
+        F: Fn(&TupleId) -> Result<bool>,
+    {
+        let latch = self.buffer_pool_manager.index_latch(self.root_block_number);
+        let _guard = latch.lock().unwrap();
+        for entry in self.scan(Bound::Included(key.clone()), Bound::Included(key.clone()))? {
+            let (_, live_tuple_id) = entry?;
+            if is_live(&live_tuple_id)? {
+                return Ok(Some(live_tuple_id));
+            }
+        }
+        self.insert_entry(txn_id, (key, tuple_id))?;
+        Ok(None)
+    }
+    fn insert_entry(&self, txn_id: TxnId, entry: IndexEntry) -> Result<()> {
+        if entry_size(&entry)? > MAX_KEY_SIZE {
+            return Err(anyhow!("index key is too large"));
+        }
+        if let Some((key, right)) = self.insert_into(txn_id, self.root_block_number, entry)? {
+            // The root keeps its block number, so its old content moves to a new page.
+            let root = read_node(
+                self.buffer_pool_manager,
+                self.key_schema,
+                self.root_block_number,
+            )?;
+            let left = self.allocate(&root)?;
+            self.write_node(
+                txn_id,
+                self.root_block_number,
+                &Node::Internal {
+                    keys: vec![key],
+                    children: vec![left, right],
+                },
+            )?;
+        }
+        Ok(())
+    }
+    // Returns false if the entry is not found.
+    pub fn delete(&self, txn_id: TxnId, key: Vec<Value>, tuple_id: TupleId) -> Result<bool> {
+        let latch = self.buffer_pool_manager.index_latch(self.root_block_number);
+        let _guard = latch.lock().unwrap();
+        let entry = (key, tuple_id);
+        let mut block_number = self.root_block_number;
+        loop {
+            match read_node(self.buffer_pool_manager, self.key_schema, block_number)? {
+                Node::Internal { keys, children } => {
+                    block_number = children[keys.partition_point(|key| key <= &entry)];
+                }
+                Node::Leaf {
+                    mut entries,
+                    next_block_number,
+                } => {
+                    return match entries.binary_search(&entry) {
+                        Ok(i) => {
+                            entries.remove(i);
+                            self.write_node(
+                                txn_id,
+                                block_number,
+                                &Node::Leaf {
+                                    entries,
+                                    next_block_number,
+                                },
+                            )?;
+                            Ok(true)
+                        }
+                        Err(_) => Ok(false),
+                    };
+                }
+            }
+        }
+    }
+    // Scans the entries in key order. The bounds may cover only the first columns of the key.
+    pub fn scan(&self, lower: Bound<Vec<Value>>, upper: Bound<Vec<Value>>) -> Result<BTreeScan> {
+        let mut block_number = self.root_block_number;
+        while let Node::Internal { keys, children } =
+            read_node(self.buffer_pool_manager, self.key_schema, block_number)?
+        {
+            block_number = children[keys.partition_point(|key| below_lower(&key.0, &lower))];
+        }
+        Ok(BTreeScan {
+            buffer_pool_manager: self.buffer_pool_manager.clone(),
+            key_schema: self.key_schema.clone(),
+            next_block_number: block_number as i32,
+            entries: Vec::new(),
+            lower,
+            upper,
+        })
+    }
+    // Frees every node of the tree. The tree must not be used afterwards.
+    pub fn drop_pages(&self, txn_id: TxnId) -> Result<()> {
+        let mut block_numbers = Vec::new();
+        self.collect_nodes(self.root_block_number, &mut block_numbers)?;
+        for block_number in block_numbers {
+            self.buffer_pool_manager.delete_page(txn_id, block_number)?;
+        }
+        Ok(())
+    }
+    fn collect_nodes(&self, block_number: usize, block_numbers: &mut Vec<usize>) -> Result<()> {
+        if let Node::Internal { children, .. } =
+            read_node(self.buffer_pool_manager, self.key_schema, block_number)?
+        {
+            for child in children {
+                self.collect_nodes(child, block_numbers)?;
+            }
+        }
+        block_numbers.push(block_number);
+        Ok(())
+    }
+    // Returns the first key and the block number of the new right sibling if the node splits.
+    fn insert_into(
+        &self,
+        txn_id: TxnId,
+        block_number: usize,
+        entry: IndexEntry,
+    ) -> Result<Option<(IndexEntry, usize)>> {
+        match read_node(self.buffer_pool_manager, self.key_schema, block_number)? {
+            Node::Leaf {
+                mut entries,
+                next_block_number,
+            } => {
+                let i = match entries.binary_search(&entry) {
+                    Ok(_) => return Err(anyhow!("index entry {:?} already exists", entry)),
+                    Err(i) => i,
+                };
+                entries.insert(i, entry);
+                let node = Node::Leaf {
+                    entries,
+                    next_block_number,
+                };
+                if node.serialize()?.len() <= PAGE_SIZE {
+                    self.write_node(txn_id, block_number, &node)?;
+                    return Ok(None);
+                }
+                let mut entries = match node {
+                    Node::Leaf { entries, .. } => entries,
+                    _ => unreachable!(),
+                };
+                let sizes = entries
+                    .iter()
+                    .map(entry_size)
+                    .collect::<Result<Vec<usize>>>()?;
+                let right_entries = entries.split_off(split_point(&sizes));
+                let key = right_entries[0].clone();
+                let right = self.allocate(&Node::Leaf {
+                    entries: right_entries,
+                    next_block_number,
+                })?;
+                self.write_node(
+                    txn_id,
+                    block_number,
+                    &Node::Leaf {
+                        entries,
+                        next_block_number: right as i32,
+                    },
+                )?;
+                Ok(Some((key, right)))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let i = keys.partition_point(|key| key <= &entry);
+                let (key, right) = match self.insert_into(txn_id, children[i], entry)? {
+                    Some(split) => split,
+                    None => return Ok(None),
+                };
+                keys.insert(i, key);
+                children.insert(i + 1, right);
+                let node = Node::Internal { keys, children };
+                if node.serialize()?.len() <= PAGE_SIZE {
+                    self.write_node(txn_id, block_number, &node)?;
+                    return Ok(None);
+                }
+                let (mut keys, mut children) = match node {
+                    Node::Internal { keys, children } => (keys, children),
+                    _ => unreachable!(),
+                };
+                let sizes = keys
+                    .iter()
+                    .map(entry_size)
+                    .collect::<Result<Vec<usize>>>()?;
+                // The middle key moves up to the parent.
+                let at = split_point(&sizes);
+                let right_keys = keys.split_off(at + 1);
+                let key = keys.pop().unwrap();
+                let right_children = children.split_off(at + 1);
+                let right = self.allocate(&Node::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                })?;
+                self.write_node(txn_id, block_number, &Node::Internal { keys, children })?;
+                Ok(Some((key, right)))
+            }
+        }
+    }
+    fn allocate(&self, node: &Node) -> Result<usize> {
+        let mut data = node.serialize()?;
+        data.resize(PAGE_SIZE, 0);
+        let res = self.buffer_pool_manager.new_page(&data)?;
+        self.buffer_pool_manager.unpin_frame(res.frame_id, true);
+        Ok(res.block_number)
+    }
+    fn write_node(&self, txn_id: TxnId, block_number: usize, node: &Node) -> Result<()> {
+        let mut data = node.serialize()?;
+        data.resize(PAGE_SIZE, 0);
+        let res = self.buffer_pool_manager.fetch_page(block_number)?;
+        let page_data = res.data.write().unwrap();
+        // The entries outlive an aborted transaction. The readers skip those of the tuples
+        // they do not see.
+        self.buffer_pool_manager
+            .write_page(txn_id, &res, page_data, &data, Undo::None)
+    }
+}
+
+// Iterates over the entries within the bounds, one leaf at a time.
+#[derive(Debug)]
+pub struct BTreeScan {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    key_schema: Schema,
+    next_block_number: i32,
+    // Entries of the current leaf not returned yet, in reverse order.
+    entries: Vec<IndexEntry>,
+    lower: Bound<Vec<Value>>,
+    upper: Bound<Vec<Value>>,
+}
+
+impl BTreeScan {
+    fn fetch_next_leaf(&mut self) -> Result<()> {
+        match read_node(
+            &self.buffer_pool_manager,
+            &self.key_schema,
+            self.next_block_number as usize,
+        )? {
+            Node::Leaf {
+                entries,
+                next_block_number,
+            } => {
+                self.next_block_number = next_block_number;
+                self.entries = entries.into_iter().rev().collect();
+                Ok(())
+            }
+            Node::Internal { .. } => Err(anyhow!("expected a leaf node")),
+        }
+    }
+}
+
+impl Iterator for BTreeScan {
+    type Item = Result<IndexEntry>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.pop() {
+                if below_lower(&entry.0, &self.lower) {
+                    continue;
+                }
+                if above_upper(&entry.0, &self.upper) {
+                    self.entries.clear();
+                    self.next_block_number = -1;
+                    return None;
+                }
+                return Some(Ok(entry));
+            }
+            if self.next_block_number == -1 {
+                return None;
+            }
+            if let Err(e) = self.fetch_next_leaf() {
+                self.next_block_number = -1;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{Column, ColumnType, Schema};
+    use crate::disk::DiskManager;
+    use crate::storage::btree::BTree;
+    use crate::storage::tuple::TupleId;
+    use crate::transaction::TransactionManager;
+    use crate::value::Value;
+    use anyhow::Result;
+    use std::fs;
+    use std::ops::Bound;
+    use std::sync::Arc;
+    fn key_schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column {
+                    name: "name".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+            ],
+        }
+    }
+    fn key(i: i32) -> Vec<Value> {
+        vec![Value::Varchar(format!("name{:02}", i % 50)), Value::Int(i)]
+    }
+    fn tuple_id(i: i32) -> TupleId {
+        TupleId {
+            block_number: i as usize / 10,
+            slot_number: i as usize % 10,
+        }
+    }
+    fn buffer_pool_manager(dir: &str) -> Result<(Arc<DiskManager>, Arc<BufferPoolManager>)> {
+        fs::create_dir_all(dir)?;
+        let disk_manager = Arc::new(DiskManager::new(dir.to_string()));
+        disk_manager.init_data_file()?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+        Ok((disk_manager, buffer_pool_manager))
+    }
+    #[test]
+    fn insert_and_scan() -> Result<()> {
+        let (_, buffer_pool_manager) = buffer_pool_manager("tmp/btree_insert_and_scan/")?;
+        let key_schema = key_schema();
+        let tree = BTree::create(&buffer_pool_manager, &key_schema)?;
+        let txn_id = buffer_pool_manager.log_manager().begin();
+        // Enough entries to split the root.
+        let n = 5000;
+        for i in 0..n {
+            let i = (i * 7919) % n;
+            tree.insert(txn_id, key(i), tuple_id(i))?;
+        }
+        assert!(tree.insert(txn_id, key(3), tuple_id(3)).is_err());
+        let all = tree
+            .scan(Bound::Unbounded, Bound::Unbounded)?
+            .collect::<Result<Vec<_>>>()?;
+        let mut expected: Vec<_> = (0..n).map(|i| (key(i), tuple_id(i))).collect();
+        expected.sort();
+        assert_eq!(all, expected);
+        // Equality on the first column of the key.
+        let name07 = vec![Value::Varchar("name07".to_string())];
+        let ids = tree
+            .scan(Bound::Included(name07.clone()), Bound::Included(name07))?
+            .map(|entry| entry.map(|(key, _)| key[1].clone()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            ids,
+            (0..n)
+                .filter(|i| i % 50 == 7)
+                .map(Value::Int)
+                .collect::<Vec<_>>()
+        );
+        // A range on the second column after an equality on the first one.
+        let lower = vec![Value::Varchar("name07".to_string()), Value::Int(1000)];
+        let upper = vec![Value::Varchar("name07".to_string()), Value::Int(1207)];
+        let ids = tree
+            .scan(Bound::Excluded(lower), Bound::Included(upper))?
+            .map(|entry| entry.map(|(key, _)| key[1].clone()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            ids,
+            vec![1007, 1057, 1107, 1157, 1207]
+                .into_iter()
+                .map(Value::Int)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+    #[test]
+    fn delete_and_drop() -> Result<()> {
+        let (disk_manager, buffer_pool_manager) =
+            buffer_pool_manager("tmp/btree_delete_and_drop/")?;
+        let key_schema = key_schema();
+        let tree = BTree::create(&buffer_pool_manager, &key_schema)?;
+        let txn_id = buffer_pool_manager.log_manager().begin();
+        let n = 2000;
+        for i in 0..n {
+            tree.insert(txn_id, key(i), tuple_id(i))?;
+        }
+        for i in (0..n).filter(|i| i % 3 != 0) {
+            assert!(tree.delete(txn_id, key(i), tuple_id(i))?);
+        }
+        assert!(!tree.delete(txn_id, key(1), tuple_id(1))?);
+        let all = tree
+            .scan(Bound::Unbounded, Bound::Unbounded)?
+            .collect::<Result<Vec<_>>>()?;
+        let mut expected: Vec<_> = (0..n)
+            .filter(|i| i % 3 == 0)
+            .map(|i| (key(i), tuple_id(i)))
+            .collect();
+        expected.sort();
+        assert_eq!(all, expected);
+        // Reopening the tree from its root finds the same entries.
+        let tree = BTree::new(&buffer_pool_manager, &key_schema, tree.root_block_number);
+        assert_eq!(
+            tree.scan(Bound::Unbounded, Bound::Unbounded)?.count(),
+            expected.len()
+        );
+        // The pages are freed once the transaction commits.
+        tree.drop_pages(txn_id)?;
+        assert_eq!(disk_manager.free_page_count()?, 0);
+        TransactionManager::new(buffer_pool_manager.clone()).commit(txn_id)?;
+        let page_count = fs::metadata("tmp/btree_delete_and_drop/data")?.len() as usize / 4096;
+        assert_eq!(disk_manager.free_page_count()?, page_count);
+        Ok(())
+    }
+}
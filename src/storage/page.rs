@@ -1,56 +1,90 @@
 use std::io;
 use std::io::{Seek, Write};
+use std::mem;
 
 use anyhow::Result;
 
 use super::tuple::Tuple;
 use crate::catalog::Schema;
+use crate::wal::{Lsn, TxnId, PAGE_LSN_SIZE};
 
 pub const TABLE_PAGE_SIZE: usize = 4096;
+// Bumped whenever the layout of table pages or tuples changes, so that a data file written in
+// another layout is rejected instead of misread.
+pub const TABLE_PAGE_FORMAT_VERSION: u16 = 1;
+const TABLE_PAGE_HEADER_VERSION_SIZE: usize = 2;
 const TABLE_PAGE_HEADER_NEXT_BLOCK_NUMBER_SIZE: usize = 4;
 const TABLE_PAGE_HEADER_LOWER_OFFSET_SIZE: usize = 2;
 const TABLE_PAGE_HEADER_UPPER_OFFSET_SIZE: usize = 2;
-const TABLE_PAGE_HEADER_SIZE: usize = TABLE_PAGE_HEADER_NEXT_BLOCK_NUMBER_SIZE
+const TABLE_PAGE_HEADER_SIZE: usize = PAGE_LSN_SIZE
+    + TABLE_PAGE_HEADER_VERSION_SIZE
+    + TABLE_PAGE_HEADER_NEXT_BLOCK_NUMBER_SIZE
     + TABLE_PAGE_HEADER_LOWER_OFFSET_SIZE
     + TABLE_PAGE_HEADER_UPPER_OFFSET_SIZE;
 const TABLE_PAGE_LINE_POINTER_OFFSET_SIZE: usize = 2;
 const TABLE_PAGE_LINE_POINTER_SIZE_SIZE: usize = 2;
 const TABLE_PAGE_LINE_POINTER_SIZE: usize =
     TABLE_PAGE_LINE_POINTER_OFFSET_SIZE + TABLE_PAGE_LINE_POINTER_SIZE_SIZE;
+// The offset never exceeds TABLE_PAGE_SIZE, so its highest bit marks a dead line pointer.
+const TABLE_PAGE_LINE_POINTER_DEAD_FLAG: u16 = 0x8000;
+const TUPLE_HEADER_XMIN_SIZE: usize = mem::size_of::<TxnId>();
+const TUPLE_HEADER_XMAX_SIZE: usize = mem::size_of::<TxnId>();
+const TUPLE_HEADER_SIZE: usize = TUPLE_HEADER_XMIN_SIZE + TUPLE_HEADER_XMAX_SIZE;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct TablePage {
     pub header: TablePageHeader,
     pub line_pointers: Vec<TablePageLinePointer>,
-    pub tuples: Vec<Tuple>,
+    // None for dead slots.
+    pub tuples: Vec<Option<Tuple>>,
+    pub tuple_headers: Vec<TupleHeader>,
 }
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct TablePageHeader {
+    // LSN of the last log record applied to the page
+    pub lsn: Lsn,
     pub next_block_number: i32,
     pub lower_offset: u16,
     pub upper_offset: u16,
 }
+// Every tuple is a version created by the transaction `xmin` and deleted by the transaction
+// `xmax`, which is 0 while the version is alive.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
+pub struct TupleHeader {
+    pub xmin: TxnId,
+    pub xmax: TxnId,
+}
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct TablePageLinePointer {
     pub offset: u16,
     pub size: u16,
+    pub state: TablePageLinePointerState,
+}
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum TablePageLinePointerState {
+    Normal,
+    Dead,
 }
 
 impl TablePage {
     pub fn new() -> Self {
         TablePage {
             header: TablePageHeader {
+                lsn: 0,
                 next_block_number: -1,
                 lower_offset: TABLE_PAGE_HEADER_SIZE as u16,
                 upper_offset: TABLE_PAGE_SIZE as u16,
             },
             line_pointers: Vec::new(),
             tuples: Vec::new(),
+            tuple_headers: Vec::new(),
         }
     }
     pub fn serialize(&self) -> Result<Vec<u8>> {
         let mut cur = io::Cursor::new(vec![0u8; TABLE_PAGE_SIZE]);
         cur.seek(io::SeekFrom::Start(0))?;
+        cur.write_all(&self.header.lsn.to_be_bytes())?;
+        cur.write_all(&TABLE_PAGE_FORMAT_VERSION.to_be_bytes())?;
         cur.write_all(&self.header.next_block_number.to_be_bytes())?;
         cur.write_all(&self.header.lower_offset.to_be_bytes())?;
         cur.write_all(&self.header.upper_offset.to_be_bytes())?;
@@ -58,73 +92,179 @@ impl TablePage {
             cur.seek(io::SeekFrom::Start(
                 (TABLE_PAGE_HEADER_SIZE + i * TABLE_PAGE_LINE_POINTER_SIZE) as u64,
             ))?;
-            cur.write_all(&line_pointer.offset.to_be_bytes())?;
+            let offset = match line_pointer.state {
+                TablePageLinePointerState::Normal => line_pointer.offset,
+                TablePageLinePointerState::Dead => {
+                    line_pointer.offset | TABLE_PAGE_LINE_POINTER_DEAD_FLAG
+                }
+            };
+            cur.write_all(&offset.to_be_bytes())?;
             cur.write_all(&line_pointer.size.to_be_bytes())?;
-            cur.seek(io::SeekFrom::Start(line_pointer.offset as u64))?;
-            let tuple_data = self.tuples[i].serialize()?;
-            cur.write_all(&tuple_data)?;
+            if let Some(tuple) = &self.tuples[i] {
+                cur.seek(io::SeekFrom::Start(line_pointer.offset as u64))?;
+                cur.write_all(&self.tuple_headers[i].xmin.to_be_bytes())?;
+                cur.write_all(&self.tuple_headers[i].xmax.to_be_bytes())?;
+                let tuple_data = tuple.serialize()?;
+                cur.write_all(&tuple_data)?;
+            }
         }
         Ok(cur.into_inner())
     }
     pub fn deserialize(data: &[u8], schema: &Schema) -> Result<TablePage> {
+        let mut lsn_buf = [0u8; 8];
+        lsn_buf.clone_from_slice(&data[0..8]);
+        let lsn = Lsn::from_be_bytes(lsn_buf);
+        check_format_version(data)?;
         let mut next_block_number_buf = [0u8; 4];
-        next_block_number_buf.clone_from_slice(&data[0..4]);
+        next_block_number_buf.clone_from_slice(&data[10..14]);
         let next_block_number = i32::from_be_bytes(next_block_number_buf);
         let mut lower_offset_buf = [0u8; 2];
-        lower_offset_buf.clone_from_slice(&data[4..6]);
+        lower_offset_buf.clone_from_slice(&data[14..16]);
         let lower_offset = u16::from_be_bytes(lower_offset_buf);
         let mut upper_offset_buf = [0u8; 2];
-        upper_offset_buf.clone_from_slice(&data[6..8]);
+        upper_offset_buf.clone_from_slice(&data[16..18]);
         let upper_offset = u16::from_be_bytes(upper_offset_buf);
         let tuple_num =
             (lower_offset - TABLE_PAGE_HEADER_SIZE as u16) / TABLE_PAGE_LINE_POINTER_SIZE as u16;
         let mut line_pointers: Vec<TablePageLinePointer> = Vec::new();
-        let mut tuples: Vec<Tuple> = Vec::new();
+        let mut tuples: Vec<Option<Tuple>> = Vec::new();
+        let mut tuple_headers: Vec<TupleHeader> = Vec::new();
         for n in 0..tuple_num {
             let start = TABLE_PAGE_HEADER_SIZE + n as usize * TABLE_PAGE_LINE_POINTER_SIZE;
             let end = TABLE_PAGE_HEADER_SIZE + (n + 1) as usize * TABLE_PAGE_LINE_POINTER_SIZE;
             let line_pointer_data = &data[start..end];
             let mut offset_buf = [0u8; 2];
             offset_buf.clone_from_slice(&line_pointer_data[0..2]);
-            let offset = u16::from_be_bytes(offset_buf);
+            let raw_offset = u16::from_be_bytes(offset_buf);
             let mut size_buf = [0u8; 2];
             size_buf.clone_from_slice(&line_pointer_data[2..4]);
             let size = u16::from_be_bytes(size_buf);
-            line_pointers.push(TablePageLinePointer { offset, size });
+            let offset = raw_offset & !TABLE_PAGE_LINE_POINTER_DEAD_FLAG;
+            if raw_offset & TABLE_PAGE_LINE_POINTER_DEAD_FLAG != 0 {
+                line_pointers.push(TablePageLinePointer {
+                    offset,
+                    size,
+                    state: TablePageLinePointerState::Dead,
+                });
+                tuples.push(None);
+                tuple_headers.push(TupleHeader::default());
+                continue;
+            }
+            line_pointers.push(TablePageLinePointer {
+                offset,
+                size,
+                state: TablePageLinePointerState::Normal,
+            });
 
             let tuple_start = offset as usize;
             let tuple_end = (offset + size) as usize;
-            let tuple_data = &data[tuple_start..tuple_end];
-            let tuple = Tuple::deserialize(tuple_data, &schema)?;
-            tuples.push(tuple);
+            tuple_headers.push(read_tuple_header(&data[tuple_start..]));
+            let tuple_data = &data[tuple_start + TUPLE_HEADER_SIZE..tuple_end];
+            let tuple = Tuple::deserialize(tuple_data, schema)?;
+            tuples.push(Some(tuple));
         }
         Ok(TablePage {
             header: TablePageHeader {
+                lsn,
                 next_block_number,
                 lower_offset,
                 upper_offset,
             },
             line_pointers,
             tuples,
+            tuple_headers,
         })
     }
 
-    pub fn insert_tuple(&mut self, tuple: &Tuple) -> Result<bool> {
-        let b = tuple.serialize()?;
-        let tuple_size = b.len();
-        if ((self.header.upper_offset - self.header.lower_offset) as usize)
-            < TABLE_PAGE_LINE_POINTER_SIZE + tuple_size
-        {
-            return Ok(false);
+    // Returns the slot number of the tuple inserted by the transaction `xmin`, or None if
+    // the page has no room for it.
+    pub fn insert_tuple(&mut self, xmin: TxnId, tuple: &Tuple) -> Result<Option<usize>> {
+        let tuple_size = TUPLE_HEADER_SIZE + tuple.serialize()?.len();
+        if !self.reserve(TABLE_PAGE_LINE_POINTER_SIZE + tuple_size) {
+            return Ok(None);
         }
         self.header.lower_offset += TABLE_PAGE_LINE_POINTER_SIZE as u16;
         self.header.upper_offset -= tuple_size as u16;
         self.line_pointers.push(TablePageLinePointer {
             offset: self.header.upper_offset,
             size: tuple_size as u16,
+            state: TablePageLinePointerState::Normal,
         });
-        self.tuples.push(tuple.clone());
-        Ok(true)
+        self.tuples.push(Some(tuple.clone()));
+        self.tuple_headers.push(TupleHeader { xmin, xmax: 0 });
+        Ok(Some(self.line_pointers.len() - 1))
+    }
+    pub fn delete_tuple(&mut self, slot_number: usize) -> Result<()> {
+        match self.line_pointers.get_mut(slot_number) {
+            Some(line_pointer) if line_pointer.state == TablePageLinePointerState::Normal => {
+                line_pointer.state = TablePageLinePointerState::Dead;
+                self.tuples[slot_number] = None;
+                self.tuple_headers[slot_number] = TupleHeader::default();
+                Ok(())
+            }
+            _ => Err(anyhow!("no tuple in slot {}", slot_number)),
+        }
+    }
+    // Deletes the versions which no snapshot sees, that is those deleted by a transaction
+    // before `horizon`. Every transaction before it has ended and is seen by the snapshots
+    // of the running ones. Returns whether any version was deleted.
+    pub fn prune(&mut self, horizon: TxnId) -> Result<bool> {
+        let slot_numbers: Vec<usize> = (0..self.tuples.len())
+            .filter(|&slot_number| {
+                let header = &self.tuple_headers[slot_number];
+                self.tuples[slot_number].is_some()
+                    && header.xmax != 0
+                    && header.xmin < horizon
+                    && header.xmax < horizon
+            })
+            .collect();
+        for &slot_number in &slot_numbers {
+            self.delete_tuple(slot_number)?;
+        }
+        Ok(!slot_numbers.is_empty())
+    }
+    pub fn set_xmax(&mut self, slot_number: usize, xmax: TxnId) -> Result<()> {
+        match self.tuples.get(slot_number) {
+            Some(Some(_)) => {
+                self.tuple_headers[slot_number].xmax = xmax;
+                Ok(())
+            }
+            _ => Err(anyhow!("no tuple in slot {}", slot_number)),
+        }
+    }
+    // Makes sure that `size` bytes are available between the line pointers and the tuples,
+    // compacting the page if the free space is fragmented by dead tuples.
+    fn reserve(&mut self, size: usize) -> bool {
+        if (self.header.upper_offset - self.header.lower_offset) as usize >= size {
+            return true;
+        }
+        let used: usize = self
+            .line_pointers
+            .iter()
+            .zip(self.tuples.iter())
+            .filter(|(_, tuple)| tuple.is_some())
+            .map(|(line_pointer, _)| line_pointer.size as usize)
+            .sum();
+        if (TABLE_PAGE_SIZE - self.header.lower_offset as usize - used) < size {
+            return false;
+        }
+        self.compact();
+        true
+    }
+    // Moves the live tuples to the end of the page without gaps.
+    // Slot numbers are kept, only the offsets in the line pointers change.
+    fn compact(&mut self) {
+        let mut upper_offset = TABLE_PAGE_SIZE as u16;
+        for (line_pointer, tuple) in self.line_pointers.iter_mut().zip(self.tuples.iter()) {
+            if tuple.is_some() {
+                upper_offset -= line_pointer.size;
+                line_pointer.offset = upper_offset;
+            } else if line_pointer.state == TablePageLinePointerState::Dead {
+                line_pointer.offset = 0;
+                line_pointer.size = 0;
+            }
+        }
+        self.header.upper_offset = upper_offset;
     }
 }
 
@@ -134,41 +274,108 @@ impl Default for TablePage {
     }
 }
 
+fn read_tuple_header(data: &[u8]) -> TupleHeader {
+    let mut xmin_buf = [0u8; TUPLE_HEADER_XMIN_SIZE];
+    xmin_buf.clone_from_slice(&data[..TUPLE_HEADER_XMIN_SIZE]);
+    let mut xmax_buf = [0u8; TUPLE_HEADER_XMAX_SIZE];
+    xmax_buf.clone_from_slice(&data[TUPLE_HEADER_XMIN_SIZE..TUPLE_HEADER_SIZE]);
+    TupleHeader {
+        xmin: TxnId::from_be_bytes(xmin_buf),
+        xmax: TxnId::from_be_bytes(xmax_buf),
+    }
+}
+
+pub fn check_format_version(data: &[u8]) -> Result<()> {
+    let mut version_buf = [0u8; TABLE_PAGE_HEADER_VERSION_SIZE];
+    version_buf
+        .clone_from_slice(&data[PAGE_LSN_SIZE..PAGE_LSN_SIZE + TABLE_PAGE_HEADER_VERSION_SIZE]);
+    let version = u16::from_be_bytes(version_buf);
+    if version != TABLE_PAGE_FORMAT_VERSION {
+        return Err(anyhow!(
+            "unsupported page format version {} (expected {}), initialize the data directory again",
+            version,
+            TABLE_PAGE_FORMAT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+// Position of the line pointer of a live tuple in a serialized page.
+fn line_pointer_position(data: &[u8], slot_number: usize) -> Result<usize> {
+    let mut lower_offset_buf = [0u8; 2];
+    lower_offset_buf.clone_from_slice(&data[14..16]);
+    let lower_offset = u16::from_be_bytes(lower_offset_buf) as usize;
+    let position = TABLE_PAGE_HEADER_SIZE + slot_number * TABLE_PAGE_LINE_POINTER_SIZE;
+    if position + TABLE_PAGE_LINE_POINTER_SIZE > lower_offset
+        || data[position] & (TABLE_PAGE_LINE_POINTER_DEAD_FLAG >> 8) as u8 != 0
+    {
+        return Err(anyhow!("no tuple in slot {}", slot_number));
+    }
+    Ok(position)
+}
+
+// The following functions change a serialized page in place, since the undo of a change has
+// no schema to deserialize the page with.
+
+// Marks the slot dead like `TablePage::delete_tuple`.
+pub fn kill_tuple(data: &mut [u8], slot_number: usize) -> Result<()> {
+    let position = line_pointer_position(data, slot_number)?;
+    data[position] |= (TABLE_PAGE_LINE_POINTER_DEAD_FLAG >> 8) as u8;
+    Ok(())
+}
+
+pub fn set_tuple_xmax(data: &mut [u8], slot_number: usize, xmax: TxnId) -> Result<()> {
+    let position = line_pointer_position(data, slot_number)?;
+    let mut offset_buf = [0u8; 2];
+    offset_buf.clone_from_slice(&data[position..position + 2]);
+    let start = u16::from_be_bytes(offset_buf) as usize + TUPLE_HEADER_XMIN_SIZE;
+    data[start..start + TUPLE_HEADER_XMAX_SIZE].copy_from_slice(&xmax.to_be_bytes());
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::catalog::{Column, ColumnType, Schema};
-    use crate::storage::page::{TablePage, TablePageHeader, TablePageLinePointer};
+    use crate::storage::page::{
+        TablePage, TablePageHeader, TablePageLinePointer, TablePageLinePointerState, TupleHeader,
+    };
     use crate::storage::tuple::Tuple;
     use crate::value::Value;
-    use std::i32;
     #[test]
     fn serialize() {
         let page = TablePage {
             header: TablePageHeader {
+                lsn: 0,
                 next_block_number: -1,
-                lower_offset: 16,
-                upper_offset: 4062,
+                lower_offset: 26,
+                upper_offset: 4028,
             },
             line_pointers: vec![
                 TablePageLinePointer {
-                    offset: 4085,
-                    size: 11,
+                    offset: 4068,
+                    size: 28,
+                    state: TablePageLinePointerState::Normal,
                 },
                 TablePageLinePointer {
-                    offset: 4062,
-                    size: 23,
+                    offset: 4028,
+                    size: 40,
+                    state: TablePageLinePointerState::Normal,
                 },
             ],
             tuples: vec![
-                Tuple {
+                Some(Tuple {
                     values: vec![Value::Int(i32::MIN), Value::Varchar("foo".to_string())],
-                },
-                Tuple {
+                }),
+                Some(Tuple {
                     values: vec![
                         Value::Int(i32::MAX),
                         Value::Varchar("あいうえお".to_string()),
                     ],
-                },
+                }),
+            ],
+            tuple_headers: vec![
+                TupleHeader { xmin: 1, xmax: 0 },
+                TupleHeader { xmin: 2, xmax: 3 },
             ],
         };
         let b = page.serialize().unwrap();
@@ -176,9 +383,9 @@ mod tests {
             b,
             vec![
                 // header
-                255, 255, 255, 255, 0, 16, 15, 222, // line pointers
-                15, 245, 0, 11, 15, 222, 0, 23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 255, 255, 255, 0, 26, 15,
+                188, // line pointers
+                15, 228, 0, 28, 15, 188, 0, 40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -321,11 +528,12 @@ mod tests {
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                // second tuple
-                127, 255, 255, 255, 0, 0, 0, 15, 227, 129, 130, 227, 129, 132, 227, 129, 134, 227,
-                129, 136, 227, 129, 138, // first tuple
-                128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, // second tuple
+                0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 127, 255, 255, 255, 0, 0, 0, 15,
+                227, 129, 130, 227, 129, 132, 227, 129, 134, 227, 129, 136, 227, 129,
+                138, // first tuple
+                0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 3, 102,
+                111, 111,
             ]
         );
     }
@@ -334,8 +542,9 @@ mod tests {
         let page = TablePage::deserialize(
             &vec![
                 // header
-                255, 255, 255, 255, 0, 16, 15, 222, // line pointers
-                15, 245, 0, 11, 15, 222, 0, 23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 255, 255, 255, 0, 26, 15,
+                188, // line pointers
+                15, 228, 0, 28, 15, 188, 0, 40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -478,12 +687,12 @@ mod tests {
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                // second tuple
-                127, 255, 255, 255, 0, 0, 0, 15, 227, 129, 130, 227, 129, 132, 227, 129, 134, 227,
-                129, 136, 227, 129, 138, // first tuple
-                128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, // second tuple
+                0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 127, 255, 255, 255, 0, 0, 0, 15,
+                227, 129, 130, 227, 129, 132, 227, 129, 134, 227, 129, 136, 227, 129,
+                138, // first tuple
+                0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 3, 102,
+                111, 111,
             ],
             &Schema {
                 columns: vec![
@@ -503,32 +712,115 @@ mod tests {
             page,
             TablePage {
                 header: TablePageHeader {
+                    lsn: 0,
                     next_block_number: -1,
-                    lower_offset: 16,
-                    upper_offset: 4062,
+                    lower_offset: 26,
+                    upper_offset: 4028,
                 },
                 line_pointers: vec![
                     TablePageLinePointer {
-                        offset: 4085,
-                        size: 11,
+                        offset: 4068,
+                        size: 28,
+                        state: TablePageLinePointerState::Normal,
                     },
                     TablePageLinePointer {
-                        offset: 4062,
-                        size: 23,
+                        offset: 4028,
+                        size: 40,
+                        state: TablePageLinePointerState::Normal,
                     },
                 ],
                 tuples: vec![
-                    Tuple {
+                    Some(Tuple {
                         values: vec![Value::Int(i32::MIN), Value::Varchar("foo".to_string())]
-                    },
-                    Tuple {
+                    }),
+                    Some(Tuple {
                         values: vec![
                             Value::Int(i32::MAX),
                             Value::Varchar("あいうえお".to_string())
                         ]
-                    }
+                    })
+                ],
+                tuple_headers: vec![
+                    TupleHeader { xmin: 1, xmax: 0 },
+                    TupleHeader { xmin: 2, xmax: 3 },
                 ],
             }
         );
     }
+    #[test]
+    fn prune() {
+        let schema = Schema {
+            columns: vec![Column {
+                name: "_1".to_string(),
+                column_type: ColumnType::Varchar,
+            }],
+        };
+        let varchar = |n: usize| Tuple {
+            values: vec![Value::Varchar("a".repeat(n))],
+        };
+        let mut page = TablePage::new();
+        page.insert_tuple(1, &varchar(2000)).unwrap();
+        page.insert_tuple(2, &varchar(2000)).unwrap();
+        assert_eq!(page.insert_tuple(3, &varchar(100)).unwrap(), None);
+        page.set_xmax(0, 3).unwrap();
+        page.set_xmax(1, 4).unwrap();
+        // The deleting transaction may still be seen as running.
+        assert!(!page.prune(3).unwrap());
+        assert!(page.prune(4).unwrap());
+        assert!(!page.prune(4).unwrap());
+        // The space of the pruned version is reused by inserts.
+        assert_eq!(page.insert_tuple(5, &varchar(1900)).unwrap(), Some(2));
+        let deserialized = TablePage::deserialize(&page.serialize().unwrap(), &schema).unwrap();
+        assert_eq!(
+            deserialized.tuples,
+            vec![None, Some(varchar(2000)), Some(varchar(1900))]
+        );
+        assert_eq!(
+            deserialized.line_pointers[0].state,
+            TablePageLinePointerState::Dead
+        );
+    }
+    #[test]
+    fn delete_tuple() {
+        let schema = Schema {
+            columns: vec![Column {
+                name: "_1".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let mut page = TablePage::new();
+        for i in 0..3 {
+            page.insert_tuple(
+                1,
+                &Tuple {
+                    values: vec![Value::Int(i)],
+                },
+            )
+            .unwrap();
+        }
+        page.delete_tuple(1).unwrap();
+        assert!(page.delete_tuple(1).is_err());
+        assert!(page.delete_tuple(3).is_err());
+        let b = page.serialize().unwrap();
+        // The dead flag is set on the offset of the second line pointer.
+        assert_eq!(&b[22..26], &[0x8f, 0xd6, 0, 21]);
+        let deserialized = TablePage::deserialize(&b, &schema).unwrap();
+        assert_eq!(deserialized, page);
+        assert_eq!(
+            deserialized.line_pointers[1].state,
+            TablePageLinePointerState::Dead
+        );
+        assert_eq!(
+            deserialized.tuples,
+            vec![
+                Some(Tuple {
+                    values: vec![Value::Int(0)]
+                }),
+                None,
+                Some(Tuple {
+                    values: vec![Value::Int(2)]
+                }),
+            ]
+        );
+    }
 }
@@ -1,19 +1,19 @@
-use std::io::Write;
-use std::sync::Arc;
+use std::sync::{Arc, RwLockWriteGuard};
 
 use anyhow::Result;
 
 use super::page::TablePage;
-use super::tuple::Tuple;
-use crate::buffer::BufferPoolManager;
+use super::tuple::{Tuple, TupleId};
+use crate::buffer::{BufferPoolManager, FetchPageResult};
 use crate::catalog::Schema;
+use crate::transaction::Snapshot;
+use crate::wal::{TxnId, Undo};
 
 #[derive(Debug)]
 pub struct Table<'a> {
     buffer_pool_manager: &'a Arc<BufferPoolManager>,
     schema: &'a Schema,
     pub first_block_number: usize,
-    current_block_number: i32,
 }
 
 impl<'a> Table<'a> {
@@ -26,7 +26,6 @@ impl<'a> Table<'a> {
             buffer_pool_manager,
             schema,
             first_block_number,
-            current_block_number: first_block_number as i32,
         }
     }
     pub fn create(
@@ -39,56 +38,280 @@ impl<'a> Table<'a> {
             buffer_pool_manager,
             schema,
             first_block_number: res.block_number,
-            current_block_number: res.block_number as i32,
         })
     }
-    pub fn insert_tuple(&self, tuple: Tuple) -> Result<()> {
+    // Iterates over the tuples visible to the snapshot.
+    pub fn scan_tuples(&self, snapshot: &Snapshot) -> TableScan {
+        self.scan(Some(snapshot.clone()))
+    }
+    // Iterates over every version of the tuples, including the deleted ones.
+    pub fn scan_all_versions(&self) -> TableScan {
+        self.scan(None)
+    }
+    fn scan(&self, snapshot: Option<Snapshot>) -> TableScan {
+        TableScan {
+            buffer_pool_manager: self.buffer_pool_manager.clone(),
+            schema: self.schema.clone(),
+            snapshot,
+            next_block_number: self.first_block_number as i32,
+            tuples: Vec::new(),
+        }
+    }
+    // Returns None if the slot is empty, out of range or invisible to the snapshot.
+    pub fn get_tuple(&self, snapshot: &Snapshot, tuple_id: &TupleId) -> Result<Option<Tuple>> {
+        let res = self.buffer_pool_manager.fetch_page(tuple_id.block_number)?;
+        let page = TablePage::deserialize(&res.data.read().unwrap(), self.schema);
+        self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        let page = page?;
+        Ok(
+            match page.tuples.into_iter().nth(tuple_id.slot_number).flatten() {
+                Some(tuple) if snapshot.is_visible(&page.tuple_headers[tuple_id.slot_number]) => {
+                    Some(tuple)
+                }
+                _ => None,
+            },
+        )
+    }
+    // Returns whether the version may still be the latest one of its row for the
+    // transaction, that is unless the transaction or a committed one deleted it.
+    pub fn is_live(&self, txn_id: TxnId, tuple_id: &TupleId) -> Result<bool> {
+        let res = self.buffer_pool_manager.fetch_page(tuple_id.block_number)?;
+        let page = TablePage::deserialize(&res.data.read().unwrap(), self.schema);
+        self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        let page = page?;
+        if page
+            .tuples
+            .get(tuple_id.slot_number)
+            .cloned()
+            .flatten()
+            .is_none()
+        {
+            return Ok(false);
+        }
+        let xmax = page.tuple_headers[tuple_id.slot_number].xmax;
+        Ok(xmax == 0 || (xmax != txn_id && self.buffer_pool_manager.log_manager().is_active(xmax)))
+    }
+    pub fn insert_tuple(&self, txn_id: TxnId, tuple: Tuple) -> Result<TupleId> {
+        if TablePage::new().insert_tuple(txn_id, &tuple)?.is_none() {
+            return Err(anyhow!("tuple is too large"));
+        }
         let mut block_number = self.first_block_number;
         loop {
-            let res = self.buffer_pool_manager.fetch_page(block_number)?;
-            let mut page = TablePage::deserialize(&res.data.read().unwrap(), self.schema)?;
-            if page.insert_tuple(&tuple)? {
-                let mut page_data = res.data.write().unwrap();
-                page_data.clear();
-                page_data.write_all(&page.serialize()?)?;
-                self.buffer_pool_manager.unpin_frame(res.frame_id, true);
-                return Ok(());
+            let (slot_number, next_block_number) =
+                self.insert_into_page(txn_id, block_number, &tuple, true)?;
+            if let Some(slot_number) = slot_number {
+                return Ok(TupleId {
+                    block_number,
+                    slot_number,
+                });
+            }
+            block_number = next_block_number as usize;
+        }
+    }
+    // Inserts the tuple into the page if it has room. Returns the slot number, or None if the
+    // page is full, along with the next page, which is created if `extend` is set and the
+    // page is the last one.
+    fn insert_into_page(
+        &self,
+        txn_id: TxnId,
+        block_number: usize,
+        tuple: &Tuple,
+        extend: bool,
+    ) -> Result<(Option<usize>, i32)> {
+        let res = self.buffer_pool_manager.fetch_page(block_number)?;
+        let page_data = res.data.write().unwrap();
+        let mut page = match TablePage::deserialize(&page_data, self.schema) {
+            Ok(page) => page,
+            Err(e) => {
+                drop(page_data);
+                self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+                return Err(e);
+            }
+        };
+        let mut slot_number = page.insert_tuple(txn_id, tuple)?;
+        // A full page makes room by deleting the versions which no snapshot sees. Their
+        // index entries stay and are skipped like those of the other invisible versions.
+        let pruned = slot_number.is_none()
+            && page.prune(self.buffer_pool_manager.log_manager().prune_horizon())?;
+        if pruned {
+            slot_number = page.insert_tuple(txn_id, tuple)?;
+        }
+        if let Some(slot_number) = slot_number {
+            self.write_page(txn_id, &res, page_data, &page, Undo::Insert { slot_number })?;
+            return Ok((Some(slot_number), page.header.next_block_number));
+        }
+        if extend && page.header.next_block_number == -1 {
+            // The new page stays linked even if the transaction aborts.
+            let new_res = match self
+                .buffer_pool_manager
+                .new_page(&TablePage::new().serialize()?)
+            {
+                Ok(new_res) => new_res,
+                Err(e) => {
+                    drop(page_data);
+                    self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+                    return Err(e);
+                }
+            };
+            self.buffer_pool_manager.unpin_frame(new_res.frame_id, true);
+            page.header.next_block_number = new_res.block_number as i32;
+            self.write_page(txn_id, &res, page_data, &page, Undo::None)?;
+        } else if pruned {
+            self.write_page(txn_id, &res, page_data, &page, Undo::None)?;
+        } else {
+            drop(page_data);
+            self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        }
+        Ok((None, page.header.next_block_number))
+    }
+    // Marks the tuple deleted by the transaction. The tuple stays visible to the others until
+    // the transaction commits.
+    pub fn delete_tuple(&self, txn_id: TxnId, tuple_id: &TupleId) -> Result<()> {
+        let res = self.buffer_pool_manager.fetch_page(tuple_id.block_number)?;
+        let page_data = res.data.write().unwrap();
+        let page = TablePage::deserialize(&page_data, self.schema).and_then(|mut page| {
+            // The tuple has been deleted by another transaction which is running or has
+            // committed after the snapshot of this one.
+            if let Some(header) = page.tuple_headers.get(tuple_id.slot_number) {
+                if header.xmax != 0 {
+                    return Err(anyhow!(
+                        "could not serialize access due to a concurrent update"
+                    ));
+                }
             }
-            if page.header.next_block_number == -1 {
-                let mut new_page = TablePage::new();
-                new_page.insert_tuple(&tuple)?;
-                let res = self.buffer_pool_manager.new_page(&new_page.serialize()?)?;
-                page.header.next_block_number = res.block_number as i32;
-                let mut page_data = res.data.write().unwrap();
-                page_data.clear();
-                page_data.write_all(&page.serialize()?)?;
-                self.buffer_pool_manager.unpin_frame(res.frame_id, true);
-            } else {
-                block_number = page.header.next_block_number as usize;
+            page.set_xmax(tuple_id.slot_number, txn_id)?;
+            Ok(page)
+        });
+        match page {
+            Ok(page) => self.write_page(
+                txn_id,
+                &res,
+                page_data,
+                &page,
+                Undo::Delete {
+                    slot_number: tuple_id.slot_number,
+                },
+            ),
+            Err(e) => {
+                drop(page_data);
+                self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+                Err(e)
             }
         }
     }
+    // Deletes the tuple and inserts its new version. Returns the id of the new version.
+    pub fn update_tuple(&self, txn_id: TxnId, tuple_id: &TupleId, tuple: Tuple) -> Result<TupleId> {
+        self.delete_tuple(txn_id, tuple_id)?;
+        // The new version goes to the page of the old one if it has room, which saves walking
+        // the pages from the first one.
+        let block_number = tuple_id.block_number;
+        if let (Some(slot_number), _) =
+            self.insert_into_page(txn_id, block_number, &tuple, false)?
+        {
+            return Ok(TupleId {
+                block_number,
+                slot_number,
+            });
+        }
+        self.insert_tuple(txn_id, tuple)
+    }
+    // Frees every page of the table. The table must not be used afterwards.
+    pub fn drop_pages(&self, txn_id: TxnId) -> Result<()> {
+        // Every page is read before any of them is deleted, so that a failure deletes none.
+        let mut block_numbers = Vec::new();
+        let mut block_number = self.first_block_number as i32;
+        while block_number != -1 {
+            let res = self.buffer_pool_manager.fetch_page(block_number as usize)?;
+            let page = TablePage::deserialize(&res.data.read().unwrap(), self.schema);
+            self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+            block_numbers.push(block_number as usize);
+            block_number = page?.header.next_block_number;
+        }
+        for block_number in block_numbers {
+            self.buffer_pool_manager.delete_page(txn_id, block_number)?;
+        }
+        Ok(())
+    }
+    // Writes the page back into the latched frame, logging the change, and unpins it.
+    fn write_page(
+        &self,
+        txn_id: TxnId,
+        res: &FetchPageResult,
+        page_data: RwLockWriteGuard<Vec<u8>>,
+        page: &TablePage,
+        undo: Undo,
+    ) -> Result<()> {
+        let data = match page.serialize() {
+            Ok(data) => data,
+            Err(e) => {
+                drop(page_data);
+                self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+                return Err(e);
+            }
+        };
+        self.buffer_pool_manager
+            .write_page(txn_id, res, page_data, &data, undo)
+    }
+}
+
+// Iterates over the tuples of a table along with their ids, one page at a time.
+#[derive(Debug)]
+pub struct TableScan {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    schema: Schema,
+    // None to return every version
+    snapshot: Option<Snapshot>,
+    next_block_number: i32,
+    // Tuples of the current page not returned yet, in reverse order.
+    tuples: Vec<(TupleId, Tuple)>,
+}
+
+impl TableScan {
+    fn fetch_next_page(&mut self) -> Result<()> {
+        let block_number = self.next_block_number as usize;
+        let res = self.buffer_pool_manager.fetch_page(block_number)?;
+        let page = TablePage::deserialize(&res.data.read().unwrap(), &self.schema);
+        self.buffer_pool_manager.unpin_frame(res.frame_id, false);
+        let page = page?;
+        self.next_block_number = page.header.next_block_number;
+        let snapshot = &self.snapshot;
+        self.tuples = page
+            .tuples
+            .into_iter()
+            .zip(page.tuple_headers.iter())
+            .enumerate()
+            .rev()
+            .filter_map(|(slot_number, (tuple, header))| match snapshot {
+                Some(snapshot) if !snapshot.is_visible(header) => None,
+                _ => tuple.map(|tuple| {
+                    (
+                        TupleId {
+                            block_number,
+                            slot_number,
+                        },
+                        tuple,
+                    )
+                }),
+            })
+            .collect();
+        Ok(())
+    }
 }
 
-impl<'a> Iterator for Table<'a> {
-    type Item = TablePage;
+impl Iterator for TableScan {
+    type Item = Result<(TupleId, Tuple)>;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_block_number == -1 {
-            return None;
-        }
-        // TODO: remove unwrap
-        let res = self
-            .buffer_pool_manager
-            .fetch_page(self.current_block_number as usize)
-            .unwrap();
-        let data = res.data.read().unwrap();
-        if let Ok(page) = TablePage::deserialize(&data, self.schema) {
-            self.current_block_number = page.header.next_block_number;
-            self.buffer_pool_manager.unpin_frame(res.frame_id, false);
-            Some(page)
-        } else {
-            self.buffer_pool_manager.unpin_frame(res.frame_id, false);
-            None
+        loop {
+            if let Some(item) = self.tuples.pop() {
+                return Some(Ok(item));
+            }
+            if self.next_block_number == -1 {
+                return None;
+            }
+            if let Err(e) = self.fetch_next_page() {
+                self.next_block_number = -1;
+                return Some(Err(e));
+            }
         }
     }
 }
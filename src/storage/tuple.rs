@@ -10,18 +10,39 @@ pub struct Tuple {
     pub values: Vec<Value>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct TupleId {
+    pub block_number: usize,
+    pub slot_number: usize,
+}
+
+// A tuple is serialized as a null bitmap of ceil(n / 8) bytes, where the i-th bit is set
+// when the i-th value is NULL, followed by the non-NULL values.
 impl Tuple {
     pub fn serialize(&self) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
+        let mut buf = vec![0u8; null_bitmap_size(self.values.len())];
+        for (i, value) in self.values.iter().enumerate() {
+            if *value == Value::Null {
+                buf[i / 8] |= 1 << (i % 8);
+            }
+        }
         for value in self.values.iter() {
             buf.write_all(&value.serialize())?;
         }
         Ok(buf)
     }
     pub fn deserialize(data: &[u8], schema: &Schema) -> Result<Tuple> {
+        let bitmap_size = null_bitmap_size(schema.columns.len());
+        if data.len() < bitmap_size {
+            return Err(anyhow!("tuple is too short"));
+        }
         let mut values = Vec::new();
-        let mut position = 0;
-        for column in schema.columns.iter() {
+        let mut position = bitmap_size;
+        for (i, column) in schema.columns.iter().enumerate() {
+            if data[i / 8] & (1 << (i % 8)) != 0 {
+                values.push(Value::Null);
+                continue;
+            }
             let value_and_size = Value::deserialize(&data[position..], &column.column_type)?;
             values.push(value_and_size.0);
             position += value_and_size.1;
@@ -30,24 +51,27 @@ impl Tuple {
     }
 }
 
+fn null_bitmap_size(column_count: usize) -> usize {
+    column_count.div_ceil(8)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::catalog::{Column, ColumnType, Schema};
     use crate::storage::tuple::Tuple;
     use crate::value::Value;
-    use std::i32;
     #[test]
     fn serialize() {
         let tuple = Tuple {
             values: vec![Value::Int(i32::MIN), Value::Varchar("foo".to_string())],
         };
         let b = tuple.serialize().unwrap();
-        assert_eq!(b, vec![128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111]);
+        assert_eq!(b, vec![0, 128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111]);
     }
     #[test]
     fn deserialize() {
         let tuple = Tuple::deserialize(
-            &[128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111],
+            &[0, 128, 0, 0, 0, 0, 0, 0, 3, 102, 111, 111],
             &Schema {
                 columns: vec![
                     Column {
@@ -69,4 +93,21 @@ mod tests {
             }
         );
     }
+    #[test]
+    fn serialize_nulls() {
+        let schema = Schema {
+            columns: (0..9)
+                .map(|i| Column {
+                    name: format!("_{}", i),
+                    column_type: ColumnType::Int,
+                })
+                .collect(),
+        };
+        let mut values = vec![Value::Null; 9];
+        values[1] = Value::Int(7);
+        let tuple = Tuple { values };
+        let b = tuple.serialize().unwrap();
+        assert_eq!(b, vec![0b1111_1101, 0b0000_0001, 0, 0, 0, 7]);
+        assert_eq!(Tuple::deserialize(&b, &schema).unwrap(), tuple);
+    }
 }
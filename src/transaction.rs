@@ -0,0 +1,705 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rand::{thread_rng, Rng};
+
+use crate::buffer::BufferPoolManager;
+use crate::lock::{LockManager, LockMode, LockTarget};
+use crate::storage::page::TupleHeader;
+use crate::storage::tuple::TupleId;
+use crate::wal::{abort, rollback_to, Lsn, TxnId};
+
+pub const DEFAULT_CHECKPOINT_LOG_SIZE: u64 = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum IsolationLevel {
+    // Reads a snapshot taken by the first statement, without locks.
+    RepeatableRead,
+    // Locks the tables it reads until it ends, and reads the latest data.
+    Serializable,
+}
+
+// The transactions whose changes a transaction sees. A transaction sees its own changes and
+// those of the transactions which had committed when it took the snapshot.
+//
+// An aborted transaction has its changes undone, so a version whose transaction is neither
+// running nor begun after the snapshot is a committed one.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Snapshot {
+    pub txn_id: TxnId,
+    // The transactions from this id on had not begun.
+    pub xmax: TxnId,
+    // running transactions
+    pub active_txn_ids: BTreeSet<TxnId>,
+}
+
+impl Snapshot {
+    // Sees every change, committed or not, as if the tuples were not versioned. The system
+    // tables are read with it.
+    pub fn latest() -> Self {
+        Snapshot {
+            txn_id: 0,
+            xmax: TxnId::MAX,
+            active_txn_ids: BTreeSet::new(),
+        }
+    }
+    fn sees(&self, txn_id: TxnId) -> bool {
+        txn_id == self.txn_id || (txn_id < self.xmax && !self.active_txn_ids.contains(&txn_id))
+    }
+    pub fn is_visible(&self, header: &TupleHeader) -> bool {
+        self.sees(header.xmin) && (header.xmax == 0 || !self.sees(header.xmax))
+    }
+}
+
+// A running transaction, as used by the statements it runs.
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub txn_id: TxnId,
+    pub isolation_level: IsolationLevel,
+    // what the transaction reads
+    pub snapshot: Snapshot,
+    lock_manager: Arc<LockManager>,
+}
+
+impl Transaction {
+    // Locks a table before reading it. Serializable transactions lock whole tables rather
+    // than the rows they read, which also keeps the rows inserted by others out.
+    pub fn lock_table_for_read(&self, first_block_number: usize) -> Result<()> {
+        match self.isolation_level {
+            IsolationLevel::RepeatableRead => Ok(()),
+            IsolationLevel::Serializable => self.lock_table(first_block_number, LockMode::Shared),
+        }
+    }
+    // Locks a table before changing some of its rows, and before reading it too if
+    // `reads` is set.
+    pub fn lock_table_for_write(&self, first_block_number: usize, reads: bool) -> Result<()> {
+        let mode = match self.isolation_level {
+            IsolationLevel::Serializable if reads => LockMode::SharedIntentionExclusive,
+            _ => LockMode::IntentionExclusive,
+        };
+        self.lock_table(first_block_number, mode)
+    }
+    pub fn lock_table(&self, first_block_number: usize, mode: LockMode) -> Result<()> {
+        self.lock_manager
+            .lock(self.txn_id, LockTarget::Table(first_block_number), mode)
+    }
+    // Locks a row before changing it, which is done whatever the isolation level.
+    pub fn lock_row(&self, tuple_id: TupleId) -> Result<()> {
+        self.lock_manager
+            .lock(self.txn_id, LockTarget::Row(tuple_id), LockMode::Exclusive)
+    }
+}
+
+#[derive(Debug)]
+struct TransactionState {
+    isolation_level: IsolationLevel,
+    // taken by the first statement of a repeatable read transaction
+    snapshot: Option<Snapshot>,
+}
+
+// A transaction opened by BEGIN, whose statements come in several requests.
+#[derive(Debug)]
+struct Session {
+    txn_id: TxnId,
+    // statements of the session which are running
+    running: usize,
+    // when the last statement of the session ended
+    last_active: Instant,
+}
+
+// Starts and ends transactions, and keeps the ones opened by BEGIN for the sessions which
+// run them, so that a transaction can span several requests.
+#[derive(Debug)]
+pub struct TransactionManager {
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    lock_manager: Arc<LockManager>,
+    sessions: Mutex<HashMap<String, Session>>,
+    transactions: Mutex<HashMap<TxnId, TransactionState>>,
+    // size of the log file from which the end of the last running transaction triggers a
+    // checkpoint, which empties the log
+    checkpoint_log_size: u64,
+}
+
+impl TransactionManager {
+    pub fn new(buffer_pool_manager: Arc<BufferPoolManager>) -> Self {
+        Self::with_checkpoint_log_size(buffer_pool_manager, DEFAULT_CHECKPOINT_LOG_SIZE)
+    }
+    pub fn with_checkpoint_log_size(
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        checkpoint_log_size: u64,
+    ) -> Self {
+        TransactionManager {
+            buffer_pool_manager,
+            lock_manager: Arc::new(LockManager::new()),
+            sessions: Mutex::new(HashMap::new()),
+            transactions: Mutex::new(HashMap::new()),
+            checkpoint_log_size,
+        }
+    }
+    pub fn begin(&self) -> TxnId {
+        self.begin_with(IsolationLevel::RepeatableRead)
+    }
+    pub fn begin_with(&self, isolation_level: IsolationLevel) -> TxnId {
+        let txn_id = self.buffer_pool_manager.log_manager().begin();
+        self.transactions.lock().unwrap().insert(
+            txn_id,
+            TransactionState {
+                isolation_level,
+                snapshot: None,
+            },
+        );
+        txn_id
+    }
+    // The locks are released once the changes are durable or undone.
+    pub fn commit(&self, txn_id: TxnId) -> Result<()> {
+        self.buffer_pool_manager.log_manager().commit(txn_id)?;
+        self.end(txn_id);
+        self.buffer_pool_manager.free_deleted_pages(txn_id)?;
+        self.checkpoint_if_large()
+    }
+    pub fn abort(&self, txn_id: TxnId) -> Result<()> {
+        abort(&self.buffer_pool_manager, txn_id)?;
+        self.end(txn_id);
+        self.buffer_pool_manager.forget_deleted_pages(txn_id);
+        self.checkpoint_if_large()
+    }
+    // The log can only be emptied while no transaction runs, so that neither the redo nor the
+    // undo of a crash needs its records.
+    fn checkpoint_if_large(&self) -> Result<()> {
+        let log_manager = self.buffer_pool_manager.log_manager();
+        if log_manager.active_txn_count() > 0 || log_manager.size() < self.checkpoint_log_size {
+            return Ok(());
+        }
+        self.buffer_pool_manager.checkpoint()
+    }
+    fn end(&self, txn_id: TxnId) {
+        self.transactions.lock().unwrap().remove(&txn_id);
+        self.lock_manager.unlock_all(txn_id);
+    }
+    // Returns the running transaction. A repeatable read transaction reads the same snapshot
+    // in all of its statements, and a serializable one reads the latest data, which its locks
+    // keep from changing.
+    pub fn transaction(&self, txn_id: TxnId) -> Result<Transaction> {
+        let mut transactions = self.transactions.lock().unwrap();
+        let state = match transactions.get_mut(&txn_id) {
+            Some(state) => state,
+            None => return Err(anyhow!("transaction {} is not active", txn_id)),
+        };
+        let snapshot = match state.isolation_level {
+            IsolationLevel::RepeatableRead => state
+                .snapshot
+                .get_or_insert_with(|| {
+                    let (xmax, active_txn_ids) =
+                        self.buffer_pool_manager.log_manager().snapshot(txn_id);
+                    Snapshot {
+                        txn_id,
+                        xmax,
+                        active_txn_ids,
+                    }
+                })
+                .clone(),
+            IsolationLevel::Serializable => Snapshot::latest(),
+        };
+        Ok(Transaction {
+            txn_id,
+            isolation_level: state.isolation_level,
+            snapshot,
+            lock_manager: self.lock_manager.clone(),
+        })
+    }
+    // A victim of a deadlock must abort, since it keeps its locks until then.
+    pub fn is_deadlock_victim(&self, txn_id: TxnId) -> bool {
+        self.lock_manager.is_victim(txn_id)
+    }
+    // Returns the point to which `rollback_to` brings the transaction back.
+    pub fn savepoint(&self, txn_id: TxnId) -> Result<Lsn> {
+        self.buffer_pool_manager.log_manager().last_lsn(txn_id)
+    }
+    pub fn rollback_to(&self, txn_id: TxnId, savepoint: Lsn) -> Result<()> {
+        rollback_to(&self.buffer_pool_manager, txn_id, savepoint)
+    }
+    // Opens a session for a new transaction. Its id is a random token, which only the client
+    // it is given to can send the statements of the transaction with.
+    pub fn begin_session(&self, isolation_level: IsolationLevel) -> String {
+        let session_id = format!("{:032x}", thread_rng().gen::<u128>());
+        let txn_id = self.begin_with(isolation_level);
+        self.sessions.lock().unwrap().insert(
+            session_id.clone(),
+            Session {
+                txn_id,
+                running: 0,
+                last_active: Instant::now(),
+            },
+        );
+        session_id
+    }
+    pub fn session_txn(&self, session_id: &str) -> Option<TxnId> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|session| session.txn_id)
+    }
+    // Returns the transaction of the session, which is not idle until `leave_session`.
+    pub fn enter_session(&self, session_id: &str) -> Result<TxnId> {
+        match self.sessions.lock().unwrap().get_mut(session_id) {
+            Some(session) => {
+                session.running += 1;
+                Ok(session.txn_id)
+            }
+            None => Err(anyhow!("session {} does not exist", session_id)),
+        }
+    }
+    pub fn leave_session(&self, session_id: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(session_id) {
+            session.running -= 1;
+            session.last_active = Instant::now();
+        }
+    }
+    // Detaches the transaction from the session. The caller commits or aborts it.
+    pub fn end_session(&self, session_id: &str) -> Result<TxnId> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(session_id) {
+            Some(session) if session.running > 0 => {
+                Err(anyhow!("session {} is running a statement", session_id))
+            }
+            Some(session) => {
+                let txn_id = session.txn_id;
+                sessions.remove(session_id);
+                Ok(txn_id)
+            }
+            None => Err(anyhow!("session {} does not exist", session_id)),
+        }
+    }
+    // Aborts the transactions of the sessions which have run no statement for `timeout`, such
+    // as those of the clients which went away, since they keep their locks and the versions
+    // their snapshots see. Returns the ids of the sessions.
+    pub fn abort_idle_sessions(&self, timeout: Duration) -> Result<Vec<String>> {
+        let mut idle_sessions = Vec::new();
+        self.sessions.lock().unwrap().retain(|session_id, session| {
+            if session.running > 0 || session.last_active.elapsed() < timeout {
+                return true;
+            }
+            idle_sessions.push((session_id.clone(), session.txn_id));
+            false
+        });
+        let mut session_ids = Vec::new();
+        for (session_id, txn_id) in idle_sessions {
+            self.abort(txn_id)?;
+            session_ids.push(session_id);
+        }
+        Ok(session_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{open_catalog, Column, ColumnType, Schema};
+    use crate::storage::table::Table;
+    use crate::storage::tuple::{Tuple, TupleId};
+    use crate::transaction::{IsolationLevel, Snapshot, TransactionManager};
+    use crate::value::Value;
+    use anyhow::Result;
+    use std::ops::Range;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    #[test]
+    fn rollback_session() -> Result<()> {
+        let catalog = open_catalog("tmp/rollback_session/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let disk_manager = buffer_pool_manager.disk_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let txn_id = transaction_manager.begin();
+        catalog.create_table(txn_id, "users", &schema)?;
+        transaction_manager.commit(txn_id)?;
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        let insert = |txn_id, id| {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Int(id)],
+                },
+            )
+        };
+        let ids = || {
+            table
+                .scan_tuples(&Snapshot::latest())
+                .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        let session_id = transaction_manager.begin_session(IsolationLevel::RepeatableRead);
+        let txn_id = transaction_manager.enter_session(&session_id)?;
+        assert_eq!(transaction_manager.session_txn(&session_id), Some(txn_id));
+        assert_eq!(transaction_manager.session_txn("b"), None);
+        assert!(transaction_manager.enter_session("b").is_err());
+        insert(txn_id, 1)?;
+        // A failed statement only loses its own changes.
+        let savepoint = transaction_manager.savepoint(txn_id)?;
+        insert(txn_id, 2)?;
+        transaction_manager.rollback_to(txn_id, savepoint)?;
+        insert(txn_id, 3)?;
+        assert_eq!(ids()?, vec![Value::Int(1), Value::Int(3)]);
+        // The pages of a dropped table survive a rollback.
+        catalog.drop_table(txn_id, "users")?;
+        assert_eq!(catalog.get_oid("users")?, None);
+        transaction_manager.leave_session(&session_id);
+        let txn_id = transaction_manager.end_session(&session_id)?;
+        transaction_manager.abort(txn_id)?;
+        assert!(transaction_manager.end_session(&session_id).is_err());
+        assert!(catalog.get_oid("users")?.is_some());
+        assert_eq!(ids()?, Vec::<Value>::new());
+        assert_eq!(disk_manager.free_page_count()?, 0);
+
+        let session_id = transaction_manager.begin_session(IsolationLevel::RepeatableRead);
+        let txn_id = transaction_manager.enter_session(&session_id)?;
+        insert(txn_id, 4)?;
+        transaction_manager.leave_session(&session_id);
+        let txn_id = transaction_manager.end_session(&session_id)?;
+        transaction_manager.commit(txn_id)?;
+        assert_eq!(ids()?, vec![Value::Int(4)]);
+        let txn_id = transaction_manager.begin();
+        catalog.drop_table(txn_id, "users")?;
+        assert_eq!(disk_manager.free_page_count()?, 0);
+        transaction_manager.commit(txn_id)?;
+        assert_eq!(disk_manager.free_page_count()?, 1);
+        Ok(())
+    }
+    #[test]
+    fn abort_idle_sessions() -> Result<()> {
+        let catalog = open_catalog("tmp/abort_idle_sessions/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let log_manager = buffer_pool_manager.log_manager();
+
+        let running = transaction_manager.begin_session(IsolationLevel::RepeatableRead);
+        let idle = transaction_manager.begin_session(IsolationLevel::RepeatableRead);
+        assert_ne!(running, idle);
+        let txn_id = transaction_manager.enter_session(&running)?;
+        assert_eq!(log_manager.active_txn_count(), 2);
+        assert!(transaction_manager
+            .abort_idle_sessions(Duration::from_secs(60))?
+            .is_empty());
+        // A session running a statement is not idle, however long it runs.
+        assert_eq!(
+            transaction_manager.abort_idle_sessions(Duration::ZERO)?,
+            vec![idle.clone()]
+        );
+        assert_eq!(log_manager.active_txn_count(), 1);
+        assert!(transaction_manager.enter_session(&idle).is_err());
+        transaction_manager.leave_session(&running);
+        assert_eq!(
+            transaction_manager.abort_idle_sessions(Duration::ZERO)?,
+            vec![running]
+        );
+        assert_eq!(log_manager.active_txn_count(), 0);
+        assert!(transaction_manager.transaction(txn_id).is_err());
+        Ok(())
+    }
+    #[test]
+    fn snapshot_isolation() -> Result<()> {
+        let catalog = open_catalog("tmp/snapshot_isolation/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let table = Table::create(&buffer_pool_manager, &schema)?;
+        let tuple = |id| Tuple {
+            values: vec![Value::Int(id)],
+        };
+        let ids = |snapshot: &Snapshot| {
+            table
+                .scan_tuples(snapshot)
+                .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+                .collect::<Result<Vec<_>>>()
+        };
+        let txn_id = transaction_manager.begin();
+        let tuple_id = table.insert_tuple(txn_id, tuple(1))?;
+        transaction_manager.commit(txn_id)?;
+
+        let reader = transaction_manager.begin();
+        let reader_snapshot = transaction_manager.transaction(reader)?.snapshot;
+        let writer = transaction_manager.begin();
+        let writer_snapshot = transaction_manager.transaction(writer)?.snapshot;
+        let new_tuple_id = table.update_tuple(writer, &tuple_id, tuple(2))?;
+        table.insert_tuple(writer, tuple(3))?;
+        // Uncommitted changes are seen only by their own transaction.
+        assert_eq!(ids(&writer_snapshot)?, vec![Value::Int(2), Value::Int(3)]);
+        assert_eq!(ids(&reader_snapshot)?, vec![Value::Int(1)]);
+        assert_eq!(table.get_tuple(&reader_snapshot, &new_tuple_id)?, None);
+        // The first updater wins.
+        assert!(table.delete_tuple(reader, &tuple_id).is_err());
+        transaction_manager.commit(writer)?;
+        // Changes committed after the snapshot stay invisible.
+        assert_eq!(
+            transaction_manager.transaction(reader)?.snapshot,
+            reader_snapshot
+        );
+        assert_eq!(ids(&reader_snapshot)?, vec![Value::Int(1)]);
+        assert!(table.delete_tuple(reader, &tuple_id).is_err());
+        transaction_manager.abort(reader)?;
+
+        let txn_id = transaction_manager.begin();
+        let snapshot = transaction_manager.transaction(txn_id)?.snapshot;
+        assert_eq!(ids(&snapshot)?, vec![Value::Int(2), Value::Int(3)]);
+        assert!(table.is_live(txn_id, &new_tuple_id)?);
+        assert!(!table.is_live(txn_id, &tuple_id)?);
+        table.delete_tuple(txn_id, &new_tuple_id)?;
+        assert!(!table.is_live(txn_id, &new_tuple_id)?);
+        assert_eq!(ids(&snapshot)?, vec![Value::Int(3)]);
+        assert_eq!(ids(&Snapshot::latest())?, vec![Value::Int(3)]);
+        // An aborted delete leaves the tuple alive.
+        transaction_manager.abort(txn_id)?;
+        assert_eq!(
+            ids(&Snapshot::latest())?,
+            vec![Value::Int(2), Value::Int(3)]
+        );
+        Ok(())
+    }
+    #[test]
+    fn prune_dead_versions() -> Result<()> {
+        let catalog = open_catalog("tmp/prune_dead_versions/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let schema = Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "padding".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+            ],
+        };
+        let table = Table::create(&buffer_pool_manager, &schema)?;
+        let tuple = |id| Tuple {
+            values: vec![Value::Int(id), Value::Varchar("a".repeat(1000))],
+        };
+        let ids = |snapshot: &Snapshot| {
+            table
+                .scan_tuples(snapshot)
+                .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+                .collect::<Result<Vec<_>>>()
+        };
+        // The first page holds three tuples.
+        let txn_id = transaction_manager.begin();
+        let tuple_ids = (1..=4)
+            .map(|id| table.insert_tuple(txn_id, tuple(id)))
+            .collect::<Result<Vec<_>>>()?;
+        transaction_manager.commit(txn_id)?;
+        assert_eq!(tuple_ids[2].block_number, table.first_block_number);
+        assert_ne!(tuple_ids[3].block_number, table.first_block_number);
+
+        let reader = transaction_manager.begin();
+        let reader_snapshot = transaction_manager.transaction(reader)?.snapshot;
+        let txn_id = transaction_manager.begin();
+        for tuple_id in &tuple_ids[..3] {
+            table.delete_tuple(txn_id, tuple_id)?;
+        }
+        transaction_manager.commit(txn_id)?;
+        // The reader still sees the deleted versions.
+        let txn_id = transaction_manager.begin();
+        let tuple_id = table.insert_tuple(txn_id, tuple(5))?;
+        transaction_manager.commit(txn_id)?;
+        assert_ne!(tuple_id.block_number, table.first_block_number);
+        assert_eq!(
+            ids(&reader_snapshot)?,
+            (1..=4).map(Value::Int).collect::<Vec<_>>()
+        );
+        transaction_manager.commit(reader)?;
+
+        let txn_id = transaction_manager.begin();
+        let tuple_id = table.insert_tuple(txn_id, tuple(6))?;
+        transaction_manager.commit(txn_id)?;
+        assert_eq!(
+            tuple_id,
+            TupleId {
+                block_number: table.first_block_number,
+                slot_number: 3,
+            }
+        );
+        assert_eq!(
+            ids(&Snapshot::latest())?,
+            vec![Value::Int(6), Value::Int(4), Value::Int(5)]
+        );
+        assert_eq!(table.scan_all_versions().count(), 3);
+        Ok(())
+    }
+    #[test]
+    fn update_on_same_page() -> Result<()> {
+        let catalog = open_catalog("tmp/update_on_same_page/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = TransactionManager::new(buffer_pool_manager.clone());
+        let schema = Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    column_type: ColumnType::Int,
+                },
+                Column {
+                    name: "padding".to_string(),
+                    column_type: ColumnType::Varchar,
+                },
+            ],
+        };
+        let table = Table::create(&buffer_pool_manager, &schema)?;
+        let tuple = |id| Tuple {
+            values: vec![Value::Int(id), Value::Varchar("a".repeat(1000))],
+        };
+        // The first page holds three tuples.
+        let txn_id = transaction_manager.begin();
+        let tuple_ids = (1..=4)
+            .map(|id| table.insert_tuple(txn_id, tuple(id)))
+            .collect::<Result<Vec<_>>>()?;
+        transaction_manager.commit(txn_id)?;
+        let second_block_number = tuple_ids[3].block_number;
+        assert_ne!(second_block_number, table.first_block_number);
+        let txn_id = transaction_manager.begin();
+        table.delete_tuple(txn_id, &tuple_ids[0])?;
+        transaction_manager.commit(txn_id)?;
+
+        // The first page has room once the deleted version is pruned, but the new version
+        // stays on the page of the old one.
+        let txn_id = transaction_manager.begin();
+        let tuple_id = table.update_tuple(txn_id, &tuple_ids[3], tuple(40))?;
+        assert_eq!(tuple_id.block_number, second_block_number);
+        let tuple_id = table.update_tuple(txn_id, &tuple_ids[1], tuple(20))?;
+        assert_eq!(tuple_id.block_number, table.first_block_number);
+        // The first page is full again, so the new version goes to the next page with room.
+        let tuple_id = table.update_tuple(txn_id, &tuple_ids[2], tuple(30))?;
+        assert_eq!(tuple_id.block_number, second_block_number);
+        transaction_manager.commit(txn_id)?;
+        assert_eq!(
+            table
+                .scan_tuples(&Snapshot::latest())
+                .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+                .collect::<Result<Vec<_>>>()?,
+            vec![Value::Int(20), Value::Int(40), Value::Int(30)]
+        );
+        Ok(())
+    }
+    #[test]
+    fn checkpoint_large_log() -> Result<()> {
+        let catalog = open_catalog("tmp/checkpoint_large_log/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let disk_manager = buffer_pool_manager.disk_manager().clone();
+        let transaction_manager =
+            TransactionManager::with_checkpoint_log_size(buffer_pool_manager.clone(), 100_000);
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let table = Table::create(&buffer_pool_manager, &schema)?;
+        let insert = |txn_id, ids: Range<i32>| -> Result<()> {
+            for id in ids {
+                table.insert_tuple(
+                    txn_id,
+                    Tuple {
+                        values: vec![Value::Int(id)],
+                    },
+                )?;
+            }
+            Ok(())
+        };
+        let txn_id = transaction_manager.begin();
+        insert(txn_id, 0..10)?;
+        transaction_manager.commit(txn_id)?;
+        let small_log_size = disk_manager.log_size()?;
+        assert!(small_log_size > 16 && small_log_size < 100_000);
+        // The log is kept while another transaction runs.
+        let other_txn_id = transaction_manager.begin();
+        insert(other_txn_id, 10..11)?;
+        let txn_id = transaction_manager.begin();
+        insert(txn_id, 11..200)?;
+        transaction_manager.commit(txn_id)?;
+        assert!(disk_manager.log_size()? >= 100_000);
+        transaction_manager.abort(other_txn_id)?;
+        assert_eq!(disk_manager.log_size()?, 16);
+        assert_eq!(
+            table
+                .scan_tuples(&Snapshot::latest())
+                .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+                .collect::<Result<Vec<_>>>()?,
+            (0..10).chain(11..200).map(Value::Int).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+    #[test]
+    fn serializable_locks() -> Result<()> {
+        let catalog = open_catalog("tmp/serializable_locks/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let transaction_manager = Arc::new(TransactionManager::new(buffer_pool_manager));
+        let first_block_number = 1;
+
+        // A serializable reader keeps writers out of the table until it ends.
+        let reader = transaction_manager.begin_with(IsolationLevel::Serializable);
+        let reader_txn = transaction_manager.transaction(reader)?;
+        assert_eq!(reader_txn.snapshot, Snapshot::latest());
+        reader_txn.lock_table_for_read(first_block_number)?;
+        let writer = transaction_manager.begin();
+        let (sender, receiver) = mpsc::channel();
+        let handle = {
+            let writer_txn = transaction_manager.transaction(writer)?;
+            thread::spawn(move || {
+                let result = writer_txn.lock_table_for_write(first_block_number, false);
+                sender.send(()).unwrap();
+                result
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        transaction_manager.commit(reader)?;
+        receiver.recv()?;
+        handle.join().unwrap()?;
+        // Repeatable read transactions read without locks.
+        let other = transaction_manager.begin();
+        transaction_manager
+            .transaction(other)?
+            .lock_table_for_read(first_block_number)?;
+
+        // The younger of two transactions waiting for each other is the victim.
+        let row = |slot_number| TupleId {
+            block_number: 2,
+            slot_number,
+        };
+        let writer_txn = transaction_manager.transaction(writer)?;
+        let other_txn = transaction_manager.transaction(other)?;
+        writer_txn.lock_row(row(0))?;
+        other_txn.lock_row(row(1))?;
+        let handle = {
+            let transaction_manager = transaction_manager.clone();
+            thread::spawn(move || -> Result<bool> {
+                let result = other_txn.lock_row(row(0));
+                let is_victim = transaction_manager.is_deadlock_victim(other);
+                // The victim aborts to release its locks.
+                transaction_manager.abort(other)?;
+                Ok(result.is_err() && is_victim)
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        writer_txn.lock_row(row(1))?;
+        assert!(handle.join().unwrap()?);
+        assert!(!transaction_manager.is_deadlock_victim(other));
+        assert!(!transaction_manager.is_deadlock_victim(writer));
+        transaction_manager.commit(writer)?;
+        Ok(())
+    }
+}
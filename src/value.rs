@@ -1,5 +1,4 @@
 use std::fmt;
-use std::i32;
 use std::io::Read;
 use std::mem;
 
@@ -9,6 +8,8 @@ use crate::catalog::ColumnType;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum Value {
+    // Stored only in the null bitmap of a tuple, so it serializes to no bytes.
+    Null,
     Int(i32),
     Varchar(String),
 }
@@ -16,6 +17,7 @@ pub enum Value {
 impl Value {
     pub fn serialize(&self) -> Vec<u8> {
         match self {
+            Value::Null => vec![],
             Value::Int(v) => v.to_be_bytes().to_vec(),
             Value::Varchar(v) => {
                 let str_byte = v.as_bytes();
@@ -25,8 +27,27 @@ impl Value {
             }
         }
     }
+    // Converts the value for a column of the type as SQL does on assignment, where numbers
+    // become strings and strings of digits become numbers. NULL fits any type. Returns None if
+    // the value cannot be converted.
+    pub fn cast(&self, column_type: &ColumnType) -> Option<Value> {
+        match (self, column_type) {
+            (Value::Null, _)
+            | (Value::Int(_), ColumnType::Int)
+            | (Value::Varchar(_), ColumnType::Varchar) => Some(self.clone()),
+            (Value::Int(v), ColumnType::Varchar) => Some(Value::Varchar(v.to_string())),
+            (Value::Varchar(v), ColumnType::Int) => v.trim().parse().ok().map(Value::Int),
+        }
+    }
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Int(v) => serde_json::Value::from(*v),
+            Value::Varchar(v) => serde_json::Value::from(v.clone()),
+        }
+    }
     pub fn deserialize(data: &[u8], column_type: &ColumnType) -> Result<(Self, usize)> {
-        let mut reader = &data[..];
+        let mut reader = data;
         match column_type {
             ColumnType::Int => {
                 let mut buf = [0u8; mem::size_of::<i32>()];
@@ -52,6 +73,7 @@ impl Value {
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Value::Null => write!(f, "NULL"),
             Value::Int(v) => write!(f, "{}", v),
             Value::Varchar(v) => write!(f, "{}", v),
         }
@@ -62,7 +84,6 @@ impl fmt::Display for Value {
 mod tests {
     use crate::catalog::ColumnType;
     use crate::value::Value;
-    use std::i32;
     #[test]
     fn serialize_int_zero() {
         assert_eq!(Value::Int(0).serialize(), vec![0, 0, 0, 0]);
@@ -86,6 +107,35 @@ mod tests {
         )
     }
     #[test]
+    fn cast() {
+        assert_eq!(Value::Null.cast(&ColumnType::Int), Some(Value::Null));
+        assert_eq!(
+            Value::Int(-1).cast(&ColumnType::Varchar),
+            Some(Value::Varchar("-1".to_string()))
+        );
+        assert_eq!(
+            Value::Varchar(" 42 ".to_string()).cast(&ColumnType::Int),
+            Some(Value::Int(42))
+        );
+        assert_eq!(
+            Value::Varchar("4.2".to_string()).cast(&ColumnType::Int),
+            None
+        );
+        assert_eq!(
+            Value::Varchar("2147483648".to_string()).cast(&ColumnType::Int),
+            None
+        );
+    }
+    #[test]
+    fn to_json() {
+        assert_eq!(Value::Null.to_json(), serde_json::Value::Null);
+        assert_eq!(Value::Int(1).to_json(), serde_json::json!(1));
+        assert_eq!(
+            Value::Varchar("1, 2".to_string()).to_json(),
+            serde_json::json!("1, 2")
+        );
+    }
+    #[test]
     fn deseriallize_int_zero() {
         let value = Value::deserialize(&[0, 0, 0, 0], &ColumnType::Int).unwrap();
         assert_eq!(value, (Value::Int(0), 4))
@@ -0,0 +1,646 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::buffer::BufferPoolManager;
+use crate::disk::{DiskManager, PAGE_SIZE};
+use crate::storage::page::{kill_tuple, set_tuple_xmax};
+
+pub mod recovery;
+
+// Log sequence number, the position of a log record counted from the creation of the log.
+// 0 means no record.
+pub type Lsn = u64;
+// 0 is used by the records that belong to no transaction.
+pub type TxnId = u64;
+
+// Every page starts with the LSN of the last log record applied to it.
+pub const PAGE_LSN_SIZE: usize = mem::size_of::<Lsn>();
+// The log file starts with the LSN of its first byte and the next transaction id, which
+// survive the truncation of the log.
+const LOG_HEADER_SIZE: usize = 2 * mem::size_of::<u64>();
+
+const RECORD_KIND_BEGIN: u8 = 0;
+const RECORD_KIND_COMMIT: u8 = 1;
+const RECORD_KIND_ABORT: u8 = 2;
+const RECORD_KIND_UPDATE: u8 = 3;
+const RECORD_KIND_COMPENSATION: u8 = 4;
+const RECORD_KIND_NEW_PAGE: u8 = 5;
+
+const UNDO_KIND_NONE: u8 = 0;
+const UNDO_KIND_INSERT: u8 = 1;
+const UNDO_KIND_DELETE: u8 = 2;
+
+pub fn page_lsn(data: &[u8]) -> Lsn {
+    let mut buf = [0u8; PAGE_LSN_SIZE];
+    buf.copy_from_slice(&data[..PAGE_LSN_SIZE]);
+    Lsn::from_be_bytes(buf)
+}
+
+pub fn set_page_lsn(data: &mut [u8], lsn: Lsn) {
+    data[..PAGE_LSN_SIZE].copy_from_slice(&lsn.to_be_bytes());
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum LogRecordBody {
+    Begin,
+    Commit,
+    // Written once every change of the transaction has been undone.
+    Abort,
+    // The bytes of a page at `offset` changed to `data`, which is rolled back by `undo`.
+    Update {
+        block_number: usize,
+        offset: usize,
+        data: Vec<u8>,
+        undo: Undo,
+    },
+    // Redo-only record of an undone update. The undo continues from `undo_next_lsn`.
+    Compensation {
+        block_number: usize,
+        offset: usize,
+        data: Vec<u8>,
+        undo_next_lsn: Lsn,
+    },
+    // Redo-only record of the initial image of an allocated page.
+    NewPage {
+        block_number: usize,
+        data: Vec<u8>,
+    },
+}
+
+// How the change of an update record is rolled back. The undo is logical since other
+// transactions may have changed the same page in the meantime, and compaction may have moved
+// the tuple.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Undo {
+    // The change outlives the transaction, like the links between pages and index entries.
+    None,
+    // The tuple inserted into the slot is killed.
+    Insert { slot_number: usize },
+    // The xmax of the tuple in the slot is cleared.
+    Delete { slot_number: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    pub txn_id: TxnId,
+    // previous record of the same transaction
+    pub prev_lsn: Lsn,
+    pub body: LogRecordBody,
+}
+
+// A record is its size (4), kind (1), transaction id (8) and previous LSN (8) followed by
+// the body. The LSN is not stored since it is the position of the record.
+impl LogRecord {
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; mem::size_of::<u32>()];
+        match &self.body {
+            LogRecordBody::Begin => buf.write_all(&[RECORD_KIND_BEGIN])?,
+            LogRecordBody::Commit => buf.write_all(&[RECORD_KIND_COMMIT])?,
+            LogRecordBody::Abort => buf.write_all(&[RECORD_KIND_ABORT])?,
+            LogRecordBody::Update { .. } => buf.write_all(&[RECORD_KIND_UPDATE])?,
+            LogRecordBody::Compensation { .. } => buf.write_all(&[RECORD_KIND_COMPENSATION])?,
+            LogRecordBody::NewPage { .. } => buf.write_all(&[RECORD_KIND_NEW_PAGE])?,
+        }
+        buf.write_all(&self.txn_id.to_be_bytes())?;
+        buf.write_all(&self.prev_lsn.to_be_bytes())?;
+        match &self.body {
+            LogRecordBody::Begin | LogRecordBody::Commit | LogRecordBody::Abort => {}
+            LogRecordBody::Update {
+                block_number,
+                offset,
+                data,
+                undo,
+            } => {
+                buf.write_all(&(*block_number as u32).to_be_bytes())?;
+                buf.write_all(&(*offset as u16).to_be_bytes())?;
+                buf.write_all(&(data.len() as u16).to_be_bytes())?;
+                buf.write_all(data)?;
+                let (kind, slot_number) = match undo {
+                    Undo::None => (UNDO_KIND_NONE, 0),
+                    Undo::Insert { slot_number } => (UNDO_KIND_INSERT, *slot_number),
+                    Undo::Delete { slot_number } => (UNDO_KIND_DELETE, *slot_number),
+                };
+                buf.write_all(&[kind])?;
+                buf.write_all(&(slot_number as u16).to_be_bytes())?;
+            }
+            LogRecordBody::Compensation {
+                block_number,
+                offset,
+                data,
+                undo_next_lsn,
+            } => {
+                buf.write_all(&(*block_number as u32).to_be_bytes())?;
+                buf.write_all(&(*offset as u16).to_be_bytes())?;
+                buf.write_all(&(data.len() as u16).to_be_bytes())?;
+                buf.write_all(data)?;
+                buf.write_all(&undo_next_lsn.to_be_bytes())?;
+            }
+            LogRecordBody::NewPage { block_number, data } => {
+                buf.write_all(&(*block_number as u32).to_be_bytes())?;
+                buf.write_all(data)?;
+            }
+        }
+        let size = buf.len() as u32;
+        buf[..mem::size_of::<u32>()].copy_from_slice(&size.to_be_bytes());
+        Ok(buf)
+    }
+    fn deserialize(lsn: Lsn, data: &[u8]) -> Result<LogRecord> {
+        let mut reader = &data[mem::size_of::<u32>()..];
+        let mut kind = [0u8; 1];
+        reader.read_exact(&mut kind)?;
+        let txn_id = read_u64(&mut reader)?;
+        let prev_lsn = read_u64(&mut reader)?;
+        let body = match kind[0] {
+            RECORD_KIND_BEGIN => LogRecordBody::Begin,
+            RECORD_KIND_COMMIT => LogRecordBody::Commit,
+            RECORD_KIND_ABORT => LogRecordBody::Abort,
+            RECORD_KIND_UPDATE => {
+                let block_number = read_u32(&mut reader)? as usize;
+                let offset = read_u16(&mut reader)? as usize;
+                let len = read_u16(&mut reader)? as usize;
+                let mut data = vec![0u8; len];
+                reader.read_exact(&mut data)?;
+                let mut kind = [0u8; 1];
+                reader.read_exact(&mut kind)?;
+                let slot_number = read_u16(&mut reader)? as usize;
+                let undo = match kind[0] {
+                    UNDO_KIND_NONE => Undo::None,
+                    UNDO_KIND_INSERT => Undo::Insert { slot_number },
+                    UNDO_KIND_DELETE => Undo::Delete { slot_number },
+                    kind => return Err(anyhow!("unknown undo kind {}", kind)),
+                };
+                LogRecordBody::Update {
+                    block_number,
+                    offset,
+                    data,
+                    undo,
+                }
+            }
+            RECORD_KIND_COMPENSATION => {
+                let block_number = read_u32(&mut reader)? as usize;
+                let offset = read_u16(&mut reader)? as usize;
+                let len = read_u16(&mut reader)? as usize;
+                let mut data = vec![0u8; len];
+                reader.read_exact(&mut data)?;
+                let undo_next_lsn = read_u64(&mut reader)?;
+                LogRecordBody::Compensation {
+                    block_number,
+                    offset,
+                    data,
+                    undo_next_lsn,
+                }
+            }
+            RECORD_KIND_NEW_PAGE => {
+                let block_number = read_u32(&mut reader)? as usize;
+                let mut data = vec![0u8; PAGE_SIZE];
+                reader.read_exact(&mut data)?;
+                LogRecordBody::NewPage { block_number, data }
+            }
+            kind => return Err(anyhow!("unknown log record kind {}", kind)),
+        };
+        Ok(LogRecord {
+            lsn,
+            txn_id,
+            prev_lsn,
+            body,
+        })
+    }
+}
+
+fn read_u16(reader: &mut &[u8]) -> Result<u16> {
+    let mut buf = [0u8; mem::size_of::<u16>()];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0u8; mem::size_of::<u32>()];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64> {
+    let mut buf = [0u8; mem::size_of::<u64>()];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+// Appends log records to an in-memory buffer, which is written to the log file when a
+// transaction commits or before a page with a later LSN is written back.
+#[derive(Debug)]
+pub struct LogManager {
+    disk_manager: Arc<DiskManager>,
+    state: Mutex<LogState>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    // LSN of the first byte of the log file
+    base_lsn: Lsn,
+    next_lsn: Lsn,
+    // Every record before this LSN is in the log file.
+    flushed_lsn: Lsn,
+    buffer: Vec<u8>,
+    // whether the log file has its header
+    has_header: bool,
+    next_txn_id: TxnId,
+    // running transactions and their last LSN, which is 0 until they log a change
+    active_txns: HashMap<TxnId, Lsn>,
+    // the oldest transaction whose changes may be unseen by the snapshot of a running one
+    snapshot_xmins: HashMap<TxnId, TxnId>,
+}
+
+impl LogState {
+    fn header(&self) -> Vec<u8> {
+        [self.base_lsn.to_be_bytes(), self.next_txn_id.to_be_bytes()].concat()
+    }
+    fn end(&mut self, txn_id: TxnId) {
+        self.active_txns.remove(&txn_id);
+        self.snapshot_xmins.remove(&txn_id);
+    }
+}
+
+impl LogManager {
+    pub fn new(disk_manager: Arc<DiskManager>) -> Self {
+        LogManager {
+            disk_manager,
+            state: Mutex::new(LogState {
+                base_lsn: 0,
+                next_lsn: LOG_HEADER_SIZE as Lsn,
+                flushed_lsn: LOG_HEADER_SIZE as Lsn,
+                buffer: Vec::new(),
+                has_header: false,
+                next_txn_id: 1,
+                active_txns: HashMap::new(),
+                snapshot_xmins: HashMap::new(),
+            }),
+        }
+    }
+    // Reads every record of the log file and continues the log after the last one.
+    // A torn record at the end is cut off.
+    pub fn open(&self) -> Result<Vec<LogRecord>> {
+        let mut state = self.state.lock().unwrap();
+        let size = self.disk_manager.log_size()? as usize;
+        if size < LOG_HEADER_SIZE {
+            return Ok(Vec::new());
+        }
+        let data = self.disk_manager.read_log(0, size)?;
+        let mut reader = &data[..LOG_HEADER_SIZE];
+        let base_lsn = read_u64(&mut reader)?;
+        let mut next_txn_id = read_u64(&mut reader)?.max(1);
+        let mut records = Vec::new();
+        let mut offset = LOG_HEADER_SIZE;
+        while offset + mem::size_of::<u32>() <= size {
+            let mut reader = &data[offset..];
+            let record_size = read_u32(&mut reader)? as usize;
+            if record_size <= mem::size_of::<u32>() || offset + record_size > size {
+                break;
+            }
+            let record = match LogRecord::deserialize(
+                base_lsn + offset as Lsn,
+                &data[offset..offset + record_size],
+            ) {
+                Ok(record) => record,
+                Err(_) => break,
+            };
+            next_txn_id = next_txn_id.max(record.txn_id + 1);
+            records.push(record);
+            offset += record_size;
+        }
+        if offset < size {
+            self.disk_manager.truncate_log(offset as u64)?;
+        }
+        state.base_lsn = base_lsn;
+        state.next_lsn = base_lsn + offset as Lsn;
+        state.flushed_lsn = state.next_lsn;
+        state.buffer.clear();
+        state.has_header = true;
+        state.next_txn_id = next_txn_id;
+        state.active_txns.clear();
+        state.snapshot_xmins.clear();
+        Ok(records)
+    }
+    // Registers the transactions found running by the recovery, with their last LSNs.
+    fn resume(&self, txns: &HashMap<TxnId, Lsn>) {
+        self.state.lock().unwrap().active_txns = txns.clone();
+    }
+    pub fn begin(&self) -> TxnId {
+        let mut state = self.state.lock().unwrap();
+        let txn_id = state.next_txn_id;
+        state.next_txn_id += 1;
+        state.active_txns.insert(txn_id, 0);
+        txn_id
+    }
+    // Makes the changes of the transaction durable.
+    pub fn commit(&self, txn_id: TxnId) -> Result<()> {
+        let lsn = self.last_lsn(txn_id)?;
+        // A transaction which changed nothing leaves no trace in the log.
+        if lsn != 0 {
+            let lsn = self.append(txn_id, LogRecordBody::Commit)?;
+            self.flush(lsn)?;
+        }
+        self.state.lock().unwrap().end(txn_id);
+        Ok(())
+    }
+    // Finishes a transaction whose changes have been undone.
+    fn end_abort(&self, txn_id: TxnId) -> Result<()> {
+        if self.last_lsn(txn_id)? != 0 {
+            self.append(txn_id, LogRecordBody::Abort)?;
+        }
+        self.state.lock().unwrap().end(txn_id);
+        Ok(())
+    }
+    pub fn last_lsn(&self, txn_id: TxnId) -> Result<Lsn> {
+        match self.state.lock().unwrap().active_txns.get(&txn_id) {
+            Some(lsn) => Ok(*lsn),
+            None => Err(anyhow!("transaction {} is not active", txn_id)),
+        }
+    }
+    pub fn is_active(&self, txn_id: TxnId) -> bool {
+        self.state.lock().unwrap().active_txns.contains_key(&txn_id)
+    }
+    // Returns the id of the next transaction and the ids of the running ones, which make the
+    // snapshot of the transaction.
+    pub fn snapshot(&self, txn_id: TxnId) -> (TxnId, BTreeSet<TxnId>) {
+        let mut state = self.state.lock().unwrap();
+        let active_txn_ids: BTreeSet<TxnId> = state.active_txns.keys().copied().collect();
+        let xmin = active_txn_ids
+            .iter()
+            .next()
+            .copied()
+            .unwrap_or(state.next_txn_id);
+        state.snapshot_xmins.insert(txn_id, xmin);
+        (state.next_txn_id, active_txn_ids)
+    }
+    // Returns the id before which every transaction has ended and is seen by the snapshots
+    // of the running ones.
+    pub fn prune_horizon(&self) -> TxnId {
+        let state = self.state.lock().unwrap();
+        state
+            .active_txns
+            .keys()
+            .chain(state.snapshot_xmins.values())
+            .copied()
+            .fold(state.next_txn_id, TxnId::min)
+    }
+    // Returns the size of the log, including the records not flushed yet.
+    pub fn size(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.next_lsn - state.base_lsn
+    }
+    pub fn active_txn_count(&self) -> usize {
+        self.state.lock().unwrap().active_txns.len()
+    }
+    // Appends a record of the transaction, logging its beginning first if this is its
+    // first record.
+    pub fn append(&self, txn_id: TxnId, body: LogRecordBody) -> Result<Lsn> {
+        let mut state = self.state.lock().unwrap();
+        if txn_id == 0 {
+            return Self::append_record(&mut state, 0, 0, body);
+        }
+        let mut prev_lsn = match state.active_txns.get(&txn_id) {
+            Some(lsn) => *lsn,
+            None => return Err(anyhow!("transaction {} is not active", txn_id)),
+        };
+        if prev_lsn == 0 {
+            prev_lsn = Self::append_record(&mut state, txn_id, 0, LogRecordBody::Begin)?;
+        }
+        let lsn = Self::append_record(&mut state, txn_id, prev_lsn, body)?;
+        state.active_txns.insert(txn_id, lsn);
+        Ok(lsn)
+    }
+    fn append_record(
+        state: &mut LogState,
+        txn_id: TxnId,
+        prev_lsn: Lsn,
+        body: LogRecordBody,
+    ) -> Result<Lsn> {
+        let lsn = state.next_lsn;
+        let data = LogRecord {
+            lsn,
+            txn_id,
+            prev_lsn,
+            body,
+        }
+        .serialize()?;
+        state.next_lsn += data.len() as Lsn;
+        state.buffer.extend(data);
+        Ok(lsn)
+    }
+    // Makes sure that the record at `lsn` and every record before it are in the log file.
+    pub fn flush(&self, lsn: Lsn) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if lsn < state.flushed_lsn || state.buffer.is_empty() {
+            return Ok(());
+        }
+        if !state.has_header {
+            if self.disk_manager.log_size()? == 0 {
+                self.disk_manager.append_log(&state.header())?;
+            }
+            state.has_header = true;
+        }
+        self.disk_manager.append_log(&state.buffer)?;
+        state.buffer.clear();
+        state.flushed_lsn = state.next_lsn;
+        Ok(())
+    }
+    // Flushes every record and returns the LSN of the next one.
+    pub fn flush_all(&self) -> Result<Lsn> {
+        let lsn = self.state.lock().unwrap().next_lsn;
+        self.flush(lsn)?;
+        Ok(lsn)
+    }
+    pub fn read_record(&self, lsn: Lsn) -> Result<LogRecord> {
+        self.flush(lsn)?;
+        let base_lsn = self.state.lock().unwrap().base_lsn;
+        if lsn < base_lsn + LOG_HEADER_SIZE as Lsn {
+            return Err(anyhow!("log record {} is truncated", lsn));
+        }
+        let offset = lsn - base_lsn;
+        let mut size = self.disk_manager.read_log(offset, mem::size_of::<u32>())?;
+        let record_size = read_u32(&mut &size[..])? as usize;
+        size = self.disk_manager.read_log(offset, record_size)?;
+        LogRecord::deserialize(lsn, &size)
+    }
+    // Empties the log file unless a record was appended after `lsn` or a transaction is
+    // running. Every page must have been written back up to `lsn`.
+    pub fn truncate(&self, lsn: Lsn) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if state.next_lsn != lsn || !state.buffer.is_empty() || !state.active_txns.is_empty() {
+            return Ok(false);
+        }
+        state.base_lsn = lsn - LOG_HEADER_SIZE as Lsn;
+        self.disk_manager.reset_log(&state.header())?;
+        state.has_header = true;
+        Ok(true)
+    }
+}
+
+// Computes the smallest byte range in which the page changed, ignoring its LSN, and returns
+// the offset with the new bytes.
+pub fn page_diff(before: &[u8], after: &[u8]) -> Option<(usize, Vec<u8>)> {
+    let start = (PAGE_LSN_SIZE..before.len()).find(|&i| before[i] != after[i])?;
+    let end = (start..before.len())
+        .rev()
+        .find(|&i| before[i] != after[i])
+        .unwrap()
+        + 1;
+    Some((start, after[start..end].to_vec()))
+}
+
+// Writes `data` at `offset` of the page and stamps the page with `lsn`.
+fn apply(
+    buffer_pool_manager: &BufferPoolManager,
+    block_number: usize,
+    offset: usize,
+    data: &[u8],
+    lsn: Lsn,
+) -> Result<()> {
+    let res = buffer_pool_manager.fetch_page(block_number)?;
+    {
+        let mut page_data = res.data.write().unwrap();
+        page_data[offset..offset + data.len()].copy_from_slice(data);
+        set_page_lsn(&mut page_data, lsn);
+    }
+    buffer_pool_manager.unpin_frame(res.frame_id, true);
+    Ok(())
+}
+
+// Undoes one record, logging the resulting change as a compensation record, and returns
+// the LSN of the next record to undo.
+fn undo(buffer_pool_manager: &BufferPoolManager, record: LogRecord) -> Result<Lsn> {
+    let (block_number, undo) = match record.body {
+        LogRecordBody::Update {
+            block_number, undo, ..
+        } if undo != Undo::None => (block_number, undo),
+        LogRecordBody::Compensation { undo_next_lsn, .. } => return Ok(undo_next_lsn),
+        _ => return Ok(record.prev_lsn),
+    };
+    let res = buffer_pool_manager.fetch_page(block_number)?;
+    let mut page_data = res.data.write().unwrap();
+    let mut data = page_data.clone();
+    let undone = match undo {
+        Undo::Insert { slot_number } => kill_tuple(&mut data, slot_number),
+        Undo::Delete { slot_number } => set_tuple_xmax(&mut data, slot_number, 0),
+        Undo::None => Ok(()),
+    };
+    let lsn = undone.and_then(|()| match page_diff(&page_data, &data) {
+        Some((offset, after)) => buffer_pool_manager
+            .log_manager()
+            .append(
+                record.txn_id,
+                LogRecordBody::Compensation {
+                    block_number,
+                    offset,
+                    data: after,
+                    undo_next_lsn: record.prev_lsn,
+                },
+            )
+            .map(Some),
+        None => Ok(None),
+    });
+    if let Ok(Some(lsn)) = lsn {
+        page_data.copy_from_slice(&data);
+        set_page_lsn(&mut page_data, lsn);
+    }
+    drop(page_data);
+    buffer_pool_manager.unpin_frame(res.frame_id, matches!(lsn, Ok(Some(_))));
+    lsn?;
+    Ok(record.prev_lsn)
+}
+
+// Undoes the changes the transaction made after `savepoint`, the latest one first. The
+// transaction keeps running.
+pub fn rollback_to(
+    buffer_pool_manager: &BufferPoolManager,
+    txn_id: TxnId,
+    savepoint: Lsn,
+) -> Result<()> {
+    let log_manager = buffer_pool_manager.log_manager();
+    let mut lsn = log_manager.last_lsn(txn_id)?;
+    while lsn > savepoint {
+        let record = log_manager.read_record(lsn)?;
+        lsn = undo(buffer_pool_manager, record)?;
+    }
+    Ok(())
+}
+
+// Rolls back every change of the transaction and ends it.
+pub fn abort(buffer_pool_manager: &BufferPoolManager, txn_id: TxnId) -> Result<()> {
+    rollback_to(buffer_pool_manager, txn_id, 0)?;
+    buffer_pool_manager.log_manager().end_abort(txn_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{open_catalog, Column, ColumnType, Schema};
+    use crate::storage::table::Table;
+    use crate::storage::tuple::Tuple;
+    use crate::transaction::Snapshot;
+    use crate::value::Value;
+    use crate::wal::{abort, LogRecordBody};
+    use anyhow::Result;
+    #[test]
+    fn abort_and_commit() -> Result<()> {
+        let catalog = open_catalog("tmp/abort_and_commit/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let log_manager = buffer_pool_manager.log_manager().clone();
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let table = Table::create(&buffer_pool_manager, &schema)?;
+        let txn_id = log_manager.begin();
+        let tuple_id = table.insert_tuple(
+            txn_id,
+            Tuple {
+                values: vec![Value::Int(1)],
+            },
+        )?;
+        log_manager.commit(txn_id)?;
+
+        // Enough tuples to span several pages, which are evicted before the abort.
+        let txn_id = log_manager.begin();
+        for i in 0..2000 {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Int(i)],
+                },
+            )?;
+        }
+        table.update_tuple(
+            txn_id,
+            &tuple_id,
+            Tuple {
+                values: vec![Value::Int(2)],
+            },
+        )?;
+        abort(&buffer_pool_manager, txn_id)?;
+        assert_eq!(log_manager.active_txn_count(), 0);
+        let tuples = table
+            .scan_tuples(&Snapshot::latest())
+            .map(|item| item.map(|(_, tuple)| tuple))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tuples,
+            vec![Tuple {
+                values: vec![Value::Int(1)]
+            }]
+        );
+        assert!(abort(&buffer_pool_manager, txn_id).is_err());
+
+        let lsn = log_manager.flush_all()?;
+        let records = log_manager.open()?;
+        assert_eq!(records.last().unwrap().body, LogRecordBody::Abort);
+        assert_eq!(log_manager.flush_all()?, lsn);
+        Ok(())
+    }
+}
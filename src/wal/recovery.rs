@@ -0,0 +1,205 @@
+use std::collections::{BinaryHeap, HashMap};
+
+use anyhow::Result;
+
+use super::{apply, page_lsn, undo, LogRecordBody, Lsn, TxnId};
+use crate::buffer::BufferPoolManager;
+
+// Brings the pages back to the state of the committed transactions after a crash, in the
+// three passes of ARIES. There are no fuzzy checkpoints, so every pass reads the whole log,
+// which is emptied by the checkpoint at the end.
+pub fn recover(buffer_pool_manager: &BufferPoolManager) -> Result<()> {
+    let log_manager = buffer_pool_manager.log_manager();
+    let records = log_manager.open()?;
+
+    // Analysis: the transactions which neither committed nor finished aborting are losers.
+    let mut losers: HashMap<TxnId, Lsn> = HashMap::new();
+    for record in records.iter().filter(|record| record.txn_id != 0) {
+        match record.body {
+            LogRecordBody::Commit | LogRecordBody::Abort => {
+                losers.remove(&record.txn_id);
+            }
+            _ => {
+                losers.insert(record.txn_id, record.lsn);
+            }
+        }
+    }
+
+    // Redo: repeat the history, including the changes of the losers.
+    for record in records.iter() {
+        let (block_number, offset, data) = match &record.body {
+            LogRecordBody::Update {
+                block_number,
+                offset,
+                data,
+                ..
+            } => (*block_number, *offset, data),
+            LogRecordBody::Compensation {
+                block_number,
+                offset,
+                data,
+                ..
+            } => (*block_number, *offset, data),
+            // The page is replaced whatever it holds, without reading it, since the extension
+            // of the data file which made it may have been lost in the crash. The records
+            // after this one are redone over it.
+            LogRecordBody::NewPage { block_number, data } => {
+                buffer_pool_manager.reset_page(*block_number, data, record.lsn)?;
+                continue;
+            }
+            _ => continue,
+        };
+        let res = buffer_pool_manager.fetch_page(block_number)?;
+        let applied = page_lsn(&res.data.read().unwrap()) >= record.lsn;
+        buffer_pool_manager.unpin_frame(res.frame_id, false);
+        if !applied {
+            apply(buffer_pool_manager, block_number, offset, data, record.lsn)?;
+        }
+    }
+
+    // Undo: roll back the losers together, the latest change first.
+    log_manager.resume(&losers);
+    let mut next_lsns: BinaryHeap<(Lsn, TxnId)> =
+        losers.iter().map(|(txn_id, lsn)| (*lsn, *txn_id)).collect();
+    while let Some((lsn, txn_id)) = next_lsns.pop() {
+        let record = log_manager.read_record(lsn)?;
+        match undo(buffer_pool_manager, record)? {
+            0 => log_manager.end_abort(txn_id)?,
+            lsn => next_lsns.push((lsn, txn_id)),
+        }
+    }
+
+    buffer_pool_manager.free_spill_pages()?;
+    buffer_pool_manager.checkpoint()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{open_catalog, Catalog, Column, ColumnType, Schema};
+    use crate::disk::DATAFILE_NAME;
+    use crate::storage::table::Table;
+    use crate::storage::tuple::Tuple;
+    use crate::transaction::Snapshot;
+    use crate::value::Value;
+    use anyhow::Result;
+    use std::fs::OpenOptions;
+    use std::path::Path;
+    use std::sync::Arc;
+    #[test]
+    fn recover_after_crash() -> Result<()> {
+        let catalog = open_catalog("tmp/recover_after_crash/")?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let disk_manager = buffer_pool_manager.disk_manager().clone();
+        let log_manager = buffer_pool_manager.log_manager().clone();
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let txn_id = log_manager.begin();
+        catalog.create_table(txn_id, "users", &schema)?;
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        for i in 0..1000 {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Int(i)],
+                },
+            )?;
+        }
+        log_manager.commit(txn_id)?;
+        // The changes of a running transaction reach the disk through evictions.
+        let txn_id = log_manager.begin();
+        for i in 1000..3000 {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Int(i)],
+                },
+            )?;
+        }
+        log_manager.flush_all()?;
+        // Records which were not flushed are lost along with their changes.
+        let other_txn_id = log_manager.begin();
+        table.insert_tuple(
+            other_txn_id,
+            Tuple {
+                values: vec![Value::Int(-1)],
+            },
+        )?;
+
+        // Crash without writing back the buffer pool.
+        drop(catalog);
+        drop(log_manager);
+        drop(buffer_pool_manager);
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager.clone()));
+        let catalog = Catalog::new(buffer_pool_manager.clone());
+        catalog.bootstrap()?;
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        let ids = table
+            .scan_tuples(&Snapshot::latest())
+            .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(ids, (0..1000).map(Value::Int).collect::<Vec<_>>());
+        assert_eq!(disk_manager.log_size()?, 16);
+
+        // The next transaction ids do not collide with the ones in the truncated log.
+        assert!(buffer_pool_manager.log_manager().begin() > txn_id);
+        Ok(())
+    }
+    #[test]
+    fn recover_lost_new_pages() -> Result<()> {
+        let dir = "tmp/recover_lost_new_pages/";
+        let catalog = open_catalog(dir)?;
+        let buffer_pool_manager = catalog.buffer_pool_manager().clone();
+        let disk_manager = buffer_pool_manager.disk_manager().clone();
+        let log_manager = buffer_pool_manager.log_manager().clone();
+        let schema = Schema {
+            columns: vec![Column {
+                name: "id".to_string(),
+                column_type: ColumnType::Int,
+            }],
+        };
+        let txn_id = log_manager.begin();
+        catalog.create_table(txn_id, "users", &schema)?;
+        log_manager.commit(txn_id)?;
+        buffer_pool_manager.checkpoint()?;
+        let data_path = Path::new(dir).join(DATAFILE_NAME);
+        let data_size = data_path.metadata()?.len();
+        let first_block_number = catalog.get_first_block_number("users")?.unwrap();
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        let txn_id = log_manager.begin();
+        for i in 0..1000 {
+            table.insert_tuple(
+                txn_id,
+                Tuple {
+                    values: vec![Value::Int(i)],
+                },
+            )?;
+        }
+        log_manager.commit(txn_id)?;
+
+        // Crash, losing the pages added to the data file since they were not synced.
+        drop(catalog);
+        drop(log_manager);
+        drop(buffer_pool_manager);
+        OpenOptions::new()
+            .write(true)
+            .open(&data_path)?
+            .set_len(data_size)?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager));
+        let catalog = Catalog::new(buffer_pool_manager.clone());
+        catalog.bootstrap()?;
+        let table = Table::new(&buffer_pool_manager, &schema, first_block_number);
+        let ids = table
+            .scan_tuples(&Snapshot::latest())
+            .map(|item| item.map(|(_, tuple)| tuple.values[0].clone()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(ids, (0..1000).map(Value::Int).collect::<Vec<_>>());
+        Ok(())
+    }
+}